@@ -0,0 +1,46 @@
+use clap::Parser;
+use llm_client::mock::{MockServer, Script};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// 本地的 OpenAI 兼容模拟服务器，用于离线开发
+#[derive(Parser)]
+#[command(
+    name = "mock-server",
+    about = "Serve scripted OpenAI-compatible replies for offline development"
+)]
+struct Args {
+    #[arg(
+        short,
+        long,
+        default_value = "127.0.0.1:8089",
+        help = "Address to listen on"
+    )]
+    listen: SocketAddr,
+    #[arg(
+        short,
+        long,
+        help = "JSON script with models and replies; without one every request is echoed"
+    )]
+    script: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .init();
+
+    let args = Args::parse();
+    let script = match &args.script {
+        Some(path) => Script::load(path)?,
+        None => Script::default(),
+    };
+    let server = MockServer::bind(args.listen, script).await?;
+    println!("Mock server listening on {}", server.api_base());
+
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use super::session::ChatSession;
+use crate::llm::{Message, MessageContent, Role};
+use anyhow::Result;
+use pulldown_cmark::{html, CowStr, Event, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// JSON 导出文件中 `format` 字段的值
+pub const JSON_FORMAT: &str = "llm-client-session";
+/// 当前的 JSON 导出格式版本
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [Self::Markdown, Self::Html, Self::Json];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Markdown => "Markdown",
+            Self::Html => "HTML",
+            Self::Json => "JSON",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// 带版本号的 JSON 导出文件
+#[derive(Serialize, Deserialize)]
+pub struct SessionExport {
+    pub format: String,
+    pub version: u32,
+    pub session: ChatSession,
+}
+
+pub fn export(session: &ChatSession, format: ExportFormat) -> Result<String> {
+    Ok(match format {
+        ExportFormat::Markdown => to_markdown(session),
+        ExportFormat::Html => to_html(session),
+        ExportFormat::Json => serde_json::to_string_pretty(&SessionExport {
+            format: JSON_FORMAT.to_string(),
+            version: JSON_VERSION,
+            session: session.clone(),
+        })?,
+    })
+}
+
+fn role_label(role: &Role) -> &'static str {
+    match role {
+        Role::User => "You",
+        Role::Assistant => "AI",
+        Role::System => "System",
+        Role::Tool => "Tool",
+    }
+}
+
+/// 消息正文的 Markdown 形式
+fn message_markdown(message: &Message) -> String {
+    match &message.content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::Image { text, url } => format!("{}\n\n![image]({})", text, url),
+        MessageContent::Function {
+            name, arguments, ..
+        } => format!("🔧 `{}({})`", name, arguments),
+        MessageContent::ToolResult { name, content, .. } => {
+            format!("Result of `{}`:\n\n```\n{}\n```", name, content)
+        }
+        MessageContent::Summary { text, original } => {
+            format!(
+                "*Summary of the {} messages above:*\n\n{}",
+                original.len(),
+                text
+            )
+        }
+    }
+}
+
+/// 导出的消息，总结消息之前先列出被总结的原始消息
+fn exported_messages<'a>(messages: impl IntoIterator<Item = &'a Message>) -> Vec<&'a Message> {
+    let mut exported = Vec::new();
+    for message in messages {
+        if let MessageContent::Summary { original, .. } = &message.content {
+            exported.extend(exported_messages(original));
+        }
+        exported.push(message);
+    }
+    exported
+}
+
+fn to_markdown(session: &ChatSession) -> String {
+    let mut output = format!("# {}\n\n", session.title);
+    for message in exported_messages(&session.messages) {
+        let _ = write!(
+            output,
+            "### {} · {}\n\n{}\n\n",
+            role_label(&message.role),
+            message.timestamp.format("%Y-%m-%d %H:%M"),
+            message_markdown(message)
+        );
+    }
+    output
+}
+
+/// 只保留 http、https 和 mailto 链接，图片另外允许内嵌的 data URL；
+/// 其他地址（例如 `javascript:`）替换为空，避免导出的页面中出现可执行的链接
+fn safe_url(url: CowStr<'_>, image: bool) -> CowStr<'_> {
+    // 浏览器会忽略地址中的空白和控制字符，比较前先去掉
+    let normalized: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let mut allowed = ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| normalized.starts_with(scheme));
+    allowed |= image && normalized.starts_with("data:image/");
+    if allowed {
+        url
+    } else {
+        CowStr::Borrowed("")
+    }
+}
+
+/// 独立的 HTML 页面，消息中的 Markdown 转为 HTML，原始 HTML 会被转义，
+/// 不安全的链接地址会被去掉
+fn to_html(session: &ChatSession) -> String {
+    let mut body = String::new();
+    for message in exported_messages(&session.messages) {
+        let markdown = message_markdown(message);
+        let events = Parser::new(&markdown).map(|event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: safe_url(dest_url, false),
+                title,
+                id,
+            }),
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: safe_url(dest_url, true),
+                title,
+                id,
+            }),
+            event => event,
+        });
+        let mut content = String::new();
+        html::push_html(&mut content, events);
+
+        let _ = write!(
+            body,
+            "<section class=\"message {}\">\n<header>{} · {}</header>\n{}</section>\n",
+            role_label(&message.role).to_lowercase(),
+            role_label(&message.role),
+            message.timestamp.format("%Y-%m-%d %H:%M"),
+            content
+        );
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }}
+.message {{ border-radius: 8px; padding: 0.5em 1em; margin: 1em 0; background: #f4f4f4; }}
+.message.you {{ background: #e3eefc; }}
+header {{ font-weight: bold; font-size: 0.9em; color: #555; }}
+pre {{ background: #272822; color: #f8f8f2; padding: 0.8em; overflow-x: auto; border-radius: 4px; }}
+img {{ max-width: 100%; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}</body>
+</html>
+"#,
+        title = escape_html(&session.title),
+        body = body
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html_of(text: &str) -> String {
+        let mut session = ChatSession::new("Links".to_string());
+        session.add_message(Message::new(
+            Role::Assistant,
+            MessageContent::Text(text.to_string()),
+        ));
+        export(&session, ExportFormat::Html).unwrap()
+    }
+
+    #[test]
+    fn drops_script_links() {
+        let html = html_of(
+            "[a](javascript:alert(1)) [b]( JavaScript:alert(2)) <vbscript:x> \
+             ![c](javascript:alert(3))",
+        );
+        // 链接文字保留，地址被清空
+        assert_eq!(html.matches(r#"href="""#).count(), 3, "{}", html);
+        assert_eq!(html.matches(r#"src="""#).count(), 1, "{}", html);
+        assert!(!html.to_lowercase().contains(r#"="javascript"#));
+    }
+
+    #[test]
+    fn keeps_web_and_mail_links() {
+        let html = html_of("[a](https://example.com) [b](mailto:me@example.com)");
+        assert!(html.contains(r#"href="https://example.com""#));
+        assert!(html.contains(r#"href="mailto:me@example.com""#));
+    }
+
+    #[test]
+    fn keeps_embedded_images_but_not_data_links() {
+        let html = html_of("![x](data:image/png;base64,AAAA) [y](data:text/html;base64,AAAA)");
+        assert!(html.contains(r#"src="data:image/png;base64,AAAA""#));
+        assert!(!html.contains("data:text/html"));
+    }
+}
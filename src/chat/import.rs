@@ -0,0 +1,286 @@
+use super::export::{SessionExport, JSON_FORMAT, JSON_VERSION};
+use super::session::{Branch, ChatSession, Variants};
+use crate::llm::{Message, MessageContent, Role};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// 解析导入的文件，支持本应用导出的 JSON 和 ChatGPT 导出的 `conversations.json`
+pub fn parse(contents: &str) -> Result<Vec<ChatSession>> {
+    let value: Value = serde_json::from_str(contents)?;
+
+    if value.get("format").and_then(Value::as_str) == Some(JSON_FORMAT) {
+        let export: SessionExport = serde_json::from_value(value)?;
+        if export.version > JSON_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported export version {} (this build reads up to {})",
+                export.version,
+                JSON_VERSION
+            ));
+        }
+        return Ok(vec![export.session]);
+    }
+
+    // conversations.json 是会话数组，也接受单个会话
+    let conversations: Vec<Conversation> = if value.is_array() {
+        serde_json::from_value(value)?
+    } else if value.get("mapping").is_some() {
+        vec![serde_json::from_value(value)?]
+    } else {
+        return Err(anyhow::anyhow!("Unrecognized file format"));
+    };
+
+    Ok(conversations.iter().map(Conversation::to_session).collect())
+}
+
+/// ChatGPT 导出中的一个会话，消息以树的形式保存
+#[derive(Deserialize)]
+struct Conversation {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    update_time: Option<f64>,
+    mapping: HashMap<String, Node>,
+    #[serde(default)]
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: Author,
+    #[serde(default)]
+    content: Value,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    metadata: Value,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    role: String,
+}
+
+impl Conversation {
+    fn to_session(&self) -> ChatSession {
+        let mut session = ChatSession::new(
+            self.title
+                .clone()
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| "Imported chat".to_string()),
+        );
+        session.title_locked = true;
+        if let Some(time) = self.create_time.and_then(timestamp) {
+            session.created_at = time;
+        }
+        session.updated_at = self
+            .update_time
+            .and_then(timestamp)
+            .unwrap_or(session.created_at);
+
+        // 从 current_node 向上走到根节点，这条路径就是界面上显示的分支，
+        // 走到的最上层节点就是根节点
+        let mut active_path = HashSet::new();
+        let mut top = None;
+        let mut node = self.current_node.as_deref();
+        while let Some(id) = node.filter(|id| active_path.insert(*id)) {
+            let Some(entry) = self.mapping.get(id) else {
+                break;
+            };
+            top = Some(id);
+            node = entry.parent.as_deref();
+        }
+
+        // 没有 current_node 时取没有父节点的节点中 id 最小的一个，保证结果稳定
+        let root = top.or_else(|| {
+            self.mapping
+                .iter()
+                .filter(|(_, node)| {
+                    node.parent
+                        .as_ref()
+                        .is_none_or(|parent| !self.mapping.contains_key(parent))
+                })
+                .map(|(id, _)| id.as_str())
+                .min()
+        });
+
+        if let Some(root) = root {
+            let branch = self.branch(root, 0, &active_path, &mut HashSet::new());
+            session.messages = branch.messages.into();
+            session.variants = branch.variants;
+        }
+        session
+    }
+
+    /// 从 `id` 开始沿当前分支收集消息，其他子节点作为候选分支保存
+    fn branch<'a>(
+        &'a self,
+        id: &'a str,
+        mut position: usize,
+        active_path: &HashSet<&str>,
+        visited: &mut HashSet<&'a str>,
+    ) -> Branch {
+        let mut branch = Branch::default();
+        let mut current = id;
+
+        while visited.insert(current) {
+            let Some(node) = self.mapping.get(current) else {
+                break;
+            };
+            if let Some(message) = node.message.as_ref().and_then(to_message) {
+                branch.messages.push(message);
+                position += 1;
+            }
+
+            let Some(active) = node
+                .children
+                .iter()
+                .position(|child| active_path.contains(child.as_str()))
+                .or_else(|| node.children.len().checked_sub(1))
+            else {
+                break;
+            };
+
+            if node.children.len() > 1 {
+                let branches = node
+                    .children
+                    .iter()
+                    .enumerate()
+                    .map(|(index, child)| {
+                        if index == active {
+                            Branch::default()
+                        } else {
+                            self.branch(child, position, active_path, visited)
+                        }
+                    })
+                    .collect();
+                branch
+                    .variants
+                    .insert(position, Variants { active, branches });
+            }
+            current = &node.children[active];
+        }
+
+        branch
+    }
+}
+
+/// 转换一条 ChatGPT 消息，跳过隐藏的系统消息和没有文字的消息
+fn to_message(message: &ChatGptMessage) -> Option<Message> {
+    let hidden = message
+        .metadata
+        .get("is_visually_hidden_from_conversation")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let role = match message.author.role.as_str() {
+        "user" => Role::User,
+        "assistant" => Role::Assistant,
+        "system" => Role::System,
+        // 浏览、代码执行等内部工具的输出不导入
+        _ => return None,
+    };
+    if hidden {
+        return None;
+    }
+
+    let text = match message.content.get("parts").and_then(Value::as_array) {
+        Some(parts) => parts
+            .iter()
+            .map(|part| match part {
+                Value::String(text) => text.clone(),
+                _ => "[attachment]".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => message
+            .content
+            .get("text")
+            .and_then(Value::as_str)?
+            .to_string(),
+    };
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    let mut result = Message::new(role, MessageContent::Text(text));
+    if let Some(time) = message.create_time.and_then(timestamp) {
+        result.timestamp = time;
+    }
+    Some(result)
+}
+
+fn timestamp(seconds: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(parent: Option<&str>, children: &[&str], role: &str, text: &str) -> Value {
+        json!({
+            "parent": parent,
+            "children": children,
+            "message": { "author": { "role": role }, "content": { "parts": [text] } },
+        })
+    }
+
+    fn texts(session: &ChatSession) -> Vec<String> {
+        session
+            .messages
+            .iter()
+            .map(|message| match &message.content {
+                MessageContent::Text(text) => text.clone(),
+                other => panic!("expected text, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn follows_current_node_when_there_are_several_roots() {
+        // "orphan" 的父节点不在导出中，和真正的根节点一样没有父节点
+        let conversation = json!({
+            "title": "Two roots",
+            "mapping": {
+                "a": node(None, &["a1"], "user", "first root"),
+                "a1": node(Some("a"), &[], "assistant", "first reply"),
+                "orphan": node(Some("missing"), &["b1"], "user", "second root"),
+                "b1": node(Some("orphan"), &[], "assistant", "second reply"),
+            },
+            "current_node": "b1",
+        });
+
+        for _ in 0..8 {
+            let sessions = parse(&conversation.to_string()).unwrap();
+            assert_eq!(texts(&sessions[0]), ["second root", "second reply"]);
+        }
+    }
+
+    #[test]
+    fn picks_a_stable_root_without_current_node() {
+        let conversation = json!({
+            "mapping": {
+                "b": node(None, &[], "user", "root b"),
+                "a": node(None, &[], "user", "root a"),
+            },
+        });
+
+        let sessions = parse(&conversation.to_string()).unwrap();
+        assert_eq!(texts(&sessions[0]), ["root a"]);
+    }
+}
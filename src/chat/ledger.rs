@@ -0,0 +1,282 @@
+use crate::config::pricing::{self, ModelPrice};
+use crate::llm::Usage;
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// 账本中的一条记录，对应一次完整的回复
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub timestamp: DateTime<Utc>,
+    pub session_id: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl LedgerEntry {
+    pub fn new(session_id: &str, usage: &Usage) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            session_id: session_id.to_string(),
+            model: usage.model.clone(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        }
+    }
+
+    pub fn cost(&self, prices: &[ModelPrice]) -> Option<f64> {
+        pricing::cost(
+            prices,
+            &self.model,
+            self.prompt_tokens,
+            self.completion_tokens,
+        )
+    }
+}
+
+/// 统计用量时的分组方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Grouping {
+    Session,
+    Model,
+    #[default]
+    Day,
+}
+
+impl Grouping {
+    pub const ALL: [Grouping; 3] = [Self::Session, Self::Model, Self::Day];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Session => "Session",
+            Self::Model => "Model",
+            Self::Day => "Day",
+        }
+    }
+}
+
+/// 一组记录的合计
+#[derive(Debug, Clone, Default)]
+pub struct UsageTotal {
+    /// 会话 id、模型名称或本地日期
+    pub key: String,
+    pub requests: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+    /// 有记录的模型不在价格表中，费用不完整
+    pub unpriced: bool,
+}
+
+impl UsageTotal {
+    fn add(&mut self, entry: &LedgerEntry, prices: &[ModelPrice]) {
+        self.requests += 1;
+        self.prompt_tokens += entry.prompt_tokens as u64;
+        self.completion_tokens += entry.completion_tokens as u64;
+        match entry.cost(prices) {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced = true,
+        }
+    }
+}
+
+/// token 用量账本，每条记录追加为 JSON Lines 文件中的一行
+///
+/// 费用在统计时按当前的价格表计算，修改价格后历史费用也会更新。
+#[derive(Default)]
+pub struct UsageLedger {
+    path: Option<PathBuf>,
+    entries: Vec<LedgerEntry>,
+}
+
+impl UsageLedger {
+    /// 当前用户的默认账本文件，例如 `~/.local/share/llm-client/usage.jsonl`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("llm-client").join("usage.jsonl"))
+    }
+
+    /// 读取账本文件，无法解析的行会被跳过
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut entries = Vec::new();
+        if path.exists() {
+            for line in fs::read_to_string(&path)?.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => warn!(?e, path = %path.display(), "Skipping unreadable ledger entry"),
+                }
+            }
+        }
+        Ok(Self {
+            path: Some(path),
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn record(&mut self, entry: LedgerEntry) -> Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// 按分组合计；按天分组时最近的在前，其他按费用从高到低
+    pub fn totals(&self, grouping: Grouping, prices: &[ModelPrice]) -> Vec<UsageTotal> {
+        let mut totals: HashMap<String, UsageTotal> = HashMap::new();
+        for entry in &self.entries {
+            let key = match grouping {
+                Grouping::Session => entry.session_id.clone(),
+                Grouping::Model => entry.model.clone(),
+                Grouping::Day => entry
+                    .timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            };
+            totals
+                .entry(key.clone())
+                .or_insert_with(|| UsageTotal {
+                    key,
+                    ..Default::default()
+                })
+                .add(entry, prices);
+        }
+
+        let mut totals: Vec<UsageTotal> = totals.into_values().collect();
+        match grouping {
+            Grouping::Day => totals.sort_by(|a, b| b.key.cmp(&a.key)),
+            _ => totals.sort_by(|a, b| b.cost.total_cmp(&a.cost).then(a.key.cmp(&b.key))),
+        }
+        totals
+    }
+
+    /// 本地时间当月的合计
+    pub fn month_total(&self, prices: &[ModelPrice]) -> UsageTotal {
+        let now = Local::now();
+        let mut total = UsageTotal::default();
+        for entry in &self.entries {
+            let time = entry.timestamp.with_timezone(&Local);
+            if time.year() == now.year() && time.month() == now.month() {
+                total.add(entry, prices);
+            }
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices() -> Vec<ModelPrice> {
+        vec![ModelPrice::new("large", 10.0, 20.0)]
+    }
+
+    fn entry(session_id: &str, model: &str, days_ago: i64, tokens: u32) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: Utc::now() - chrono::Duration::days(days_ago),
+            session_id: session_id.to_string(),
+            model: model.to_string(),
+            prompt_tokens: tokens,
+            completion_tokens: tokens,
+        }
+    }
+
+    fn ledger(entries: Vec<LedgerEntry>) -> UsageLedger {
+        let mut ledger = UsageLedger::default();
+        for entry in entries {
+            ledger.record(entry).unwrap();
+        }
+        ledger
+    }
+
+    #[test]
+    fn totals_by_model_and_session() {
+        let ledger = ledger(vec![
+            entry("a", "large", 0, 100_000),
+            entry("a", "small", 0, 100_000),
+            entry("b", "large", 0, 50_000),
+        ]);
+
+        let models = ledger.totals(Grouping::Model, &prices());
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].key, "large");
+        assert_eq!(models[0].requests, 2);
+        assert_eq!(models[0].prompt_tokens, 150_000);
+        assert!((models[0].cost - 4.5).abs() < 1e-9);
+        assert!(!models[0].unpriced);
+        // 不在价格表中的模型没有费用，排在后面
+        assert_eq!(models[1].key, "small");
+        assert!(models[1].unpriced);
+
+        let sessions = ledger.totals(Grouping::Session, &prices());
+        let keys: Vec<&str> = sessions.iter().map(|total| total.key.as_str()).collect();
+        assert_eq!(keys, ["a", "b"]);
+        assert!(sessions[0].unpriced && !sessions[1].unpriced);
+        assert!((sessions[0].cost - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lists_recent_days_first() {
+        let ledger = ledger(vec![
+            entry("a", "large", 2, 1),
+            entry("a", "large", 0, 1),
+            entry("a", "large", 2, 1),
+        ]);
+        let days = ledger.totals(Grouping::Day, &prices());
+        assert_eq!(days.len(), 2);
+        assert!(days[0].key > days[1].key);
+        assert_eq!(days[1].requests, 2);
+    }
+
+    #[test]
+    fn month_total_only_counts_this_month() {
+        // 40 天前一定在上个月或更早
+        let ledger = ledger(vec![
+            entry("a", "large", 0, 100_000),
+            entry("a", "large", 40, 100_000),
+        ]);
+        let total = ledger.month_total(&prices());
+        assert_eq!(total.requests, 1);
+        assert_eq!(total.completion_tokens, 100_000);
+        assert!((total.cost - 3.0).abs() < 1e-9);
+
+        assert_eq!(UsageLedger::default().month_total(&prices()).requests, 0);
+    }
+
+    #[test]
+    fn reopens_recorded_entries() {
+        let path = std::env::temp_dir()
+            .join(format!("llm-client-test-{}", uuid::Uuid::new_v4()))
+            .join("usage.jsonl");
+        let mut ledger = UsageLedger::open(&path).unwrap();
+        ledger.record(entry("a", "large", 0, 10)).unwrap();
+        ledger.record(entry("b", "large", 0, 20)).unwrap();
+
+        let reopened = UsageLedger::open(&path).unwrap();
+        assert_eq!(reopened.month_total(&prices()).prompt_tokens, 30);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,5 +1,13 @@
+pub mod export;
+pub mod import;
+pub mod ledger;
+pub mod search;
 pub mod session;
 pub mod session_manager;
+pub mod storage;
+pub mod summary;
+pub mod title;
 
 pub use session::ChatSession;
 pub use session_manager::SessionManager;
+pub use storage::SessionStore;
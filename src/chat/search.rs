@@ -0,0 +1,263 @@
+use super::session::ChatSession;
+use crate::llm::{Message, MessageContent};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+
+/// 片段中匹配位置前后保留的字符数
+const SNIPPET_CONTEXT: usize = 40;
+/// 最多返回的结果数
+const MAX_RESULTS: usize = 50;
+
+/// 消息在会话中的位置：(会话 id, 消息位置)
+type Location = (String, usize);
+
+/// 所有会话消息的倒排索引
+#[derive(Default)]
+pub struct SearchIndex {
+    // 词 -> 包含这个词的消息
+    postings: BTreeMap<String, BTreeSet<Location>>,
+    // 会话 -> 会话中出现过的词，重新索引会话时用来清理旧的条目
+    session_terms: HashMap<String, BTreeSet<String>>,
+}
+
+/// 一条搜索结果
+pub struct SearchHit {
+    pub session_id: String,
+    pub session_title: String,
+    pub message_index: usize,
+    pub snippet: String,
+    /// 片段中需要高亮的字节范围
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl SearchIndex {
+    /// 重新索引会话当前分支上的所有消息
+    pub fn index_session(&mut self, session: &ChatSession) {
+        self.remove_session(&session.id);
+
+        let mut terms = BTreeSet::new();
+        for (index, message) in session.messages.iter().enumerate() {
+            for term in tokenize(&message_text(message)) {
+                self.postings
+                    .entry(term.clone())
+                    .or_default()
+                    .insert((session.id.clone(), index));
+                terms.insert(term);
+            }
+        }
+        self.session_terms.insert(session.id.clone(), terms);
+    }
+
+    pub fn remove_session(&mut self, id: &str) {
+        for term in self.session_terms.remove(id).into_iter().flatten() {
+            if let Some(locations) = self.postings.get_mut(&term) {
+                locations.retain(|(session_id, _)| session_id != id);
+                if locations.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// 查找包含所有查询词的消息，查询词按前缀匹配
+    pub fn search(&self, query: &str, sessions: &HashMap<String, ChatSession>) -> Vec<SearchHit> {
+        let terms = tokenize(query);
+        let mut matches: Option<BTreeSet<Location>> = None;
+        for term in &terms {
+            let locations: BTreeSet<Location> = self
+                .postings
+                .range(term.clone()..)
+                .take_while(|(key, _)| key.starts_with(term.as_str()))
+                .flat_map(|(_, locations)| locations.iter().cloned())
+                .collect();
+            matches = Some(match matches {
+                Some(previous) => previous.intersection(&locations).cloned().collect(),
+                None => locations,
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = matches
+            .into_iter()
+            .flatten()
+            .filter_map(|(session_id, message_index)| {
+                let session = sessions.get(&session_id)?;
+                let text = message_text(session.messages.get(message_index)?);
+                let (snippet, highlights) = snippet(&text, &terms);
+                Some(SearchHit {
+                    session_title: session.title.clone(),
+                    session_id,
+                    message_index,
+                    snippet,
+                    highlights,
+                })
+            })
+            .collect();
+
+        // 最近更新的会话排在前面
+        hits.sort_by_key(|hit| {
+            std::cmp::Reverse(sessions.get(&hit.session_id).map(|s| s.updated_at))
+        });
+        hits.truncate(MAX_RESULTS);
+        hits
+    }
+}
+
+/// 可搜索的文字；总结消息包含被总结的原始消息，搜索结果指向总结
+fn message_text(message: &Message) -> Cow<'_, str> {
+    match &message.content {
+        MessageContent::Text(text) | MessageContent::Image { text, .. } => Cow::Borrowed(text),
+        MessageContent::ToolResult { content, .. } => Cow::Borrowed(content),
+        MessageContent::Function { name, .. } => Cow::Borrowed(name),
+        MessageContent::Summary { text, original } => {
+            let mut all = text.clone();
+            for message in original {
+                all.push('\n');
+                all.push_str(&message_text(message));
+            }
+            Cow::Owned(all)
+        }
+    }
+}
+
+/// 拆分为小写的单词；中日韩文字没有空格分隔，每个字单独作为一个词
+fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut word = String::new();
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                terms.push(std::mem::take(&mut word));
+            }
+            terms.push(c.to_string());
+        } else if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        } else if !word.is_empty() {
+            terms.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        terms.push(word);
+    }
+    terms
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}')
+}
+
+/// 截取第一个匹配附近的文字，并找出其中所有匹配的位置
+fn snippet(text: &str, terms: &[String]) -> (String, Vec<Range<usize>>) {
+    // 逐字符比较小写形式，记录每个字符在原文中的字节位置
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|(_, c)| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    let mut matches: Vec<Range<usize>> = Vec::new();
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        if term.is_empty() || term.len() > lower.len() {
+            continue;
+        }
+        for start in 0..=lower.len() - term.len() {
+            if lower[start..start + term.len()] == term[..] {
+                matches.push(start..start + term.len());
+            }
+        }
+    }
+    matches.sort_by_key(|range| range.start);
+
+    let first = matches.first().map_or(0, |range| range.start);
+    let start = first.saturating_sub(SNIPPET_CONTEXT);
+    let end = (first + SNIPPET_CONTEXT * 2).min(chars.len());
+    let byte_at = |index: usize| chars.get(index).map_or(text.len(), |(byte, _)| *byte);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let offset = snippet.len();
+    snippet.push_str(&text[byte_at(start)..byte_at(end)]);
+    if end < chars.len() {
+        snippet.push('…');
+    }
+
+    // 片段中的换行替换为空格，保持结果列表紧凑
+    let snippet = snippet.replace(['\n', '\r'], " ");
+    let highlights = matches
+        .into_iter()
+        .filter(|range| range.start >= start && range.end <= end)
+        .map(|range| {
+            offset + byte_at(range.start) - byte_at(start)
+                ..offset + byte_at(range.end) - byte_at(start)
+        })
+        .collect();
+
+    (snippet, highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 片段中高亮的文字
+    fn highlighted(text: &str, terms: &[&str]) -> (String, Vec<String>) {
+        let terms: Vec<String> = terms.iter().map(|term| term.to_string()).collect();
+        let (snippet, highlights) = snippet(text, &terms);
+        let words = highlights
+            .into_iter()
+            .map(|range| snippet[range].to_string())
+            .collect();
+        (snippet, words)
+    }
+
+    #[test]
+    fn tokenizes_words_and_cjk_characters() {
+        assert_eq!(
+            tokenize("Hello, 世界! Rust2024 héllo_WORLD"),
+            ["hello", "世", "界", "rust2024", "héllo", "world"]
+        );
+        assert!(tokenize(" ,.! ").is_empty());
+    }
+
+    #[test]
+    fn highlights_every_match_ignoring_case() {
+        let (snippet, words) = highlighted("Rust is rust, RUST!", &["rust"]);
+        assert_eq!(snippet, "Rust is rust, RUST!");
+        assert_eq!(words, ["Rust", "rust", "RUST"]);
+    }
+
+    #[test]
+    fn highlights_multibyte_text() {
+        let (_, words) = highlighted("Ünïcode wörld über alles", &["wörld", "über"]);
+        assert_eq!(words, ["wörld", "über"]);
+
+        let (_, words) = highlighted("我喜欢编程和编辑", &["编"]);
+        assert_eq!(words, ["编", "编"]);
+    }
+
+    #[test]
+    fn trims_long_text_around_the_first_match() {
+        let text = format!("{}needle\n{}", "é".repeat(100), "ü".repeat(100));
+        let (snippet, words) = highlighted(&text, &["needle"]);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("needle ü"));
+        assert_eq!(snippet.chars().count(), SNIPPET_CONTEXT * 3 + 2);
+        assert_eq!(words, ["needle"]);
+    }
+
+    #[test]
+    fn handles_missing_and_oversized_terms() {
+        let (snippet, words) = highlighted("short", &["", "much longer than the text"]);
+        assert_eq!(snippet, "short");
+        assert!(words.is_empty());
+    }
+}
@@ -1,19 +1,78 @@
-use crate::llm::message::Message;
+use crate::config::Preset;
+use crate::llm::message::{Message, MessageContent, Role};
+use crate::llm::ConfigOverrides;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::sync::mpsc;
+use std::collections::{BTreeMap, VecDeque};
 use uuid::Uuid;
 
+/// 会话中从某个位置开始的一段后续消息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Branch {
+    pub messages: Vec<Message>,
+    /// 这段消息内部的分支点，键为在会话中的绝对位置
+    #[serde(default)]
+    pub variants: BTreeMap<usize, Variants>,
+}
+
+/// 同一位置上的多个候选分支，例如重新生成的回复或编辑后重发的消息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Variants {
+    pub active: usize,
+    /// 所有候选分支；当前分支的内容在 `ChatSession::messages` 中，这里对应的位置为空
+    pub branches: Vec<Branch>,
+}
+
+/// 分叉会话的来源
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForkOrigin {
+    pub session_id: String,
+    /// 复制到的最后一条消息在原会话中的位置
+    pub message_index: usize,
+}
+
+/// 会话级别的设置，通常来自创建会话时选择的预设
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SessionSettings {
+    #[serde(default)]
+    pub preset: Option<String>,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub overrides: ConfigOverrides,
+}
+
+impl SessionSettings {
+    pub fn from_preset(preset: &Preset) -> Self {
+        Self {
+            preset: Some(preset.name.clone()),
+            system_prompt: preset.system_prompt.clone(),
+            overrides: ConfigOverrides {
+                model: preset.model.clone(),
+                temperature: preset.temperature,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatSession {
     pub id: String,
     pub title: String,
+    /// 当前分支上的消息
     pub messages: VecDeque<Message>,
+    /// 当前分支上的分支点，键为消息位置
+    #[serde(default)]
+    pub variants: BTreeMap<usize, Variants>,
+    #[serde(default)]
+    pub forked_from: Option<ForkOrigin>,
+    /// 标题已经自动生成或被用户修改过，之后不再自动生成
+    #[serde(default)]
+    pub title_locked: bool,
+    #[serde(default)]
+    pub settings: SessionSettings,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    #[serde(skip)]
-    pub stream_tx: Option<tokio::sync::mpsc::Sender<String>>,
 }
 
 impl ChatSession {
@@ -23,9 +82,12 @@ impl ChatSession {
             id: Uuid::new_v4().to_string(),
             title,
             messages: VecDeque::new(),
+            variants: BTreeMap::new(),
+            forked_from: None,
+            title_locked: false,
+            settings: SessionSettings::default(),
             created_at: now,
             updated_at: now,
-            stream_tx: None,
         }
     }
 
@@ -33,4 +95,236 @@ impl ChatSession {
         self.messages.push_back(message);
         self.updated_at = Utc::now();
     }
+
+    pub fn rename(&mut self, title: String) {
+        self.title = title;
+        self.title_locked = true;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn set_settings(&mut self, settings: SessionSettings) {
+        self.settings = settings;
+        self.updated_at = Utc::now();
+    }
+
+    /// 发送给模型的消息：系统提示词加上当前分支上的消息
+    pub fn request_messages(&self) -> VecDeque<Message> {
+        let mut messages = self.messages.clone();
+        if !self.settings.system_prompt.trim().is_empty() {
+            messages.push_front(Message::new(
+                Role::System,
+                MessageContent::Text(self.settings.system_prompt.clone()),
+            ));
+        }
+        messages
+    }
+
+    /// 开头最多可以被总结的消息数
+    ///
+    /// 总结不能越过分支点，否则那里的候选分支会随被代替的消息一起丢失。
+    pub fn summarizable(&self) -> usize {
+        self.variants
+            .keys()
+            .next()
+            .map_or(self.messages.len(), |&index| index.min(self.messages.len()))
+    }
+
+    /// 用一条总结消息代替开头的 `count` 条消息，原始消息保存在总结中
+    ///
+    /// 已有的总结会合并进新的总结；`count` 越过分支点时不做任何修改。
+    pub fn summarize_prefix(&mut self, count: usize, text: String) {
+        let count = count.min(self.messages.len());
+        if count > self.summarizable() {
+            return;
+        }
+        let mut original = Vec::new();
+        for message in self.messages.drain(..count) {
+            match message.content {
+                MessageContent::Summary {
+                    original: earlier, ..
+                } => original.extend(earlier),
+                _ => original.push(message),
+            }
+        }
+
+        self.variants = shift_variants(std::mem::take(&mut self.variants), count);
+        self.messages.push_front(Message::new(
+            Role::System,
+            MessageContent::Summary { text, original },
+        ));
+        self.updated_at = Utc::now();
+    }
+
+    /// 在 `index` 处开始一个新的分支
+    ///
+    /// 从该位置开始的消息会作为一个候选分支保存起来，之后添加的消息属于新分支。
+    pub fn branch_from(&mut self, index: usize) {
+        let index = index.min(self.messages.len());
+        let tail = self.take_tail(index);
+
+        let variants = self.variants.entry(index).or_insert_with(|| Variants {
+            active: 0,
+            branches: vec![Branch::default()],
+        });
+        variants.branches[variants.active] = tail;
+        variants.branches.push(Branch::default());
+        variants.active = variants.branches.len() - 1;
+
+        self.updated_at = Utc::now();
+    }
+
+    /// 切换 `index` 处的候选分支
+    pub fn switch_variant(&mut self, index: usize, target: usize) {
+        match self.variants.get(&index) {
+            Some(variants) if target < variants.branches.len() && target != variants.active => {}
+            _ => return,
+        }
+
+        let tail = self.take_tail(index);
+        let variants = self
+            .variants
+            .get_mut(&index)
+            .expect("variants checked above");
+        variants.branches[variants.active] = tail;
+        let branch = std::mem::take(&mut variants.branches[target]);
+        variants.active = target;
+
+        self.messages.extend(branch.messages);
+        self.variants.extend(branch.variants);
+        self.updated_at = Utc::now();
+    }
+
+    /// `index` 处的分支信息：(当前分支, 分支数量)
+    pub fn variant_info(&self, index: usize) -> Option<(usize, usize)> {
+        self.variants
+            .get(&index)
+            .map(|variants| (variants.active, variants.branches.len()))
+    }
+
+    /// 取出从 `index` 开始的消息及其中的分支点
+    fn take_tail(&mut self, index: usize) -> Branch {
+        Branch {
+            messages: self.messages.split_off(index).into(),
+            variants: self.variants.split_off(&(index + 1)),
+        }
+    }
+}
+
+/// 开头的 `count` 条消息被一条总结代替后，调整分支点的位置；
+/// 分支点都不在被代替的消息中
+fn shift_variants(variants: BTreeMap<usize, Variants>, count: usize) -> BTreeMap<usize, Variants> {
+    variants
+        .into_iter()
+        .map(|(index, mut variants)| {
+            for branch in &mut variants.branches {
+                branch.variants = shift_variants(std::mem::take(&mut branch.variants), count);
+            }
+            (index - count + 1, variants)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(texts: &[&str]) -> ChatSession {
+        let mut session = ChatSession::new("Branches".to_string());
+        for text in texts {
+            session.add_message(Message::new(
+                Role::User,
+                MessageContent::Text(text.to_string()),
+            ));
+        }
+        session
+    }
+
+    fn texts(session: &ChatSession) -> Vec<String> {
+        session
+            .messages
+            .iter()
+            .map(|message| match &message.content {
+                MessageContent::Text(text) => text.clone(),
+                other => panic!("unexpected content {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn branches_and_switches_back() {
+        let mut session = session(&["a", "b", "c"]);
+        session.branch_from(1);
+        assert_eq!(texts(&session), ["a"]);
+        assert_eq!(session.variant_info(1), Some((1, 2)));
+
+        session.add_message(Message::new(
+            Role::User,
+            MessageContent::Text("B".to_string()),
+        ));
+        session.switch_variant(1, 0);
+        assert_eq!(texts(&session), ["a", "b", "c"]);
+        assert_eq!(session.variant_info(1), Some((0, 2)));
+
+        session.switch_variant(1, 1);
+        assert_eq!(texts(&session), ["a", "B"]);
+    }
+
+    #[test]
+    fn keeps_nested_branch_points_with_their_branch() {
+        let mut session = session(&["a", "b", "c", "d"]);
+        session.branch_from(2);
+        session.add_message(Message::new(
+            Role::User,
+            MessageContent::Text("C".to_string()),
+        ));
+        session.branch_from(1);
+        assert_eq!(texts(&session), ["a"]);
+        // 位置 2 的分支点随原来的分支一起保存
+        assert_eq!(session.variant_info(2), None);
+
+        session.switch_variant(1, 0);
+        assert_eq!(texts(&session), ["a", "b", "C"]);
+        assert_eq!(session.variant_info(2), Some((1, 2)));
+        session.switch_variant(2, 0);
+        assert_eq!(texts(&session), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn take_tail_splits_messages_and_branch_points() {
+        let mut session = session(&["a", "b", "c"]);
+        session.branch_from(1);
+        session.add_message(Message::new(
+            Role::User,
+            MessageContent::Text("B".to_string()),
+        ));
+        session.branch_from(2);
+
+        // 位置 1 的分支点属于前半段，位置 2 的属于取出的部分
+        let tail = session.take_tail(1);
+        assert_eq!(tail.messages.len(), 1);
+        assert_eq!(tail.variants.keys().copied().collect::<Vec<_>>(), [2usize]);
+        assert_eq!(
+            session.variants.keys().copied().collect::<Vec<_>>(),
+            [1usize]
+        );
+        assert_eq!(texts(&session), ["a"]);
+    }
+
+    #[test]
+    fn ignores_out_of_range_indices() {
+        let mut session = session(&["a", "b"]);
+        session.switch_variant(1, 0);
+        assert_eq!(texts(&session), ["a", "b"]);
+
+        // 超出末尾的位置视为在末尾分支
+        session.branch_from(10);
+        assert_eq!(texts(&session), ["a", "b"]);
+        assert_eq!(session.variant_info(2), Some((1, 2)));
+
+        session.switch_variant(2, 5);
+        session.switch_variant(2, 1);
+        assert_eq!(session.variant_info(2), Some((1, 2)));
+        session.switch_variant(3, 0);
+        assert_eq!(texts(&session), ["a", "b"]);
+    }
 }
@@ -1,12 +1,23 @@
-use super::session::ChatSession;
-use crate::llm::message::Message;
+use super::export::{self, ExportFormat};
+use super::import;
+use super::search::{SearchHit, SearchIndex};
+use super::session::{ChatSession, ForkOrigin};
+use super::storage::SessionStore;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use tracing::error;
 use uuid::Uuid;
 
 pub struct SessionManager {
     sessions: HashMap<String, ChatSession>,
     current_session_id: Option<String>,
+    store: Option<SessionStore>,
+    // 每个会话最后一次写入磁盘时的 updated_at
+    saved_at: HashMap<String, DateTime<Utc>>,
+    search_index: SearchIndex,
+    // 每个会话最后一次建立索引时的 updated_at
+    indexed_at: HashMap<String, DateTime<Utc>>,
 }
 
 impl SessionManager {
@@ -14,9 +25,34 @@ impl SessionManager {
         Self {
             sessions: HashMap::new(),
             current_session_id: None,
+            store: None,
+            saved_at: HashMap::new(),
+            search_index: SearchIndex::default(),
+            indexed_at: HashMap::new(),
         }
     }
 
+    /// 从磁盘加载已有会话，之后的修改会通过 `persist_changes` 写回
+    pub fn with_store(store: SessionStore) -> Result<Self> {
+        let mut manager = Self::new();
+
+        for session in store.load_all()? {
+            manager
+                .saved_at
+                .insert(session.id.clone(), session.updated_at);
+            manager.sessions.insert(session.id.clone(), session);
+        }
+
+        manager.current_session_id = manager
+            .sessions
+            .values()
+            .max_by_key(|session| session.updated_at)
+            .map(|session| session.id.clone());
+        manager.store = Some(store);
+
+        Ok(manager)
+    }
+
     pub fn create_session(&mut self, title: String) -> String {
         let id = Uuid::new_v4().to_string();
         let mut session = ChatSession::new(title);
@@ -26,6 +62,80 @@ impl SessionManager {
         id
     }
 
+    /// 复制会话当前分支上直到 `message_index`（含）的消息，创建一个新会话并切换过去
+    pub fn fork_session(&mut self, id: &str, message_index: usize) -> Result<String> {
+        let source = self
+            .sessions
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        if message_index >= source.messages.len() {
+            return Err(anyhow::anyhow!("Message index out of range"));
+        }
+
+        let mut session = ChatSession::new(format!("{} (fork)", source.title));
+        session.messages = source
+            .messages
+            .iter()
+            .take(message_index + 1)
+            .cloned()
+            .collect();
+        session.forked_from = Some(ForkOrigin {
+            session_id: id.to_string(),
+            message_index,
+        });
+        session.title_locked = true;
+        session.settings = source.settings.clone();
+
+        let new_id = session.id.clone();
+        self.sessions.insert(new_id.clone(), session);
+        self.current_session_id = Some(new_id.clone());
+        Ok(new_id)
+    }
+
+    pub fn get_session(&self, id: &str) -> Option<&ChatSession> {
+        self.sessions.get(id)
+    }
+
+    pub fn get_session_mut(&mut self, id: &str) -> Option<&mut ChatSession> {
+        self.sessions.get_mut(id)
+    }
+
+    pub fn rename_session(&mut self, id: &str, title: String) -> Result<()> {
+        let session = self
+            .sessions
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        session.rename(title);
+        Ok(())
+    }
+
+    pub fn export_session(&self, id: &str, format: ExportFormat) -> Result<String> {
+        let session = self
+            .sessions
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        export::export(session, format)
+    }
+
+    /// 导入文件中的会话并切换到第一个，返回导入的数量
+    ///
+    /// 与已有会话 id 相同时分配新的 id，不会覆盖已有会话。
+    pub fn import_sessions(&mut self, contents: &str) -> Result<usize> {
+        let sessions = import::parse(contents)?;
+        let count = sessions.len();
+
+        for (index, mut session) in sessions.into_iter().enumerate() {
+            if self.sessions.contains_key(&session.id) {
+                session.id = Uuid::new_v4().to_string();
+            }
+            if index == 0 {
+                self.current_session_id = Some(session.id.clone());
+            }
+            self.sessions.insert(session.id.clone(), session);
+        }
+        Ok(count)
+    }
+
     pub fn get_current_session(&self) -> Option<&ChatSession> {
         self.current_session_id
             .as_ref()
@@ -47,17 +157,11 @@ impl SessionManager {
         }
     }
 
+    /// 所有会话，最新创建的在前
     pub fn get_all_sessions(&self) -> Vec<&ChatSession> {
-        self.sessions.values().collect()
-    }
-
-    pub fn add_message_to_current(&mut self, message: Message) -> Result<()> {
-        if let Some(session) = self.get_current_session_mut() {
-            session.add_message(message);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("No active session"))
-        }
+        let mut sessions: Vec<&ChatSession> = self.sessions.values().collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.created_at));
+        sessions
     }
 
     pub fn delete_session(&mut self, id: &str) -> Result<()> {
@@ -65,9 +169,61 @@ impl SessionManager {
             if Some(id.to_string()) == self.current_session_id {
                 self.current_session_id = self.sessions.keys().next().cloned();
             }
+            self.saved_at.remove(id);
+            self.indexed_at.remove(id);
+            self.search_index.remove_session(id);
+            if let Some(store) = &self.store {
+                store.delete(id)?;
+            }
             Ok(())
         } else {
             Err(anyhow::anyhow!("Session not found"))
         }
     }
+
+    /// 重新索引自上次索引以来有改动的会话，返回是否有会话被重新索引
+    pub fn refresh_index(&mut self) -> bool {
+        let mut changed = false;
+        for session in self.sessions.values() {
+            if self.indexed_at.get(&session.id) == Some(&session.updated_at) {
+                continue;
+            }
+            self.search_index.index_session(session);
+            self.indexed_at
+                .insert(session.id.clone(), session.updated_at);
+            changed = true;
+        }
+        changed
+    }
+
+    /// 在所有会话中搜索消息
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        self.search_index.search(query, &self.sessions)
+    }
+
+    /// 将自上次保存以来有改动的会话写入磁盘
+    pub fn persist_changes(&mut self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+
+        for session in self.sessions.values() {
+            if self.saved_at.get(&session.id) == Some(&session.updated_at) {
+                continue;
+            }
+
+            match store.save(session) {
+                Ok(()) => {
+                    self.saved_at.insert(session.id.clone(), session.updated_at);
+                }
+                Err(e) => error!(?e, id = %session.id, "Failed to save session"),
+            }
+        }
+    }
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,73 @@
+use super::session::ChatSession;
+use crate::fs::write_atomic;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// 会话的磁盘存储，每个会话保存为一个 JSON 文件
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// 当前用户的默认会话目录，例如 `~/.local/share/llm-client/sessions`
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("llm-client").join("sessions"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 读取所有会话，无法解析的文件会被跳过
+    pub fn load_all(&self) -> Result<Vec<ChatSession>> {
+        let mut sessions = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            match fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<ChatSession>(&bytes)?))
+            {
+                Ok(session) => sessions.push(session),
+                Err(e) => warn!(?e, path = %path.display(), "Skipping unreadable session file"),
+            }
+        }
+
+        debug!(count = sessions.len(), "Loaded sessions from disk");
+        Ok(sessions)
+    }
+
+    /// 原子地保存会话：先写入临时文件并同步到磁盘，再重命名覆盖
+    pub fn save(&self, session: &ChatSession) -> Result<()> {
+        write_atomic(
+            &self.session_path(&session.id),
+            &serde_json::to_vec_pretty(session)?,
+        )?;
+        debug!(id = %session.id, "Saved session");
+        Ok(())
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        let path = self.session_path(id);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn session_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
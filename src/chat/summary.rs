@@ -0,0 +1,148 @@
+use super::ChatSession;
+use crate::llm::context;
+use crate::llm::tokens::{self, Tokenizer};
+use crate::llm::{Completion, LLMClient, Message, MessageContent};
+use anyhow::Result;
+
+/// 会话超过可用上下文的这个比例时开始总结
+const SUMMARIZE_AT: f32 = 0.75;
+/// 总结后剩下的原始消息大约占可用上下文的比例
+const KEEP_RATIO: f32 = 0.5;
+/// 最近的几条消息总是保留原文
+const KEEP_RECENT: usize = 4;
+
+/// 需要总结的开头消息数量，会话还不够长时返回 `None`
+///
+/// 切点不会越过会话中的第一个分支点，见 [`ChatSession::summarizable`]。
+pub fn summary_cut(
+    session: &ChatSession,
+    tokenizer: &dyn Tokenizer,
+    budget: usize,
+) -> Option<usize> {
+    let messages = &session.messages;
+    let counts: Vec<usize> = messages
+        .iter()
+        .map(|message| tokens::count_message(tokenizer, message))
+        .collect();
+    let total: usize = counts.iter().sum();
+    if (total as f32) < budget as f32 * SUMMARIZE_AT {
+        return None;
+    }
+
+    let limit = messages
+        .len()
+        .saturating_sub(KEEP_RECENT)
+        .min(session.summarizable());
+    let mut remaining = total;
+    let mut cut = 0;
+    while cut < limit && remaining as f32 > budget as f32 * KEEP_RATIO {
+        remaining -= counts[cut];
+        cut += 1;
+    }
+
+    // 工具调用和结果不能分开，切点落在它们中间时往前退
+    while cut > 0 && cut < messages.len() && is_tool_message(&messages[cut]) {
+        cut -= 1;
+    }
+
+    // 只剩一条已有的总结时没有必要再总结
+    let only_summary = cut == 1 && matches!(messages[0].content, MessageContent::Summary { .. });
+    (cut > 0 && !only_summary).then_some(cut)
+}
+
+fn is_tool_message(message: &Message) -> bool {
+    matches!(
+        message.content,
+        MessageContent::Function { .. } | MessageContent::ToolResult { .. }
+    )
+}
+
+/// 让模型总结这些消息，已有的总结会作为上下文一起发送
+pub async fn summarize(client: &LLMClient, messages: &[Message]) -> Result<Completion> {
+    let reply = client.complete(context::summary_request(messages)).await?;
+    let summary = reply.text.trim();
+    if summary.is_empty() {
+        return Err(anyhow::anyhow!("Model returned an empty summary"));
+    }
+    Ok(Completion {
+        text: summary.to_string(),
+        ..reply
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::tokens::Words;
+    use crate::llm::Role;
+
+    /// 每条消息 10 个 token（6 个单词加上固定开销）
+    fn text(index: usize) -> Message {
+        let role = if index.is_multiple_of(2) {
+            Role::User
+        } else {
+            Role::Assistant
+        };
+        Message::new(role, MessageContent::Text(format!("{} a a a a a", index)))
+    }
+
+    fn session(len: usize) -> ChatSession {
+        let mut session = ChatSession::new("Summary".to_string());
+        for index in 0..len {
+            session.add_message(text(index));
+        }
+        session
+    }
+
+    #[test]
+    fn leaves_short_sessions_alone() {
+        assert_eq!(summary_cut(&session(10), &Words, 1000), None);
+    }
+
+    #[test]
+    fn keeps_about_half_of_the_budget() {
+        assert_eq!(summary_cut(&session(10), &Words, 100), Some(5));
+        // 最近的几条消息总是保留
+        assert_eq!(summary_cut(&session(6), &Words, 10), Some(2));
+    }
+
+    #[test]
+    fn stops_at_the_first_branch_point() {
+        let mut session = session(4);
+        session.branch_from(2);
+        for index in 2..10 {
+            session.add_message(text(index));
+        }
+        assert_eq!(summary_cut(&session, &Words, 100), Some(2));
+
+        // 越过分支点的总结不做修改
+        session.summarize_prefix(5, "summary".to_string());
+        assert_eq!(session.messages.len(), 10);
+
+        session.summarize_prefix(2, "summary".to_string());
+        assert_eq!(session.messages.len(), 9);
+        assert_eq!(session.variant_info(1), Some((1, 2)));
+    }
+
+    #[test]
+    fn does_not_split_tool_calls() {
+        let mut session = session(10);
+        session.messages[4] = Message::new(
+            Role::Assistant,
+            MessageContent::Function {
+                id: "call".to_string(),
+                name: "call".to_string(),
+                arguments: serde_json::json!({}),
+            },
+        );
+        session.messages[5] = Message::new(
+            Role::Tool,
+            MessageContent::ToolResult {
+                call_id: "call".to_string(),
+                name: "call".to_string(),
+                content: "a a a a a a".to_string(),
+            },
+        );
+        assert_eq!(summary_cut(&session, &Words, 100), Some(3));
+    }
+}
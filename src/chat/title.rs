@@ -0,0 +1,54 @@
+use crate::llm::{Completion, LLMClient, Message, MessageContent, Role};
+use anyhow::Result;
+use std::collections::VecDeque;
+
+/// 生成的标题最多保留的字符数
+const MAX_TITLE_CHARS: usize = 60;
+
+/// 让模型根据会话中的对话内容生成一个简短的标题，返回的文字为整理后的标题
+pub async fn generate_title(
+    client: &LLMClient,
+    messages: &VecDeque<Message>,
+    prompt: &str,
+) -> Result<Completion> {
+    // 只发送对话文字，工具调用和图片对生成标题没有帮助
+    let mut request: Vec<Message> = messages
+        .iter()
+        .filter(|message| matches!(message.role, Role::User | Role::Assistant))
+        .filter_map(|message| match &message.content {
+            MessageContent::Text(text) | MessageContent::Image { text, .. } => Some(Message::new(
+                message.role.clone(),
+                MessageContent::Text(text.clone()),
+            )),
+            _ => None,
+        })
+        .collect();
+    request.push(Message::new(
+        Role::User,
+        MessageContent::Text(prompt.to_string()),
+    ));
+
+    let reply = client.complete(request).await?;
+    let title =
+        clean_title(&reply.text).ok_or_else(|| anyhow::anyhow!("Model returned an empty title"))?;
+    Ok(Completion {
+        text: title,
+        ..reply
+    })
+}
+
+/// 取回复的第一行，去掉引号、Markdown 标记和 "Title:" 之类的前缀
+fn clean_title(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line);
+    let title = line
+        .trim_matches(|c: char| c.is_whitespace() || "\"'`*#“”「」.。".contains(c))
+        .chars()
+        .take(MAX_TITLE_CHARS)
+        .collect::<String>();
+
+    (!title.is_empty()).then_some(title)
+}
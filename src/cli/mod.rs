@@ -0,0 +1,411 @@
+use crate::chat::ledger::{LedgerEntry, UsageLedger};
+use crate::chat::session::SessionSettings;
+use crate::chat::{title, ChatSession, SessionManager, SessionStore};
+use crate::config::AppConfig;
+use crate::llm::{LLMClient, Message, MessageContent, Role, StreamMessage, ToolRegistry};
+use crate::{proxy, tui};
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::net::SocketAddr;
+use tracing::warn;
+
+/// 命令行参数，没有子命令时启动图形界面
+#[derive(Parser)]
+#[command(
+    name = "llm-client",
+    version,
+    about = "Chat with language models in a window or from the terminal"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Chat interactively in the terminal")]
+    Chat(SessionArgs),
+    #[command(
+        about = "Send one prompt and print the reply; piped stdin is appended to the prompt"
+    )]
+    Ask {
+        #[command(flatten)]
+        session: SessionArgs,
+        #[arg(long, help = "Save the exchange as a new session")]
+        save: bool,
+        #[arg(help = "Prompt text; read from stdin when omitted")]
+        prompt: Vec<String>,
+    },
+    #[command(about = "List saved sessions")]
+    Sessions,
+    #[command(about = "Open the full-screen terminal interface")]
+    Tui {
+        #[arg(short, long, help = "Profile from the configuration file")]
+        profile: Option<String>,
+    },
+    #[command(
+        about = "Serve an OpenAI-compatible API on localhost that records requests as sessions"
+    )]
+    Serve {
+        #[arg(
+            short,
+            long,
+            default_value = "127.0.0.1:8080",
+            help = "Address to listen on"
+        )]
+        listen: SocketAddr,
+        #[arg(
+            short,
+            long,
+            help = "Profile used when the request's model is not a profile name"
+        )]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Args)]
+pub struct SessionArgs {
+    #[arg(
+        short,
+        long,
+        help = "Continue a saved session by id or unique id prefix"
+    )]
+    session: Option<String>,
+    #[arg(short, long, help = "Profile from the configuration file")]
+    profile: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Model to use instead of the session or profile default"
+    )]
+    model: Option<String>,
+    #[arg(long, help = "System prompt for a new session")]
+    system: Option<String>,
+}
+
+/// 命令行模式共用的配置、会话和用量账本，与图形界面读写相同的文件
+pub(crate) struct Context {
+    pub(crate) config: AppConfig,
+    pub(crate) sessions: SessionManager,
+    pub(crate) ledger: UsageLedger,
+}
+
+impl Context {
+    pub(crate) fn load() -> Result<Self> {
+        let config = match AppConfig::default_path() {
+            Some(path) => AppConfig::load_or_create(&path)?,
+            None => AppConfig::default(),
+        };
+        let store = SessionStore::default_dir()
+            .ok_or_else(|| anyhow::anyhow!("No data directory available"))
+            .and_then(SessionStore::new)?;
+        let ledger = match UsageLedger::default_path() {
+            Some(path) => UsageLedger::open(path)?,
+            None => UsageLedger::default(),
+        };
+
+        Ok(Self {
+            config,
+            sessions: SessionManager::with_store(store)?,
+            ledger,
+        })
+    }
+
+    pub(crate) fn client(&self, profile: Option<&str>) -> Result<LLMClient> {
+        let profile = match profile {
+            Some(name) => self
+                .config
+                .profile(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown profile: {}", name))?,
+            None => self.config.default_profile(),
+        };
+        Ok(LLMClient::new(profile.to_llm_config()).with_tools(ToolRegistry::with_builtin_tools()))
+    }
+
+    /// 按完整 id 或唯一的 id 前缀查找会话
+    fn find_session(&self, id: &str) -> Result<String> {
+        if self.sessions.get_session(id).is_some() {
+            return Ok(id.to_string());
+        }
+        let matches: Vec<&ChatSession> = self
+            .sessions
+            .get_all_sessions()
+            .into_iter()
+            .filter(|session| session.id.starts_with(id))
+            .collect();
+        match matches.as_slice() {
+            [session] => Ok(session.id.clone()),
+            [] => Err(anyhow::anyhow!("No session matches {}", id)),
+            _ => Err(anyhow::anyhow!(
+                "{} sessions match {}, use a longer id",
+                matches.len(),
+                id
+            )),
+        }
+    }
+
+    /// 继续指定的会话，或者创建一个新会话
+    fn open_session(&mut self, args: &SessionArgs) -> Result<String> {
+        if let Some(id) = &args.session {
+            return self.find_session(id);
+        }
+        let id = self.sessions.create_session("New Chat".to_string());
+        if let Some(system) = &args.system {
+            if let Some(session) = self.sessions.get_session_mut(&id) {
+                session.set_settings(SessionSettings {
+                    system_prompt: system.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(id)
+    }
+
+    /// 发送会话并把回复逐段写到标准输出，工具调用和回复会加入会话
+    async fn reply(
+        &mut self,
+        client: &LLMClient,
+        session: &mut ChatSession,
+        model: Option<&str>,
+    ) -> Result<()> {
+        let mut overrides = session.settings.overrides.clone();
+        if let Some(model) = model {
+            overrides.model = Some(model.to_string());
+        }
+
+        let mut rx = client
+            .send_message_streaming(&session.request_messages(), overrides)
+            .await?;
+        while let Some(message) = rx.recv().await {
+            match message {
+                StreamMessage::Chunk(chunk) => {
+                    print!("{}", chunk);
+                    io::stdout().flush()?;
+                }
+                StreamMessage::Intermediate(message) => {
+                    match &message.content {
+                        MessageContent::Function { name, .. } => eprintln!("[calling {}]", name),
+                        MessageContent::Text(_) => println!(),
+                        _ => {}
+                    }
+                    session.add_message(message);
+                }
+                StreamMessage::Done(message) => {
+                    println!();
+                    if let Some(usage) = &message.usage {
+                        if let Err(e) = self.ledger.record(LedgerEntry::new(&session.id, usage)) {
+                            warn!(?e, "Failed to record usage");
+                        }
+                    }
+                    session.add_message(message);
+                    return Ok(());
+                }
+                StreamMessage::Retrying {
+                    error,
+                    attempt,
+                    delay,
+                } => {
+                    // 已经输出的部分无法撤回，重试后的回复会从头输出
+                    eprintln!(
+                        "\n[{} — retrying in {:.0}s (attempt {})]",
+                        error.hint(),
+                        delay.as_secs_f32().ceil(),
+                        attempt
+                    );
+                }
+                StreamMessage::Error(error) => return Err(error.into()),
+            }
+        }
+        Err(anyhow::anyhow!("The reply ended unexpectedly"))
+    }
+
+    /// 新会话在第一次回复后生成标题
+    async fn generate_title(&mut self, client: &LLMClient, session: &mut ChatSession) {
+        let prompt = self.config.title_prompt.trim();
+        if session.title_locked || prompt.is_empty() {
+            return;
+        }
+        match title::generate_title(client, &session.messages, prompt).await {
+            Ok(reply) => {
+                if let Some(usage) = &reply.usage {
+                    if let Err(e) = self.ledger.record(LedgerEntry::new(&session.id, usage)) {
+                        warn!(?e, "Failed to record usage");
+                    }
+                }
+                session.rename(reply.text);
+            }
+            Err(e) => warn!(?e, "Failed to generate session title"),
+        }
+    }
+}
+
+/// 运行命令行子命令
+pub fn run(command: Command) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut context = Context::load()?;
+
+    runtime.block_on(async {
+        match command {
+            Command::Chat(args) => chat(&mut context, &args).await,
+            Command::Ask {
+                session,
+                save,
+                prompt,
+            } => ask(&mut context, &session, save, prompt).await,
+            Command::Sessions => {
+                list_sessions(&context);
+                Ok(())
+            }
+            Command::Tui { profile } => tui::run(context, profile.as_deref()).await,
+            Command::Serve { listen, profile } => {
+                proxy::run(context, listen, profile.as_deref()).await
+            }
+        }
+    })
+}
+
+/// 交互式对话，每轮结束后保存会话
+async fn chat(context: &mut Context, args: &SessionArgs) -> Result<()> {
+    let client = context.client(args.profile.as_deref())?;
+    let id = context.open_session(args)?;
+    let mut session = context
+        .sessions
+        .get_session(&id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+    eprintln!(
+        "Chatting in \"{}\" ({}, {} messages). Type /exit or press Ctrl-D to quit.",
+        session.title,
+        session.id,
+        session.messages.len()
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        eprint!("> ");
+        io::stderr().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            eprintln!();
+            break;
+        };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "/exit" | "/quit" => break,
+            _ => {}
+        }
+
+        let len = session.messages.len();
+        session.add_message(Message::new(
+            Role::User,
+            MessageContent::Text(line.to_string()),
+        ));
+        if let Err(e) = context
+            .reply(&client, &mut session, args.model.as_deref())
+            .await
+        {
+            // 没有得到回复的消息不保留，包括中途的工具调用
+            eprintln!("Error: {}", e);
+            session.messages.truncate(len);
+            continue;
+        }
+        context.generate_title(&client, &mut session).await;
+        save(context, &session);
+    }
+
+    // 没有发送过消息的新会话不保存
+    if session.messages.is_empty() && args.session.is_none() {
+        context.sessions.delete_session(&id)?;
+    }
+    Ok(())
+}
+
+/// 单次提问；只有继续已有会话或使用 `--save` 时才保存
+async fn ask(
+    context: &mut Context,
+    args: &SessionArgs,
+    save_session: bool,
+    prompt: Vec<String>,
+) -> Result<()> {
+    // 先检查配置和会话，参数有误时不必等待标准输入
+    let client = context.client(args.profile.as_deref())?;
+    if let Some(id) = &args.session {
+        context.find_session(id)?;
+    }
+
+    let prompt = prompt.join(" ");
+    let input = if io::stdin().is_terminal() {
+        String::new()
+    } else {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        input
+    };
+    let text = match (prompt.trim().is_empty(), input.trim().is_empty()) {
+        (false, false) => format!("{}\n\n{}", prompt, input.trim_end()),
+        (false, true) => prompt,
+        (true, false) => input.trim_end().to_string(),
+        (true, true) => return Err(anyhow::anyhow!("No prompt given")),
+    };
+
+    let persist = save_session || args.session.is_some();
+    let mut session = if persist {
+        let id = context.open_session(args)?;
+        context
+            .sessions
+            .get_session(&id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?
+    } else {
+        let mut session = ChatSession::new("New Chat".to_string());
+        if let Some(system) = &args.system {
+            session.settings.system_prompt = system.clone();
+        }
+        session
+    };
+
+    let len = session.messages.len();
+    session.add_message(Message::new(Role::User, MessageContent::Text(text)));
+    if let Err(e) = context
+        .reply(&client, &mut session, args.model.as_deref())
+        .await
+    {
+        // 和 `chat` 一样，没有得到回复的消息不保存
+        session.messages.truncate(len);
+        return Err(e);
+    }
+    if persist {
+        context.generate_title(&client, &mut session).await;
+        save(context, &session);
+        eprintln!("Saved to session {}", session.id);
+    }
+    Ok(())
+}
+
+/// 写回会话管理器并保存到磁盘
+fn save(context: &mut Context, session: &ChatSession) {
+    if let Some(stored) = context.sessions.get_session_mut(&session.id) {
+        *stored = session.clone();
+    }
+    context.sessions.persist_changes();
+}
+
+fn list_sessions(context: &Context) {
+    let mut sessions = context.sessions.get_all_sessions();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+    for session in sessions {
+        println!(
+            "{}  {}  {:>4}  {}",
+            session.id,
+            session
+                .updated_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M"),
+            session.messages.len(),
+            session.title
+        );
+    }
+}
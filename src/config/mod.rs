@@ -0,0 +1,174 @@
+use crate::fs::write_atomic;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+pub mod preset;
+pub mod pricing;
+pub mod profile;
+
+pub use preset::Preset;
+pub use pricing::ModelPrice;
+pub use profile::Profile;
+
+/// 保存在配置文件中的应用配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AppConfig {
+    /// 启动时使用的配置档案
+    pub default_profile: String,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// 第一次回复完成后用来生成会话标题的提示词，为空时不自动生成
+    #[serde(default = "default_title_prompt")]
+    pub title_prompt: String,
+    /// 新建会话时可以选择的预设
+    #[serde(default = "Preset::builtin")]
+    pub presets: Vec<Preset>,
+    /// 统计费用时使用的价格表
+    #[serde(default = "ModelPrice::builtin")]
+    pub prices: Vec<ModelPrice>,
+    /// 每月的预算（美元），超出后在界面上提示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_budget: Option<f64>,
+}
+
+fn default_title_prompt() -> String {
+    "Summarize the conversation above as a title of at most six words, \
+     in the language of the conversation. Reply with the title only."
+        .to_string()
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        let mut profiles = vec![
+            Profile {
+                name: "mistral".to_string(),
+                api_base: MISTRAL_API_BASE.to_string(),
+                api_key_env: Some("MISTRAL_API_KEY".to_string()),
+                model: "mistral-large-latest".to_string(),
+                ..Profile::new("mistral")
+            },
+            Profile {
+                api_key_env: Some("OPENAI_API_KEY".to_string()),
+                ..Profile::new("openai")
+            },
+        ];
+        // 之前通过环境变量配置的用户升级后继续使用原来的接口
+        if let Some(profile) = environment_profile(|var| std::env::var(var).ok()) {
+            profiles.insert(0, profile);
+        }
+
+        Self {
+            default_profile: profiles[0].name.clone(),
+            profiles,
+            title_prompt: default_title_prompt(),
+            presets: Preset::builtin(),
+            prices: ModelPrice::builtin(),
+            monthly_budget: None,
+        }
+    }
+}
+
+const MISTRAL_API_BASE: &str = "https://api.mistral.ai/v1";
+
+/// 旧版本没有配置文件，从 `OPENAI_API_KEY` 和 `OPENAI_API_BASE` 读取接口设置，
+/// 接口地址默认为 Mistral；设置了其中任意一个时按同样的方式生成配置档案
+fn environment_profile(var: impl Fn(&str) -> Option<String>) -> Option<Profile> {
+    let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+    let api_key = var("OPENAI_API_KEY");
+    let api_base = var("OPENAI_API_BASE");
+    if api_key.is_none() && api_base.is_none() {
+        return None;
+    }
+
+    Some(Profile {
+        api_base: api_base.unwrap_or_else(|| MISTRAL_API_BASE.to_string()),
+        api_key_env: Some("OPENAI_API_KEY".to_string()),
+        model: "mistral-large-latest".to_string(),
+        ..Profile::new("environment")
+    })
+}
+
+impl AppConfig {
+    /// 当前用户的默认配置文件，例如 `~/.config/llm-client/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("llm-client").join("config.toml"))
+    }
+
+    /// 读取配置文件，不存在时写入一份默认配置
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            info!(path = %path.display(), "Creating default configuration file");
+            let config = Self::default();
+            config.save(path)?;
+            return Ok(config);
+        }
+
+        let config: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        if config.profiles.is_empty() {
+            return Err(anyhow::anyhow!("No profiles defined in {}", path.display()));
+        }
+        Ok(config)
+    }
+
+    /// 原子地写入配置文件
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, toml::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn preset(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// 默认配置档案，找不到时退回到第一个
+    pub fn default_profile(&self) -> &Profile {
+        self.profile(&self.default_profile)
+            .or_else(|| self.profiles.first())
+            .expect("configuration has no profiles")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(vars: &[(&str, &str)]) -> Option<Profile> {
+        environment_profile(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn keeps_the_endpoint_from_the_environment() {
+        let profile = profile(&[
+            ("OPENAI_API_KEY", "sk-test"),
+            ("OPENAI_API_BASE", "http://localhost:8000/v1"),
+        ])
+        .unwrap();
+        assert_eq!(profile.api_base, "http://localhost:8000/v1");
+        assert_eq!(profile.api_key_env.as_deref(), Some("OPENAI_API_KEY"));
+        // 密钥只通过环境变量引用，不写入配置文件
+        assert_eq!(profile.api_key, None);
+    }
+
+    #[test]
+    fn uses_the_previous_default_endpoint_with_only_a_key() {
+        let profile = profile(&[("OPENAI_API_KEY", "sk-test")]).unwrap();
+        assert_eq!(profile.api_base, MISTRAL_API_BASE);
+        assert_eq!(profile.model, "mistral-large-latest");
+    }
+
+    #[test]
+    fn skips_the_profile_without_variables() {
+        assert!(profile(&[]).is_none());
+        assert!(profile(&[("OPENAI_API_KEY", " ")]).is_none());
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+/// 可复用的会话预设：系统提示词以及可选的模型和温度
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+impl Preset {
+    pub fn new(name: impl Into<String>, system_prompt: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            system_prompt: system_prompt.into(),
+            model: None,
+            temperature: None,
+        }
+    }
+
+    /// 内置的预设，写入新建的配置文件
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new(
+                "Code reviewer",
+                "You are a meticulous senior engineer reviewing code. Point out bugs, \
+                 unclear naming and missing error handling, and suggest concrete fixes.",
+            ),
+            Self {
+                temperature: Some(0.2),
+                ..Self::new(
+                    "Translator",
+                    "Translate everything the user sends between Chinese and English. \
+                     Reply with the translation only.",
+                )
+            },
+            Self::new(
+                "Concise assistant",
+                "Answer as briefly as possible. Use lists instead of paragraphs.",
+            ),
+        ]
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+/// 模型的价格，单位为美元每百万 token
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelPrice {
+    /// 模型名称前缀，匹配多个时使用最长的一个
+    pub model: String,
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn new(model: impl Into<String>, input: f64, output: f64) -> Self {
+        Self {
+            model: model.into(),
+            input,
+            output,
+        }
+    }
+
+    /// 内置的价格表，写入新建的配置文件
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new("gpt-4o-mini", 0.15, 0.6),
+            Self::new("gpt-4o", 2.5, 10.0),
+            Self::new("gpt-4.1-mini", 0.4, 1.6),
+            Self::new("gpt-4.1", 2.0, 8.0),
+            Self::new("o3-mini", 1.1, 4.4),
+            Self::new("mistral-large", 2.0, 6.0),
+            Self::new("mistral-small", 0.2, 0.6),
+            Self::new("claude-3-5-haiku", 0.8, 4.0),
+            Self::new("claude-3-5-sonnet", 3.0, 15.0),
+            Self::new("claude-sonnet-4", 3.0, 15.0),
+            Self::new("claude-opus-4", 15.0, 75.0),
+        ]
+    }
+}
+
+/// 按价格表计算费用，价格表中没有这个模型时返回 `None`
+pub fn cost(
+    prices: &[ModelPrice],
+    model: &str,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+) -> Option<f64> {
+    let price = prices
+        .iter()
+        .filter(|price| model.starts_with(&price.model))
+        .max_by_key(|price| price.model.len())?;
+    Some(
+        (prompt_tokens as f64 * price.input + completion_tokens as f64 * price.output)
+            / 1_000_000.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_the_longest_matching_prefix() {
+        let prices = [
+            ModelPrice::new("gpt-4o", 2.5, 10.0),
+            ModelPrice::new("gpt-4o-mini", 0.15, 0.6),
+            ModelPrice::new("gpt", 100.0, 100.0),
+        ];
+        assert_eq!(
+            cost(&prices, "gpt-4o-mini-2024-07-18", 1_000_000, 0),
+            Some(0.15)
+        );
+        assert_eq!(cost(&prices, "gpt-4o-2024-08-06", 0, 1_000_000), Some(10.0));
+        assert_eq!(cost(&prices, "gpt-3.5-turbo", 500_000, 0), Some(50.0));
+
+        // 顺序不影响匹配结果
+        let mut reversed = prices.to_vec();
+        reversed.reverse();
+        assert_eq!(cost(&reversed, "gpt-4o-mini", 1_000_000, 0), Some(0.15));
+    }
+
+    #[test]
+    fn returns_none_for_unknown_models() {
+        let prices = ModelPrice::builtin();
+        assert_eq!(cost(&prices, "llama3.1:8b", 1000, 1000), None);
+        // 前缀只从模型名称开头匹配
+        assert_eq!(cost(&prices, "openai/gpt-4o", 1000, 1000), None);
+        assert_eq!(cost(&[], "gpt-4o", 1000, 1000), None);
+    }
+}
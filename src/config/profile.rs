@@ -0,0 +1,70 @@
+use crate::llm::context::ContextPolicy;
+use crate::llm::{LLMConfig, ProviderKind};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// 一组命名的接口配置，例如 OpenAI、Mistral 或内部网关
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub api_base: String,
+    /// 从该环境变量读取 API Key，优先于 `api_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// 直接写在配置文件中的 API Key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    /// 覆盖按模型名称推断的上下文长度
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub context_policy: ContextPolicy,
+}
+
+impl Profile {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            provider: ProviderKind::OpenAI,
+            api_base: "https://api.openai.com/v1".to_string(),
+            api_key_env: None,
+            api_key: None,
+            model: "gpt-4o-mini".to_string(),
+            temperature: 0.7,
+            max_tokens: 1000,
+            context_window: None,
+            context_policy: ContextPolicy::default(),
+        }
+    }
+
+    /// 解析 API Key 引用，得到客户端使用的配置
+    pub fn to_llm_config(&self) -> LLMConfig {
+        let env_key = self
+            .api_key_env
+            .as_deref()
+            .filter(|var| !var.is_empty())
+            .and_then(|var| match std::env::var(var) {
+                Ok(key) => Some(key),
+                Err(_) => {
+                    warn!(var, profile = %self.name, "API key environment variable is not set");
+                    None
+                }
+            });
+
+        LLMConfig {
+            provider: self.provider,
+            api_key: env_key.or_else(|| self.api_key.clone()).unwrap_or_default(),
+            api_base: self.api_base.clone(),
+            model: self.model.clone(),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            context_window: self.context_window,
+            context_policy: self.context_policy,
+        }
+    }
+}
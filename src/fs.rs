@@ -0,0 +1,49 @@
+use anyhow::Result;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// 原子地写入文件：先写入同目录下的临时文件并同步到磁盘，再重命名覆盖，
+/// 写到一半退出时原文件保持不变
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Not a file path: {}", path.display()))?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_the_file_without_leaving_a_temporary() {
+        let dir = std::env::temp_dir().join(format!("llm-client-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("nested").join("config.toml");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["config.toml"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
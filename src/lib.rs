@@ -0,0 +1,9 @@
+pub mod chat;
+pub mod cli;
+pub mod config;
+mod fs;
+pub mod llm;
+pub mod mock;
+pub mod proxy;
+pub mod tui;
+pub mod ui;
@@ -1,177 +1,484 @@
 use anyhow::Result;
-use async_openai::{
-    config::OpenAIConfig,
-    types::{ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs},
-    Client as OpenAIClient,
-};
-use chrono::Utc;
 use futures::StreamExt;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
 use tracing::{debug, error, info, warn};
 
 use super::{
-    config::LLMConfig,
-    message::{Message, MessageContent, Role, StreamMessage},
+    config::{ConfigOverrides, LLMConfig},
+    context::{self, ContextPolicy},
+    error::LLMError,
+    message::{Message, MessageContent, Role, StreamMessage, Usage},
+    provider::{self, ChatEvent, ChatProvider, ChatRequest, ChatStream, ToolSpec},
+    tokens,
+    tools::ToolRegistry,
 };
 
+/// 一次回复中最多允许的工具调用轮数，防止模型陷入循环
+const MAX_TOOL_ROUNDS: usize = 8;
+/// 临时错误的最多重试次数
+const MAX_RETRIES: u32 = 3;
+/// 第一次重试前的等待时间，之后每次翻倍
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// 对话客户端，通过 `ChatProvider` 与具体的后端通信
 #[derive(Clone)]
 pub struct LLMClient {
-    client: OpenAIClient<OpenAIConfig>,
+    provider: Arc<RwLock<Arc<dyn ChatProvider>>>,
     config: Arc<RwLock<LLMConfig>>,
-    response_tx: Arc<RwLock<Option<tokio::sync::mpsc::Sender<StreamMessage>>>>,
+    tools: Arc<ToolRegistry>,
+}
+
+/// 流式返回中逐步拼接的工具调用
+#[derive(Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// 单次请求的回复
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    /// 接口报告的 token 用量，调用方负责记入账本
+    pub usage: Option<Usage>,
 }
 
 impl LLMClient {
     pub fn new(config: LLMConfig) -> Self {
-        let client = OpenAIClient::with_config(
-            OpenAIConfig::new()
-                .with_api_key(&config.api_key)
-                .with_api_base(&config.api_base),
-        );
-
         Self {
-            client,
+            provider: Arc::new(RwLock::new(provider::create_provider(&config))),
             config: Arc::new(RwLock::new(config)),
-            response_tx: Arc::new(RwLock::new(None)),
+            tools: Arc::new(ToolRegistry::new()),
         }
     }
 
-    pub async fn set_response_tx(&self, tx: tokio::sync::mpsc::Sender<StreamMessage>) {
-        let mut response_tx = self.response_tx.write().await;
-        *response_tx = Some(tx);
+    /// 设置可供模型调用的工具
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = Arc::new(tools);
+        self
     }
 
-    pub async fn send_message(&self, messages: Vec<Message>) -> Result<String> {
-        let config = self.config.read().await;
-
-        let request = match &messages[0].content {
-            MessageContent::Text(text) => CreateChatCompletionRequestArgs::default()
-                .model(&config.model)
-                .temperature(config.temperature)
-                .max_tokens(config.max_tokens)
-                .messages([ChatCompletionRequestUserMessageArgs::default()
-                    .content(text.clone())
-                    .build()?
-                    .into()])
-                .build()?,
-            MessageContent::Image { text, url } => {
-                // 实现图片消息处理
-                todo!()
-            }
-            MessageContent::Function { name, arguments } => {
-                // 实现函数调用
-                todo!()
-            }
+    /// 替换配置并重建后端，之后发出的请求都会使用新配置
+    pub async fn update_config(&self, config: LLMConfig) {
+        info!(
+            provider = ?config.provider,
+            api_base = %config.api_base,
+            model = %config.model,
+            "Updating LLM configuration"
+        );
+        *self.provider.write().await = provider::create_provider(&config);
+        *self.config.write().await = config;
+    }
+
+    /// 将整个会话历史发送给模型，并以流的形式返回回复
+    ///
+    /// 模型请求调用工具时会在本地执行，并把调用和结果作为
+    /// `StreamMessage::Intermediate` 发出，然后继续请求直到得到最终回答。
+    pub async fn send_message_streaming(
+        &self,
+        history: &VecDeque<Message>,
+        overrides: ConfigOverrides,
+    ) -> Result<mpsc::Receiver<StreamMessage>> {
+        info!(messages = history.len(), "Starting streaming request");
+        let mut history: Vec<Message> = history.iter().cloned().collect();
+
+        let request = self.build_request(&history, &overrides).await?;
+        let mut usage = Usage {
+            model: request.model.clone(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
         };
 
-        let mut response_text = String::new();
-
-        let mut stream = self.client.chat().create_stream(request).await?;
-
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(response) => {
-                    if let Some(text) = response
-                        .choices
-                        .get(0)
-                        .and_then(|choice| choice.delta.content.as_ref())
-                    {
-                        response_text.push_str(text);
-                        if let Some(tx) = &*self.response_tx.read().await {
-                            tx.send(StreamMessage::Chunk(text.clone())).await.ok();
+        let (tx, rx) = mpsc::channel(100);
+        debug!("Channel created");
+
+        let client = self.clone();
+        tokio::spawn(async move {
+            info!("Starting stream processing");
+
+            let mut request = Some(request);
+            for _ in 0..MAX_TOOL_ROUNDS {
+                let request = match request.take() {
+                    Some(request) => request,
+                    None => match client.build_request(&history, &overrides).await {
+                        Ok(request) => request,
+                        Err(e) => {
+                            let error = LLMError::classify(&e);
+                            let _ = tx.send(StreamMessage::Error(error)).await;
+                            return;
                         }
+                    },
+                };
+
+                let Some((content, tool_calls)) =
+                    client.stream_with_retry(request, &tx, &mut usage).await
+                else {
+                    return;
+                };
+
+                if tool_calls.is_empty() {
+                    let mut final_message =
+                        Message::new(Role::Assistant, MessageContent::Text(content));
+                    // 后端没有返回用量时不记录
+                    if usage.prompt_tokens > 0 || usage.completion_tokens > 0 {
+                        final_message.usage = Some(usage);
                     }
+                    let _ = tx.send(StreamMessage::Done(final_message)).await;
+                    return;
                 }
-                Err(e) => {
-                    error!(?e, "Stream error");
-                    return Err(anyhow::anyhow!("Stream error: {}", e));
+
+                let mut new_messages = Vec::new();
+                if !content.is_empty() {
+                    new_messages.push(Message::new(Role::Assistant, MessageContent::Text(content)));
+                }
+                for call in &tool_calls {
+                    let arguments = serde_json::from_str(&call.arguments)
+                        .unwrap_or_else(|_| serde_json::Value::String(call.arguments.clone()));
+                    new_messages.push(Message::new(
+                        Role::Assistant,
+                        MessageContent::Function {
+                            id: call.id.clone(),
+                            name: call.name.clone(),
+                            arguments,
+                        },
+                    ));
+                }
+                for call in tool_calls {
+                    info!(name = %call.name, "Calling tool");
+                    let arguments =
+                        serde_json::from_str(&call.arguments).unwrap_or(serde_json::Value::Null);
+                    let result = client.tools.call(&call.name, arguments).await;
+                    new_messages.push(Message::new(
+                        Role::Tool,
+                        MessageContent::ToolResult {
+                            call_id: call.id,
+                            name: call.name,
+                            content: result,
+                        },
+                    ));
+                }
+
+                for message in new_messages {
+                    history.push(message.clone());
+                    if tx.send(StreamMessage::Intermediate(message)).await.is_err() {
+                        return;
+                    }
                 }
             }
+
+            warn!("Too many tool call rounds");
+            let _ = tx
+                .send(StreamMessage::Error(LLMError::Other(format!(
+                    "Gave up after {} rounds of tool calls",
+                    MAX_TOOL_ROUNDS
+                ))))
+                .await;
+        });
+
+        Ok(rx)
+    }
+
+    /// 不带工具的单次请求，返回完整的回复文字和用量，用于生成标题等后台任务
+    pub async fn complete(&self, messages: Vec<Message>) -> Result<Completion> {
+        let mut request = self
+            .build_request(&messages, &ConfigOverrides::default())
+            .await?;
+        request.tools.clear();
+
+        let mut usage = Usage {
+            model: request.model.clone(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
+        };
+        let mut stream = self.create_stream(request).await?;
+        let mut text = String::new();
+        while let Some(event) = stream.next().await {
+            match event? {
+                ChatEvent::Content(delta) => text.push_str(&delta),
+                ChatEvent::Usage {
+                    prompt_tokens,
+                    completion_tokens,
+                } => {
+                    usage.prompt_tokens += prompt_tokens;
+                    usage.completion_tokens += completion_tokens;
+                }
+                ChatEvent::ToolCall { .. } => {}
+            }
         }
 
-        Ok(response_text)
+        let reported = usage.prompt_tokens > 0 || usage.completion_tokens > 0;
+        Ok(Completion {
+            text,
+            usage: reported.then_some(usage),
+        })
     }
 
-    pub async fn send_message_streaming(
+    /// 发送一轮请求并读取回复，临时错误按退避时间自动重试
+    ///
+    /// 重试前发出 `StreamMessage::Retrying`，接收端应丢弃这一轮已经显示的文字。
+    /// 出错或接收端关闭时返回 `None`，错误已经发送给接收端。
+    async fn stream_with_retry(
         &self,
-        message: Message,
-    ) -> Result<mpsc::Receiver<StreamMessage>> {
-        info!("Starting streaming request");
-        let config = self.config.read().await;
-        debug!(?config, "Using configuration");
-
-        let request = match &message.content {
-            MessageContent::Text(text) => {
-                debug!(text_length = text.len(), "Creating text request");
-                CreateChatCompletionRequestArgs::default()
-                    .model(&config.model)
-                    .temperature(config.temperature)
-                    .max_tokens(config.max_tokens)
-                    .messages([ChatCompletionRequestUserMessageArgs::default()
-                        .content(text.clone())
-                        .build()?
-                        .into()])
-                    .build()?
+        request: ChatRequest,
+        tx: &mpsc::Sender<StreamMessage>,
+        usage: &mut Usage,
+    ) -> Option<(String, Vec<PendingToolCall>)> {
+        let mut attempt = 0;
+        loop {
+            let result = match self.create_stream(request.clone()).await {
+                Ok(mut stream) => read_stream(&mut stream, tx, usage).await,
+                Err(e) => Err(RoundError::Failed(LLMError::classify(&e))),
+            };
+            let error = match result {
+                Ok(reply) => return Some(reply),
+                Err(RoundError::Cancelled) => return None,
+                Err(RoundError::Failed(error)) => error,
+            };
+
+            attempt += 1;
+            if !error.is_transient() || attempt > MAX_RETRIES {
+                error!(%error, attempt, "Request failed");
+                let _ = tx.send(StreamMessage::Error(error)).await;
+                return None;
             }
-            MessageContent::Image { text, url } => {
-                // 实现图片消息处理
-                todo!()
+
+            let delay = retry_delay(attempt, error.retry_after());
+            warn!(%error, attempt, ?delay, "Retrying request");
+            let retrying = StreamMessage::Retrying {
+                error,
+                attempt,
+                delay,
+            };
+            if tx.send(retrying).await.is_err() {
+                return None;
             }
-            MessageContent::Function { name, arguments } => {
-                // 实现函数调用
-                todo!()
+            tokio::select! {
+                _ = tx.closed() => return None,
+                _ = tokio::time::sleep(delay) => {}
             }
-        };
+        }
+    }
 
-        debug!("Creating stream");
-        let mut stream = self.client.chat().create_stream(request).await?;
-        info!("Stream created successfully");
+    async fn create_stream(&self, request: ChatRequest) -> Result<ChatStream> {
+        let provider = self.provider.read().await.clone();
+        debug!(provider = ?provider.kind(), "Creating stream");
+        provider.stream_chat(request).await
+    }
 
-        let (tx, rx) = mpsc::channel(100);
-        debug!("Channel created");
+    async fn build_request(
+        &self,
+        history: &[Message],
+        overrides: &ConfigOverrides,
+    ) -> Result<ChatRequest> {
+        let config = self.config.read().await.clone();
+        debug!(
+            provider = ?config.provider,
+            api_base = %config.api_base,
+            model = %config.model,
+            "Using configuration"
+        );
 
-        tokio::spawn(async move {
-            info!("Starting stream processing");
-            let mut content = String::new();
-
-            while let Some(result) = stream.next().await {
-                match result {
-                    Ok(response) => {
-                        for chat_choice in response.choices {
-                            if let Some(delta_content) = chat_choice.delta.content {
-                                if !delta_content.is_empty() {
-                                    content.push_str(&delta_content);
-                                    if let Err(e) =
-                                        tx.send(StreamMessage::Chunk(delta_content)).await
-                                    {
-                                        error!(?e, "Failed to send content");
-                                        return;
-                                    }
-                                }
-                            }
-
-                            if let Some(reason) = chat_choice.finish_reason {
-                                info!(reason = ?reason, "Stream finished with reason");
-                                let final_message = Message {
-                                    role: Role::Assistant,
-                                    content: MessageContent::Text(content),
-                                    timestamp: Utc::now(),
-                                };
-                                let _ = tx.send(StreamMessage::Done(final_message)).await;
-                                return;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!(?e, "Stream error");
-                        let _ = tx.send(StreamMessage::Error(e.to_string())).await;
-                        return;
+        let capabilities = self.provider.read().await.capabilities();
+        let tools = if capabilities.tools && !self.tools.is_empty() {
+            self.tools
+                .tools()
+                .map(|tool| ToolSpec {
+                    name: tool.name().to_string(),
+                    description: tool.description().to_string(),
+                    parameters: tool.parameters(),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // 后端不支持图片时只发送文字部分，保存的总结作为系统消息发送
+        let messages = history
+            .iter()
+            .map(|message| match &message.content {
+                MessageContent::Summary { text, .. } => context::summary_message(text),
+                MessageContent::Image { text, .. } if !capabilities.vision => {
+                    warn!("Provider does not support images, sending text only");
+                    Message {
+                        content: MessageContent::Text(text.clone()),
+                        ..message.clone()
                     }
                 }
+                _ => message.clone(),
+            })
+            .collect();
+
+        let model = overrides
+            .model
+            .clone()
+            .unwrap_or_else(|| config.model.clone());
+        let messages = fit_context(messages, &config, &model);
+
+        Ok(ChatRequest {
+            model,
+            temperature: overrides.temperature.unwrap_or(config.temperature),
+            max_tokens: config.max_tokens,
+            messages,
+            tools,
+        })
+    }
+}
+
+/// 一轮请求没有得到完整回复的原因
+enum RoundError {
+    /// 接收端已经关闭
+    Cancelled,
+    Failed(LLMError),
+}
+
+/// 按配置的策略裁剪消息，使请求不超过模型的上下文长度
+///
+/// 较早的消息由界面在后台总结并保存在会话中，这里只在总结之后仍然超出时
+/// 丢弃最早的消息；除 `DropOldest` 外系统消息（包括保存的总结）都会保留。
+fn fit_context(messages: Vec<Message>, config: &LLMConfig, model: &str) -> Vec<Message> {
+    let tokenizer = tokens::tokenizer_for_model(model);
+    let context_window = config.context_window(model);
+    let policy = config.context_policy;
+
+    let fitted = context::fit(
+        messages,
+        tokenizer.as_ref(),
+        context_window,
+        config.max_tokens as usize,
+        policy != ContextPolicy::DropOldest,
+    );
+    if !fitted.dropped.is_empty() {
+        info!(
+            dropped = fitted.dropped.len(),
+            context_window,
+            ?policy,
+            "Conversation exceeds the context window"
+        );
+    }
+    fitted.messages
+}
+
+/// 第 `attempt` 次重试前等待的时间
+///
+/// 服务端给出了 `Retry-After` 时按它等待，否则使用指数退避，
+/// 并在退避时间的一半到全部之间随机取值，避免多个请求同时重试。
+fn retry_delay(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    if let Some(delay) = retry_after {
+        return delay.min(MAX_RETRY_DELAY);
+    }
+    let backoff = RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_RETRY_DELAY);
+    // v4 UUID 的低 53 位是随机的
+    let random = uuid::Uuid::new_v4().as_u128() as u64 & ((1 << 53) - 1);
+    let jitter = random as f64 / (1u64 << 53) as f64;
+    backoff.mul_f64(0.5 + 0.5 * jitter)
+}
+
+/// 读取一轮流式响应，转发文本片段并拼接工具调用
+///
+/// token 用量累加到 `usage` 中。
+async fn read_stream(
+    stream: &mut ChatStream,
+    tx: &mpsc::Sender<StreamMessage>,
+    usage: &mut Usage,
+) -> Result<(String, Vec<PendingToolCall>), RoundError> {
+    let mut content = String::new();
+    let mut tool_calls: BTreeMap<usize, PendingToolCall> = BTreeMap::new();
+
+    loop {
+        // 接收端关闭（用户停止了回复）时立即结束，丢弃底层的 HTTP 流
+        let result = tokio::select! {
+            _ = tx.closed() => {
+                info!("Receiver closed, cancelling stream");
+                return Err(RoundError::Cancelled);
             }
-        });
+            result = stream.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+        };
 
-        Ok(rx)
+        match result {
+            Ok(ChatEvent::Content(delta_content)) => {
+                content.push_str(&delta_content);
+                if let Err(e) = tx.send(StreamMessage::Chunk(delta_content)).await {
+                    error!(?e, "Failed to send content");
+                    return Err(RoundError::Cancelled);
+                }
+            }
+            Ok(ChatEvent::ToolCall {
+                index,
+                id,
+                name,
+                arguments,
+            }) => {
+                let call = tool_calls.entry(index).or_default();
+                if let Some(id) = id {
+                    call.id = id;
+                }
+                if let Some(name) = name {
+                    call.name.push_str(&name);
+                }
+                if let Some(arguments) = arguments {
+                    call.arguments.push_str(&arguments);
+                }
+            }
+            Ok(ChatEvent::Usage {
+                prompt_tokens,
+                completion_tokens,
+            }) => {
+                usage.prompt_tokens += prompt_tokens;
+                usage.completion_tokens += completion_tokens;
+            }
+            Err(e) => {
+                warn!(?e, "Stream error");
+                return Err(RoundError::Failed(LLMError::classify(&e)));
+            }
+        }
+    }
+
+    info!("Stream finished");
+    Ok((content, tool_calls.into_values().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_with_jitter() {
+        for (attempt, full) in [(1, 1), (2, 2), (3, 4), (6, 32)] {
+            let full = Duration::from_secs(full);
+            for _ in 0..20 {
+                let delay = retry_delay(attempt, None);
+                assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+            }
+        }
+    }
+
+    #[test]
+    fn caps_the_delay() {
+        for attempt in [0, 7, 16, 17, u32::MAX] {
+            let delay = retry_delay(attempt, None);
+            assert!(delay <= MAX_RETRY_DELAY, "{}: {:?}", attempt, delay);
+        }
+        assert!(retry_delay(u32::MAX, None) >= MAX_RETRY_DELAY / 2);
+    }
+
+    #[test]
+    fn follows_retry_after() {
+        let delay = Duration::from_millis(1500);
+        assert_eq!(retry_delay(3, Some(delay)), delay);
+        assert_eq!(
+            retry_delay(1, Some(Duration::from_secs(3600))),
+            MAX_RETRY_DELAY
+        );
+        assert_eq!(retry_delay(1, Some(Duration::ZERO)), Duration::ZERO);
     }
 }
@@ -1,24 +1,82 @@
+use super::context::ContextPolicy;
+use super::provider::ProviderKind;
+use super::tokens;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LLMConfig {
+    #[serde(default)]
+    pub provider: ProviderKind,
     pub api_key: String,
     pub api_base: String,
     pub model: String,
     pub temperature: f32,
     pub max_tokens: u32,
+    /// 上下文长度，未设置时按模型名称推断
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub context_policy: ContextPolicy,
 }
 
-impl Default for LLMConfig {
-    fn default() -> Self {
-        Self {
-            api_key: std::env::var("OPENAI_API_KEY")
-                .unwrap_or_else(|_| "synmeS7JUMYHDSucuvtJkwJ0djDbvvQB".to_string()),
-            api_base: std::env::var("OPENAI_API_BASE")
-                .unwrap_or_else(|_| "https://api.mistral.ai/v1".to_string()),
-            model: "mistral-large-latest".to_string(),
+/// 调试输出中不显示 API Key，避免写进日志
+impl fmt::Debug for LLMConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let api_key = if self.api_key.is_empty() {
+            "<empty>"
+        } else {
+            "<redacted>"
+        };
+        f.debug_struct("LLMConfig")
+            .field("provider", &self.provider)
+            .field("api_key", &api_key)
+            .field("api_base", &self.api_base)
+            .field("model", &self.model)
+            .field("temperature", &self.temperature)
+            .field("max_tokens", &self.max_tokens)
+            .field("context_window", &self.context_window)
+            .field("context_policy", &self.context_policy)
+            .finish()
+    }
+}
+
+impl LLMConfig {
+    /// `model` 的上下文长度，配置中指定的值优先
+    pub fn context_window(&self, model: &str) -> usize {
+        self.context_window
+            .map(|size| size as usize)
+            .unwrap_or_else(|| tokens::context_window(model))
+    }
+}
+
+/// 单个会话对接口配置的覆盖，未设置的项使用配置档案中的值
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConfigOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_output_hides_the_api_key() {
+        let config = LLMConfig {
+            provider: ProviderKind::OpenAI,
+            api_key: "sk-secret".to_string(),
+            api_base: "https://api.openai.com/v1".to_string(),
+            model: "gpt-4o".to_string(),
             temperature: 0.7,
             max_tokens: 1000,
-        }
+            context_window: None,
+            context_policy: ContextPolicy::default(),
+        };
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("sk-secret"), "{}", debug);
+        assert!(debug.contains("<redacted>") && debug.contains("gpt-4o"));
     }
 }
@@ -0,0 +1,216 @@
+use super::message::{Message, MessageContent, Role};
+use super::tokens::{self, Tokenizer, TOKENS_PER_REPLY};
+use serde::{Deserialize, Serialize};
+
+/// 会话超出模型上下文长度时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextPolicy {
+    /// 从最早的消息开始丢弃，包括系统提示词
+    DropOldest,
+    /// 保留系统提示词，丢弃最早的其他消息
+    #[default]
+    KeepSystem,
+    /// 保留系统提示词，较早的消息在后台总结后保存在会话中
+    Summarize,
+}
+
+impl ContextPolicy {
+    pub const ALL: [ContextPolicy; 3] = [Self::DropOldest, Self::KeepSystem, Self::Summarize];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::DropOldest => "Drop oldest messages",
+            Self::KeepSystem => "Keep system prompt",
+            Self::Summarize => "Summarize earlier messages",
+        }
+    }
+}
+
+/// 裁剪后的消息和被丢弃的消息
+pub struct Fitted {
+    pub messages: Vec<Message>,
+    pub dropped: Vec<Message>,
+}
+
+/// 丢弃最早的消息，直到剩下的消息加上回复预留的 `max_tokens` 能放进上下文
+///
+/// 最后一条消息总是保留；`keep_system` 为真时系统消息也会保留。
+pub fn fit(
+    messages: Vec<Message>,
+    tokenizer: &dyn Tokenizer,
+    context_window: usize,
+    max_tokens: usize,
+    keep_system: bool,
+) -> Fitted {
+    let budget = context_window.saturating_sub(max_tokens + TOKENS_PER_REPLY);
+    let counts: Vec<usize> = messages
+        .iter()
+        .map(|message| tokens::count_message(tokenizer, message))
+        .collect();
+    let mut total: usize = counts.iter().sum();
+
+    let last = messages.len().saturating_sub(1);
+    let mut keep = vec![true; messages.len()];
+    let mut dropping_tools = false;
+    for index in 0..last {
+        let is_system = matches!(messages[index].role, Role::System);
+        if keep_system && is_system {
+            continue;
+        }
+
+        // 工具结果必须跟在对应的调用后面，调用被丢弃时结果也一起丢弃
+        let is_tool = matches!(
+            messages[index].content,
+            MessageContent::Function { .. } | MessageContent::ToolResult { .. }
+        );
+        if total <= budget && !(dropping_tools && is_tool) {
+            break;
+        }
+
+        keep[index] = false;
+        total -= counts[index];
+        dropping_tools = true;
+    }
+
+    let mut fitted = Fitted {
+        messages: Vec::new(),
+        dropped: Vec::new(),
+    };
+    for (message, keep) in messages.into_iter().zip(keep) {
+        if keep {
+            fitted.messages.push(message);
+        } else {
+            fitted.dropped.push(message);
+        }
+    }
+    fitted
+}
+
+/// 总结较早消息时使用的请求
+pub fn summary_request(dropped: &[Message]) -> Vec<Message> {
+    let transcript = dropped
+        .iter()
+        .filter_map(|message| match &message.content {
+            MessageContent::Text(text) | MessageContent::Image { text, .. } => {
+                Some(format!("{:?}: {}", message.role, text))
+            }
+            MessageContent::Summary { text, .. } => Some(format!("Earlier summary: {}", text)),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    vec![
+        Message::new(
+            Role::System,
+            MessageContent::Text(
+                "Summarize the following conversation in a few sentences. Keep names, \
+                 numbers, decisions and open questions."
+                    .to_string(),
+            ),
+        ),
+        Message::new(Role::User, MessageContent::Text(transcript)),
+    ]
+}
+
+/// 发送给模型的总结消息
+pub fn summary_message(summary: &str) -> Message {
+    Message::new(
+        Role::System,
+        MessageContent::Text(format!("Summary of the earlier conversation:\n{}", summary)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens::Words;
+    use super::*;
+
+    /// 每条消息 10 个 token（6 个单词加上固定开销）
+    fn message(role: Role, name: &str) -> Message {
+        Message::new(role, MessageContent::Text(format!("{} x x x x x", name)))
+    }
+
+    fn names(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| match &message.content {
+                MessageContent::Text(text) => text.split(' ').next().unwrap(),
+                MessageContent::Function { .. } => "call",
+                MessageContent::ToolResult { .. } => "result",
+                _ => "other",
+            })
+            .collect()
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            message(Role::System, "system"),
+            message(Role::User, "one"),
+            message(Role::Assistant, "two"),
+            message(Role::User, "three"),
+            message(Role::Assistant, "four"),
+        ]
+    }
+
+    /// 剩下 `budget` 个 token 的上下文长度
+    fn window(budget: usize, max_tokens: usize) -> usize {
+        budget + max_tokens + TOKENS_PER_REPLY
+    }
+
+    #[test]
+    fn keeps_everything_that_fits() {
+        let fitted = fit(conversation(), &Words, window(50, 100), 100, false);
+        assert_eq!(fitted.messages.len(), 5);
+        assert!(fitted.dropped.is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_messages() {
+        let fitted = fit(conversation(), &Words, window(30, 10), 10, false);
+        assert_eq!(names(&fitted.messages), ["two", "three", "four"]);
+        assert_eq!(names(&fitted.dropped), ["system", "one"]);
+
+        let fitted = fit(conversation(), &Words, window(30, 10), 10, true);
+        assert_eq!(names(&fitted.messages), ["system", "three", "four"]);
+        assert_eq!(names(&fitted.dropped), ["one", "two"]);
+    }
+
+    #[test]
+    fn always_keeps_the_last_message() {
+        let fitted = fit(conversation(), &Words, 0, 1000, true);
+        assert_eq!(names(&fitted.messages), ["system", "four"]);
+
+        let fitted = fit(Vec::new(), &Words, 0, 0, false);
+        assert!(fitted.messages.is_empty() && fitted.dropped.is_empty());
+    }
+
+    #[test]
+    fn drops_tool_results_with_their_call() {
+        let messages = vec![
+            message(Role::User, "question"),
+            Message::new(
+                Role::Assistant,
+                MessageContent::Function {
+                    id: "1".to_string(),
+                    name: "call".to_string(),
+                    arguments: serde_json::json!({}),
+                },
+            ),
+            Message::new(
+                Role::Tool,
+                MessageContent::ToolResult {
+                    call_id: "1".to_string(),
+                    name: "call".to_string(),
+                    content: "x x x x x x".to_string(),
+                },
+            ),
+            message(Role::Assistant, "answer"),
+        ];
+        // 丢弃调用后已经放得下，结果仍然一起丢弃
+        let fitted = fit(messages, &Words, window(24, 0), 0, false);
+        assert_eq!(names(&fitted.messages), ["answer"]);
+        assert_eq!(names(&fitted.dropped), ["question", "call", "result"]);
+    }
+}
@@ -0,0 +1,369 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::fmt;
+use std::time::Duration;
+
+/// 调用模型接口时的错误，按用户能采取的措施分类
+#[derive(Debug, Clone)]
+pub enum LLMError {
+    /// API key 无效或没有权限
+    Auth(String),
+    /// 请求过于频繁，`retry_after` 来自响应头
+    RateLimit {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// 额度或余额用完，重试没有意义
+    Quota(String),
+    /// 请求超出了模型的上下文长度
+    ContextLength(String),
+    /// 服务端错误或过载
+    Server { status: u16, message: String },
+    /// 连接失败、超时或响应中途断开
+    Network(String),
+    /// 请求或回复被内容安全策略拦截
+    ContentFilter(String),
+    /// 其他错误，例如请求参数无效
+    Other(String),
+}
+
+impl LLMError {
+    /// 根据失败的 HTTP 响应分类
+    pub fn from_response(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let message = error_message(body);
+        let lower = body.to_lowercase();
+
+        // 先按状态码分类：429 的响应体里常有 "tokens" 等字样（每分钟 token 限额），
+        // 不能当作上下文超长
+        match status.as_u16() {
+            401 | 403 => Self::Auth(message),
+            402 => Self::Quota(message),
+            429 if lower.contains("insufficient_quota") || lower.contains("billing") => {
+                Self::Quota(message)
+            }
+            429 => Self::RateLimit {
+                retry_after: retry_after(headers),
+                message,
+            },
+            408 => Self::Network(message),
+            400 | 413 if is_context_length(&lower) => Self::ContextLength(message),
+            400..=499 if is_content_filter(&lower) => Self::ContentFilter(message),
+            // 529 是 Anthropic 的过载状态码
+            status @ (500..=599) => Self::Server { status, message },
+            _ => Self::Other(format!("HTTP {}: {}", status, message)),
+        }
+    }
+
+    /// 根据流中的错误事件分类，`kind` 是事件中的错误类型
+    pub fn from_event(kind: &str, message: String) -> Self {
+        let lower = message.to_lowercase();
+        match kind {
+            "authentication_error" | "permission_error" | "invalid_api_key" => Self::Auth(message),
+            "rate_limit_error" | "rate_limit_exceeded" => Self::RateLimit {
+                retry_after: None,
+                message,
+            },
+            "insufficient_quota" => Self::Quota(message),
+            "overloaded_error" => Self::Server {
+                status: 529,
+                message,
+            },
+            "api_error" | "server_error" => Self::Server {
+                status: 500,
+                message,
+            },
+            _ if is_context_length(&lower) => Self::ContextLength(message),
+            _ if is_content_filter(&lower) => Self::ContentFilter(message),
+            _ => Self::Other(message),
+        }
+    }
+
+    /// 把任意错误归类，无法识别的错误归为 `Other`
+    pub fn classify(error: &anyhow::Error) -> Self {
+        if let Some(error) = error.downcast_ref::<LLMError>() {
+            return error.clone();
+        }
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            return Self::from_reqwest(error);
+        }
+        Self::Other(error.to_string())
+    }
+
+    fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_timeout() || error.is_connect() || error.is_request() || error.is_body() {
+            Self::Network(error.to_string())
+        } else {
+            Self::Other(error.to_string())
+        }
+    }
+
+    /// 可以自动重试的临时错误
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimit { .. } | Self::Server { .. } | Self::Network(_)
+        )
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimit { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// 需要修改设置才能解决的错误
+    pub fn needs_settings(&self) -> bool {
+        matches!(
+            self,
+            Self::Auth(_) | Self::Quota(_) | Self::ContextLength(_)
+        )
+    }
+
+    /// 显示给用户的提示，说明可以怎么处理
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::Auth(_) => "API key rejected — open Settings to check the key and API base",
+            Self::RateLimit { .. } => "Rate limited by the provider — wait a moment and try again",
+            Self::Quota(_) => "Quota or credit exhausted — check billing or switch profile",
+            Self::ContextLength(_) => {
+                "Conversation is too long for this model — start a new chat or \
+                 set the context policy in Settings"
+            }
+            Self::Server { .. } => "The provider is having problems — try again later",
+            Self::Network(_) => "Could not reach the provider — check your connection",
+            Self::ContentFilter(_) => {
+                "Blocked by the provider's content filter — rephrase the message"
+            }
+            Self::Other(_) => "Request failed",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Auth(message)
+            | Self::RateLimit { message, .. }
+            | Self::Quota(message)
+            | Self::ContextLength(message)
+            | Self::Server { message, .. }
+            | Self::Network(message)
+            | Self::ContentFilter(message)
+            | Self::Other(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for LLMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server { status, message } => {
+                write!(f, "{} (HTTP {}): {}", self.hint(), status, message)
+            }
+            _ => write!(f, "{}: {}", self.hint(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for LLMError {}
+
+/// 从常见的错误响应格式中取出说明文字，无法解析时使用整个响应体
+fn error_message(body: &str) -> String {
+    let value: serde_json::Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(_) => return body.trim().to_string(),
+    };
+    let message = [
+        &value["error"]["message"],
+        &value["error"],
+        &value["message"],
+        &value["detail"],
+    ]
+    .into_iter()
+    .find_map(|value| value.as_str())
+    .map_or_else(|| body.trim().to_string(), str::to_string);
+    message
+}
+
+/// 只匹配各家接口报告上下文超长时的固定说法，避免误判其他与 token 有关的错误
+fn is_context_length(lower: &str) -> bool {
+    [
+        "context_length_exceeded",
+        "maximum context length",
+        "exceeds the context window",
+        "prompt is too long",
+    ]
+    .iter()
+    .any(|pattern| lower.contains(pattern))
+}
+
+fn is_content_filter(lower: &str) -> bool {
+    [
+        "content_filter",
+        "content_policy_violation",
+        "content management policy",
+    ]
+    .iter()
+    .any(|pattern| lower.contains(pattern))
+}
+
+/// `Retry-After` 响应头，只支持秒数
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn response(status: u16, body: &str) -> LLMError {
+        let status = StatusCode::from_u16(status).unwrap();
+        LLMError::from_response(status, &HeaderMap::new(), body)
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn classifies_responses_by_status() {
+        assert!(matches!(response(401, "bad key"), LLMError::Auth(m) if m == "bad key"));
+        assert!(matches!(response(403, ""), LLMError::Auth(_)));
+        assert!(matches!(response(402, ""), LLMError::Quota(_)));
+        assert!(matches!(response(408, ""), LLMError::Network(_)));
+        assert!(matches!(
+            response(503, "busy"),
+            LLMError::Server { status: 503, .. }
+        ));
+        assert!(matches!(
+            response(529, "overloaded"),
+            LLMError::Server { status: 529, .. }
+        ));
+        assert!(
+            matches!(response(418, "teapot"), LLMError::Other(m) if m == "HTTP 418 I'm a teapot: teapot")
+        );
+    }
+
+    #[test]
+    fn classifies_responses_by_body() {
+        let quota = r#"{"error": {"message": "Out of credit", "type": "insufficient_quota"}}"#;
+        assert!(matches!(response(429, quota), LLMError::Quota(m) if m == "Out of credit"));
+
+        let context = r#"{"error": {"code": "context_length_exceeded", "message": "Too long"}}"#;
+        assert!(matches!(response(400, context), LLMError::ContextLength(m) if m == "Too long"));
+
+        let too_large = "Prompt is too long: 210000 tokens > 200000 maximum";
+        assert!(matches!(
+            response(413, too_large),
+            LLMError::ContextLength(_)
+        ));
+        // 其他状态码不检查上下文长度
+        assert!(matches!(
+            response(500, "maximum context length"),
+            LLMError::Server { .. }
+        ));
+
+        let filter = r#"{"error": {"code": "content_filter", "message": "Blocked"}}"#;
+        assert!(matches!(response(400, filter), LLMError::ContentFilter(_)));
+
+        // 常见格式以外的响应体原样作为说明
+        assert_eq!(
+            response(401, r#"{"detail": "Bad model"}"#).message(),
+            "Bad model"
+        );
+        assert_eq!(response(401, " <html> \n").message(), "<html>");
+        assert_eq!(
+            response(401, r#"{"error": 1}"#).message(),
+            r#"{"error": 1}"#
+        );
+    }
+
+    #[test]
+    fn reads_retry_after_seconds() {
+        let error = LLMError::from_response(StatusCode::TOO_MANY_REQUESTS, &headers(" 1.5 "), "");
+        assert!(error.is_transient());
+        assert_eq!(error.retry_after(), Some(Duration::from_millis(1500)));
+
+        for value in ["-1", "NaN", "inf", "Wed, 21 Oct 2015 07:28:00 GMT", ""] {
+            assert_eq!(retry_after(&headers(value)), None, "{:?}", value);
+        }
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(response(503, "").retry_after(), None);
+    }
+
+    #[test]
+    fn token_rate_limits_are_retryable() {
+        // OpenAI 超出每分钟 token 限额时的响应
+        let body = r#"{
+            "error": {
+                "message": "Rate limit reached for gpt-4o in organization org-abc on tokens per min (TPM): Limit 30000, Used 27512, Requested 4096. Please try again in 3.216s. Visit https://platform.openai.com/account/rate-limits to learn more.",
+                "type": "tokens",
+                "param": null,
+                "code": "rate_limit_exceeded"
+            }
+        }"#;
+        let error = LLMError::from_response(StatusCode::TOO_MANY_REQUESTS, &headers("4"), body);
+        assert!(
+            matches!(&error, LLMError::RateLimit { message, .. } if message.starts_with("Rate limit reached")),
+            "{:?}",
+            error
+        );
+        assert!(error.is_transient());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(4)));
+
+        // 即使说明里提到上下文长度，429 也按限流处理
+        let body = "Too many tokens: this request exceeds the context window rate limit";
+        assert!(matches!(response(429, body), LLMError::RateLimit { .. }));
+        assert!(matches!(
+            LLMError::from_event("rate_limit_exceeded", body.to_string()),
+            LLMError::RateLimit { .. }
+        ));
+    }
+
+    #[test]
+    fn classifies_stream_events() {
+        let event = |kind: &str, message: &str| LLMError::from_event(kind, message.to_string());
+        assert!(matches!(
+            event("authentication_error", ""),
+            LLMError::Auth(_)
+        ));
+        assert!(matches!(
+            event("rate_limit_error", ""),
+            LLMError::RateLimit {
+                retry_after: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            event("insufficient_quota", ""),
+            LLMError::Quota(_)
+        ));
+        assert!(matches!(
+            event("overloaded_error", ""),
+            LLMError::Server { status: 529, .. }
+        ));
+        assert!(matches!(
+            event("api_error", ""),
+            LLMError::Server { status: 500, .. }
+        ));
+        assert!(matches!(
+            event("invalid_request_error", "Prompt is too long"),
+            LLMError::ContextLength(_)
+        ));
+        assert!(matches!(
+            event("", "Output blocked by content_filter"),
+            LLMError::ContentFilter(_)
+        ));
+        assert!(matches!(event("unknown", "Oops"), LLMError::Other(m) if m == "Oops"));
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::path::Path;
+
+/// 读取本地图片文件并编码为 base64 data URL
+pub fn data_url_from_file(path: &Path) -> Result<String> {
+    let mime = mime_from_path(path)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported image type: {}", path.display()))?;
+    let bytes = std::fs::read(path)?;
+    Ok(format!("data:{};base64,{}", mime, STANDARD.encode(bytes)))
+}
+
+/// 解码 base64 data URL，返回图片的原始字节
+pub fn decode_data_url(url: &str) -> Option<Vec<u8>> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    if !header.ends_with(";base64") {
+        return None;
+    }
+    STANDARD.decode(data).ok()
+}
+
+/// data URL 中的图片类型，例如 `image/png`
+pub fn data_url_media_type(url: &str) -> Option<&str> {
+    let (header, _) = url.strip_prefix("data:")?.split_once(',')?;
+    header.split(';').next().filter(|mime| !mime.is_empty())
+}
+
+fn mime_from_path(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_media_type_of_data_urls() {
+        assert_eq!(
+            data_url_media_type("data:image/png;base64,iVBORw0K"),
+            Some("image/png")
+        );
+        assert_eq!(data_url_media_type("data:;base64,AAAA"), None);
+        assert_eq!(data_url_media_type("https://example.com/cat.png"), None);
+    }
+}
@@ -1,11 +1,14 @@
+use super::error::LLMError;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Role {
     System,
     User,
     Assistant,
+    Tool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,9 +19,22 @@ pub enum MessageContent {
         url: String,
     },
     Function {
+        #[serde(default)]
+        id: String,
         name: String,
         arguments: serde_json::Value,
     },
+    ToolResult {
+        call_id: String,
+        name: String,
+        content: String,
+    },
+    /// 较早消息的总结，发送请求时代替这些消息
+    Summary {
+        text: String,
+        /// 被总结的原始消息
+        original: Vec<Message>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,12 +42,45 @@ pub struct Message {
     pub role: Role,
     pub content: MessageContent,
     pub timestamp: DateTime<Utc>,
+    /// 回复被用户中途停止，内容不完整
+    #[serde(default)]
+    pub stopped: bool,
+    /// 生成这条回复消耗的 token，后端没有返回时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// 一次回复的 token 用量，包括其中所有工具调用轮次
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl Message {
+    pub fn new(role: Role, content: MessageContent) -> Self {
+        Self {
+            role,
+            content,
+            timestamp: Utc::now(),
+            stopped: false,
+            usage: None,
+        }
+    }
 }
 
 // 新增：流式消息类型
 #[derive(Debug, Clone)]
 pub enum StreamMessage {
     Chunk(String),         // 部分响应
+    Intermediate(Message), // 工具调用过程中产生的消息
     Done(Message),         // 完整消息
-    Error(String),         // 错误信息
+    Error(LLMError),       // 错误信息
+    /// 遇到临时错误，等待 `delay` 后重新请求这一轮，已经收到的文字作废
+    Retrying {
+        error: LLMError,
+        attempt: u32,
+        delay: Duration,
+    },
 }
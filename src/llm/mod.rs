@@ -1,7 +1,17 @@
 pub mod client;
 pub mod config;
+pub mod context;
+pub mod error;
+pub mod image;
 pub mod message;
+pub mod models;
+pub mod provider;
+pub mod tokens;
+pub mod tools;
 
-pub use client::LLMClient;
-pub use config::LLMConfig;
-pub use message::{Message, MessageContent, Role, StreamMessage}; 
\ No newline at end of file
+pub use client::{Completion, LLMClient};
+pub use config::{ConfigOverrides, LLMConfig};
+pub use error::LLMError;
+pub use message::{Message, MessageContent, Role, StreamMessage, Usage};
+pub use provider::ProviderKind;
+pub use tools::ToolRegistry;
@@ -0,0 +1,103 @@
+use super::provider::{ModelInfo, ProviderKind};
+use crate::config::Profile;
+use crate::fs::write_atomic;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// 缓存超过这个时间后在下次打开设置时重新获取
+const MAX_AGE_HOURS: i64 = 24;
+
+/// 一个配置档案从接口获取到的模型列表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedModels {
+    /// 获取列表时的后端和地址，修改后缓存不再适用
+    pub provider: ProviderKind,
+    pub api_base: String,
+    pub fetched_at: DateTime<Utc>,
+    pub models: Vec<ModelInfo>,
+}
+
+impl CachedModels {
+    pub fn is_stale(&self) -> bool {
+        Utc::now() - self.fetched_at > Duration::hours(MAX_AGE_HOURS)
+    }
+
+    pub fn find(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|model| model.id == id)
+    }
+}
+
+/// 按配置档案名称缓存的模型列表，保存在一个 JSON 文件中
+#[derive(Default)]
+pub struct ModelCache {
+    path: Option<PathBuf>,
+    entries: HashMap<String, CachedModels>,
+}
+
+impl ModelCache {
+    /// 当前用户的默认缓存文件，例如 `~/.local/share/llm-client/models.json`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("llm-client").join("models.json"))
+    }
+
+    /// 读取缓存文件，文件损坏时从空缓存开始
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?).unwrap_or_else(|e| {
+                warn!(?e, path = %path.display(), "Ignoring unreadable model cache");
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// `profile` 的模型列表；后端或地址改变后旧的列表不再返回
+    pub fn get(&self, profile: &Profile) -> Option<&CachedModels> {
+        self.entries.get(&profile.name).filter(|cached| {
+            cached.provider == profile.provider && cached.api_base == profile.api_base
+        })
+    }
+
+    /// `profile` 已知的模型列表，没有缓存时为空
+    pub fn models(&self, profile: &Profile) -> &[ModelInfo] {
+        self.get(profile).map_or(&[], |cached| &cached.models)
+    }
+
+    /// 记录新获取的列表并写回文件
+    pub fn insert(&mut self, profile: &Profile, mut models: Vec<ModelInfo>) -> Result<()> {
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        self.entries.insert(
+            profile.name.clone(),
+            CachedModels {
+                provider: profile.provider,
+                api_base: profile.api_base.clone(),
+                fetched_at: Utc::now(),
+                models,
+            },
+        );
+        self.save()
+    }
+
+    /// 原子地写入缓存文件
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        write_atomic(path, &serde_json::to_vec_pretty(&self.entries)?)
+    }
+}
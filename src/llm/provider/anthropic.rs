@@ -0,0 +1,469 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use super::{
+    check_response, http_client, Capabilities, ChatEvent, ChatProvider, ChatRequest, ChatStream,
+    ModelInfo, ProviderKind,
+};
+use crate::llm::{
+    config::LLMConfig,
+    error::LLMError,
+    message::{Message, MessageContent, Role},
+};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic 风格的 Messages API 后端
+pub struct AnthropicProvider {
+    http: reqwest::Client,
+    api_base: String,
+    api_key: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: &LLMConfig) -> Self {
+        Self {
+            http: http_client(),
+            api_base: config.api_base.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.api_base, path))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+}
+
+#[async_trait]
+impl ChatProvider for AnthropicProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Anthropic
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            vision: true,
+            model_listing: true,
+        }
+    }
+
+    async fn stream_chat(&self, request: ChatRequest) -> Result<ChatStream> {
+        let (system, messages) = to_anthropic_messages(&request.messages);
+
+        let mut body = json!({
+            "model": request.model,
+            "max_tokens": request.max_tokens,
+            "temperature": request.temperature,
+            "messages": messages,
+            "stream": true,
+        });
+        if !system.is_empty() {
+            body["system"] = Value::String(system);
+        }
+        if !request.tools.is_empty() {
+            body["tools"] = request
+                .tools
+                .into_iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "input_schema": tool.parameters,
+                    })
+                })
+                .collect();
+        }
+
+        let response = self
+            .request(reqwest::Method::POST, "/messages")
+            .json(&body)
+            .send()
+            .await?;
+        let response = check_response(response).await?;
+
+        Ok(response
+            .bytes_stream()
+            .eventsource()
+            .filter_map(|event| async move {
+                match event {
+                    Ok(event) => parse_event(&event.data).transpose(),
+                    Err(e) => Some(Err(LLMError::Network(format!("Stream error: {}", e)).into())),
+                }
+            })
+            .boxed())
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let response = self.request(reqwest::Method::GET, "/models").send().await?;
+        let body: Value = check_response(response).await?.json().await?;
+
+        Ok(body["data"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| {
+                        let mut info = ModelInfo::new(model["id"].as_str()?);
+                        info.context_window =
+                            model["max_input_tokens"].as_u64().map(|size| size as u32);
+                        Some(info)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// 解析一条 SSE 事件，不关心的事件返回 `None`
+fn parse_event(data: &str) -> Result<Option<ChatEvent>> {
+    let event: Value = serde_json::from_str(data)?;
+    let index = event["index"].as_u64().unwrap_or(0) as usize;
+
+    match event["type"].as_str() {
+        Some("content_block_start") if event["content_block"]["type"] == "tool_use" => {
+            let block = &event["content_block"];
+            Ok(Some(ChatEvent::ToolCall {
+                index,
+                id: block["id"].as_str().map(str::to_string),
+                name: block["name"].as_str().map(str::to_string),
+                arguments: None,
+            }))
+        }
+        Some("content_block_delta") => {
+            let delta = &event["delta"];
+            match delta["type"].as_str() {
+                Some("text_delta") => Ok(delta["text"]
+                    .as_str()
+                    .map(|text| ChatEvent::Content(text.to_string()))),
+                Some("input_json_delta") => Ok(Some(ChatEvent::ToolCall {
+                    index,
+                    id: None,
+                    name: None,
+                    arguments: delta["partial_json"].as_str().map(str::to_string),
+                })),
+                _ => Ok(None),
+            }
+        }
+        // 输入的 token 数在开始时给出，输出的 token 数在结束时给出
+        Some("message_start") => Ok(Some(ChatEvent::Usage {
+            prompt_tokens: token_count(&event["message"]["usage"]["input_tokens"]),
+            completion_tokens: 0,
+        })),
+        Some("message_delta") => Ok(Some(ChatEvent::Usage {
+            prompt_tokens: 0,
+            completion_tokens: token_count(&event["usage"]["output_tokens"]),
+        })),
+        Some("error") => Err(LLMError::from_event(
+            event["error"]["type"].as_str().unwrap_or_default(),
+            event["error"]["message"]
+                .as_str()
+                .unwrap_or(data)
+                .to_string(),
+        )
+        .into()),
+        other => {
+            debug!(event = ?other, "Ignoring stream event");
+            Ok(None)
+        }
+    }
+}
+
+fn token_count(value: &Value) -> u32 {
+    value.as_u64().unwrap_or(0) as u32
+}
+
+/// 转换为 Messages API 的格式
+///
+/// 系统消息单独作为 `system` 参数，相邻的同角色消息合并为一条，
+/// 工具结果作为 user 消息中的 `tool_result` 内容块发送。
+fn to_anthropic_messages(history: &[Message]) -> (String, Vec<Value>) {
+    let mut system = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for message in history {
+        let (role, blocks) = match (&message.role, &message.content) {
+            (Role::System, content) => {
+                system.push(content_text(content));
+                continue;
+            }
+            (
+                _,
+                MessageContent::Function {
+                    id,
+                    name,
+                    arguments,
+                },
+            ) => (
+                "assistant",
+                vec![json!({
+                    "type": "tool_use",
+                    "id": id,
+                    "name": name,
+                    "input": tool_input(arguments),
+                })],
+            ),
+            (
+                _,
+                MessageContent::ToolResult {
+                    call_id, content, ..
+                },
+            ) => (
+                "user",
+                vec![json!({ "type": "tool_result", "tool_use_id": call_id, "content": content })],
+            ),
+            (Role::User, MessageContent::Image { text, url }) => {
+                let mut blocks = vec![image_block(url)];
+                if !text.is_empty() {
+                    blocks.push(json!({ "type": "text", "text": text }));
+                }
+                ("user", blocks)
+            }
+            (Role::Assistant, content) => (
+                "assistant",
+                vec![json!({ "type": "text", "text": content_text(content) })],
+            ),
+            (_, content) => (
+                "user",
+                vec![json!({ "type": "text", "text": content_text(content) })],
+            ),
+        };
+
+        match messages.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(content) = last["content"].as_array_mut() {
+                    content.extend(blocks);
+                }
+            }
+            _ => messages.push(json!({ "role": role, "content": blocks })),
+        }
+    }
+
+    (system.join("\n\n"), messages)
+}
+
+/// `tool_use` 的 `input` 必须是对象；模型给出的参数不是合法 JSON 时会保存为原始字符串，
+/// 能解析成对象就用解析结果，否则发送空对象
+fn tool_input(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(_) => arguments.clone(),
+        Value::String(raw) => match serde_json::from_str(raw) {
+            Ok(value @ Value::Object(_)) => value,
+            _ => json!({}),
+        },
+        _ => json!({}),
+    }
+}
+
+fn content_text(content: &MessageContent) -> String {
+    match content {
+        MessageContent::Text(text) | MessageContent::Summary { text, .. } => text.clone(),
+        MessageContent::Image { text, .. } => text.clone(),
+        MessageContent::Function {
+            name, arguments, ..
+        } => format!("Function call: {} with args: {}", name, arguments),
+        MessageContent::ToolResult { content, .. } => content.clone(),
+    }
+}
+
+fn image_block(url: &str) -> Value {
+    let data_url = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"));
+
+    match data_url {
+        Some((media_type, data)) => json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": media_type, "data": data },
+        }),
+        None => json!({
+            "type": "image",
+            "source": { "type": "url", "url": url },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, text: &str) -> Message {
+        Message::new(role, MessageContent::Text(text.to_string()))
+    }
+
+    fn function(arguments: Value) -> Message {
+        Message::new(
+            Role::Assistant,
+            MessageContent::Function {
+                id: "call_1".to_string(),
+                name: "search".to_string(),
+                arguments,
+            },
+        )
+    }
+
+    #[test]
+    fn tool_use_input_is_always_an_object() {
+        let input = |arguments: Value| {
+            let (_, messages) = to_anthropic_messages(&[function(arguments)]);
+            messages[0]["content"][0]["input"].clone()
+        };
+        assert_eq!(input(json!({ "q": "rust" })), json!({ "q": "rust" }));
+        // 解析失败时保存的原始字符串
+        assert_eq!(input(json!(r#"{"q": "rust"}"#)), json!({ "q": "rust" }));
+        assert_eq!(input(json!(r#"{"q": "ru"#)), json!({}));
+        assert_eq!(input(json!("[1, 2]")), json!({}));
+        assert_eq!(input(Value::Null), json!({}));
+    }
+
+    #[test]
+    fn parses_text_and_usage_events() {
+        let start = r#"{"type": "message_start", "message": {"usage": {"input_tokens": 25, "output_tokens": 1}}}"#;
+        assert!(matches!(
+            parse_event(start).unwrap(),
+            Some(ChatEvent::Usage {
+                prompt_tokens: 25,
+                completion_tokens: 0
+            })
+        ));
+
+        let delta = r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}}"#;
+        assert!(
+            matches!(parse_event(delta).unwrap(), Some(ChatEvent::Content(text)) if text == "Hello")
+        );
+
+        let end = r#"{"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 15}}"#;
+        assert!(matches!(
+            parse_event(end).unwrap(),
+            Some(ChatEvent::Usage {
+                prompt_tokens: 0,
+                completion_tokens: 15
+            })
+        ));
+
+        for ignored in [
+            r#"{"type": "ping"}"#,
+            r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}"#,
+            r#"{"type": "content_block_stop", "index": 0}"#,
+            r#"{"type": "message_stop"}"#,
+        ] {
+            assert!(parse_event(ignored).unwrap().is_none(), "{}", ignored);
+        }
+        assert!(parse_event("not json").is_err());
+    }
+
+    #[test]
+    fn parses_tool_use_deltas() {
+        let start = r#"{"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {}}}"#;
+        assert!(matches!(
+            parse_event(start).unwrap(),
+            Some(ChatEvent::ToolCall { index: 1, id: Some(id), name: Some(name), arguments: None })
+                if id == "toolu_01" && name == "get_weather"
+        ));
+
+        let delta = r#"{"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"city\": \"Pa"}}"#;
+        assert!(matches!(
+            parse_event(delta).unwrap(),
+            Some(ChatEvent::ToolCall { index: 1, id: None, name: None, arguments: Some(arguments) })
+                if arguments == r#"{"city": "Pa"#
+        ));
+    }
+
+    #[test]
+    fn stream_errors_are_classified() {
+        let error =
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#;
+        let error = parse_event(error).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LLMError>(),
+            Some(LLMError::Server { status: 529, message }) if message == "Overloaded"
+        ));
+    }
+
+    #[test]
+    fn hoists_system_messages_and_merges_roles() {
+        let history = [
+            message(Role::System, "Be brief."),
+            message(Role::User, "Hi"),
+            Message::new(
+                Role::System,
+                MessageContent::Summary {
+                    text: "Earlier: greetings".to_string(),
+                    original: Vec::new(),
+                },
+            ),
+            message(Role::User, "Weather?"),
+            message(Role::Assistant, "Checking."),
+            function(json!({ "city": "Paris" })),
+            Message::new(
+                Role::Tool,
+                MessageContent::ToolResult {
+                    call_id: "call_1".to_string(),
+                    name: "search".to_string(),
+                    content: "Sunny".to_string(),
+                },
+            ),
+            message(Role::Assistant, "It is sunny."),
+        ];
+        let (system, messages) = to_anthropic_messages(&history);
+
+        assert_eq!(system, "Be brief.\n\nEarlier: greetings");
+        let roles: Vec<&str> = messages
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["user", "assistant", "user", "assistant"]);
+
+        // 去掉系统消息后相邻的两条用户消息合并
+        assert_eq!(
+            messages[0]["content"],
+            json!([{ "type": "text", "text": "Hi" }, { "type": "text", "text": "Weather?" }])
+        );
+        assert_eq!(messages[1]["content"][1]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][1]["id"], "call_1");
+        assert_eq!(
+            messages[2]["content"],
+            json!([{ "type": "tool_result", "tool_use_id": "call_1", "content": "Sunny" }])
+        );
+    }
+
+    #[test]
+    fn converts_image_parts() {
+        let image = |text: &str, url: &str| {
+            Message::new(
+                Role::User,
+                MessageContent::Image {
+                    text: text.to_string(),
+                    url: url.to_string(),
+                },
+            )
+        };
+        let (_, messages) = to_anthropic_messages(&[
+            image("What is this?", "data:image/png;base64,iVBORw0K"),
+            image("", "https://example.com/cat.jpg"),
+        ]);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0]["content"],
+            json!([
+                {
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": "image/png", "data": "iVBORw0K" },
+                },
+                { "type": "text", "text": "What is this?" },
+                {
+                    "type": "image",
+                    "source": { "type": "url", "url": "https://example.com/cat.jpg" },
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{config::LLMConfig, error::LLMError, message::Message};
+
+pub mod anthropic;
+pub mod ollama;
+pub mod openai;
+
+pub use anthropic::AnthropicProvider;
+pub use ollama::OllamaProvider;
+pub use openai::OpenAIProvider;
+
+/// 建立连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// 两次读到数据之间的最长间隔，流式响应卡住时据此结束；
+/// 本地模型加载或长时间思考时第一个片段可能很慢，所以留得比较宽
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 后端接口的类型，决定使用哪个 `ChatProvider` 实现
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// OpenAI 兼容的 `/chat/completions` 接口（OpenAI、Mistral 以及大多数网关）
+    #[default]
+    OpenAI,
+    /// Anthropic 风格的 `/messages` 接口
+    Anthropic,
+    /// Ollama 的 `/api/chat` 接口
+    Ollama,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 3] = [Self::OpenAI, Self::Anthropic, Self::Ollama];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::OpenAI => "OpenAI compatible",
+            Self::Anthropic => "Anthropic",
+            Self::Ollama => "Ollama",
+        }
+    }
+}
+
+/// 后端支持的功能
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    pub tools: bool,
+    pub vision: bool,
+    pub model_listing: bool,
+}
+
+/// 后端提供的一个模型，元数据只在接口返回时才有
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    /// 上下文长度（token）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// 是否支持图片输入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<bool>,
+    /// 是否支持工具调用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<bool>,
+}
+
+impl ModelInfo {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            context_window: None,
+            vision: None,
+            tools: None,
+        }
+    }
+}
+
+/// 提供给模型的工具描述
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// 与具体后端无关的对话请求
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    pub messages: Vec<Message>,
+    pub tools: Vec<ToolSpec>,
+}
+
+/// 流式响应中的一个事件
+#[derive(Debug, Clone)]
+pub enum ChatEvent {
+    /// 回复文本片段
+    Content(String),
+    /// 工具调用片段，相同 `index` 的片段需要拼接起来
+    ToolCall {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: Option<String>,
+    },
+    /// token 用量，同一次响应中的多个事件需要相加
+    Usage {
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    },
+}
+
+pub type ChatStream = BoxStream<'static, Result<ChatEvent>>;
+
+/// 对话后端
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// 发送对话请求，返回流式事件
+    async fn stream_chat(&self, request: ChatRequest) -> Result<ChatStream>;
+
+    /// 列出后端可用的模型
+    async fn list_models(&self) -> Result<Vec<ModelInfo>>;
+}
+
+/// 根据配置创建对应的后端
+pub fn create_provider(config: &LLMConfig) -> Arc<dyn ChatProvider> {
+    match config.provider {
+        ProviderKind::OpenAI => Arc::new(OpenAIProvider::new(config)),
+        ProviderKind::Anthropic => Arc::new(AnthropicProvider::new(config)),
+        ProviderKind::Ollama => Arc::new(OllamaProvider::new(config)),
+    }
+}
+
+/// 用给定配置临时创建后端并列出模型，可用于检查连接是否正常
+pub async fn list_models(config: &LLMConfig) -> Result<Vec<ModelInfo>> {
+    let provider = create_provider(config);
+    if !provider.capabilities().model_listing {
+        return Err(anyhow::anyhow!(
+            "{} does not support listing models",
+            provider.kind().label()
+        ));
+    }
+    provider.list_models().await
+}
+
+/// 后端共用的 HTTP 客户端设置，超时后按网络错误处理并自动重试
+pub(crate) fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        // 和 `reqwest::Client::new` 一样，只有 TLS 后端无法初始化时才会失败
+        .expect("Failed to create the HTTP client")
+}
+
+/// 检查 HTTP 响应状态，失败时根据状态码、响应头和响应体区分错误类型
+pub(crate) async fn check_response(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let headers = response.headers().clone();
+    let body = response.text().await.unwrap_or_default();
+    Err(LLMError::from_response(status, &headers, &body).into())
+}
@@ -0,0 +1,376 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use super::{
+    check_response, http_client, Capabilities, ChatEvent, ChatProvider, ChatRequest, ChatStream,
+    ModelInfo, ProviderKind,
+};
+use crate::llm::{
+    config::LLMConfig,
+    error::LLMError,
+    message::{Message, MessageContent, Role},
+};
+
+/// Ollama 原生 `/api/chat` 后端，响应为逐行的 JSON（NDJSON）
+pub struct OllamaProvider {
+    http: reqwest::Client,
+    api_base: String,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &LLMConfig) -> Self {
+        Self {
+            http: http_client(),
+            api_base: config.api_base.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OllamaProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Ollama
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            vision: true,
+            model_listing: true,
+        }
+    }
+
+    async fn stream_chat(&self, request: ChatRequest) -> Result<ChatStream> {
+        let mut body = json!({
+            "model": request.model,
+            "messages": to_ollama_messages(&request.messages),
+            "stream": true,
+            "options": {
+                "temperature": request.temperature,
+                "num_predict": request.max_tokens,
+            },
+        });
+        if !request.tools.is_empty() {
+            body["tools"] = request
+                .tools
+                .into_iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        },
+                    })
+                })
+                .collect();
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/api/chat", self.api_base))
+            .json(&body)
+            .send()
+            .await?;
+        let response = check_response(response).await?;
+
+        // Ollama 一次返回完整的工具调用，按出现顺序编号
+        Ok(lines(response)
+            .scan(0usize, |next_index, line| {
+                let events = match line.and_then(|line| parse_line(&line, next_index)) {
+                    Ok(events) => events.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                futures::future::ready(Some(futures::stream::iter(events)))
+            })
+            .flatten()
+            .boxed())
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let response = self
+            .http
+            .get(format!("{}/api/tags", self.api_base))
+            .send()
+            .await?;
+        let body: Value = check_response(response).await?.json().await?;
+
+        Ok(body["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| model["name"].as_str())
+                    .map(ModelInfo::new)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// 按行切分响应体
+fn lines(response: reqwest::Response) -> BoxStream<'static, Result<String>> {
+    futures::stream::unfold(
+        (response.bytes_stream().boxed(), Vec::new()),
+        |(mut bytes, mut buffer)| async move {
+            loop {
+                if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=pos).collect();
+                    let line = String::from_utf8_lossy(&line).trim().to_string();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    return Some((Ok(line), (bytes, buffer)));
+                }
+
+                match bytes.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                    Some(Err(e)) => return Some((Err(e.into()), (bytes, buffer))),
+                    None if buffer.iter().any(|b| !b.is_ascii_whitespace()) => {
+                        let line = String::from_utf8_lossy(&buffer).trim().to_string();
+                        return Some((Ok(line), (bytes, Vec::new())));
+                    }
+                    None => return None,
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+fn parse_line(line: &str, next_index: &mut usize) -> Result<Vec<ChatEvent>> {
+    let chunk: Value = serde_json::from_str(line)?;
+    if let Some(error) = chunk["error"].as_str() {
+        return Err(LLMError::from_event("", error.to_string()).into());
+    }
+
+    let message = &chunk["message"];
+    let mut events = Vec::new();
+
+    if let Some(content) = message["content"].as_str().filter(|c| !c.is_empty()) {
+        events.push(ChatEvent::Content(content.to_string()));
+    }
+
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        let function = &call["function"];
+        events.push(ChatEvent::ToolCall {
+            index: *next_index,
+            id: Some(format!("call_{}", uuid::Uuid::new_v4().simple())),
+            name: function["name"].as_str().map(str::to_string),
+            arguments: Some(function["arguments"].to_string()),
+        });
+        *next_index += 1;
+    }
+
+    // 最后一行带有这次请求的 token 数
+    if chunk["done"].as_bool() == Some(true) {
+        events.push(ChatEvent::Usage {
+            prompt_tokens: chunk["prompt_eval_count"].as_u64().unwrap_or(0) as u32,
+            completion_tokens: chunk["eval_count"].as_u64().unwrap_or(0) as u32,
+        });
+    }
+
+    Ok(events)
+}
+
+fn to_ollama_messages(history: &[Message]) -> Vec<Value> {
+    let mut messages: Vec<Value> = Vec::new();
+
+    for message in history {
+        let role = match message.role {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        };
+
+        match &message.content {
+            MessageContent::Text(text) | MessageContent::Summary { text, .. } => {
+                messages.push(json!({ "role": role, "content": text }))
+            }
+            MessageContent::Image { text, url } => {
+                let mut value = json!({ "role": role, "content": text });
+                // Ollama 只接受 base64 图片数据
+                match url
+                    .strip_prefix("data:")
+                    .and_then(|rest| rest.split_once(";base64,"))
+                {
+                    Some((_, data)) => value["images"] = json!([data]),
+                    None => warn!(url, "Ollama does not support remote image URLs"),
+                }
+                messages.push(value);
+            }
+            MessageContent::Function {
+                name, arguments, ..
+            } => {
+                let call = json!({ "function": { "name": name, "arguments": arguments } });
+                match messages.last_mut() {
+                    Some(last) if last["role"] == "assistant" => {
+                        match last["tool_calls"].as_array_mut() {
+                            Some(calls) => calls.push(call),
+                            None => last["tool_calls"] = json!([call]),
+                        }
+                    }
+                    _ => messages.push(json!({
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [call],
+                    })),
+                }
+            }
+            MessageContent::ToolResult { content, .. } => {
+                messages.push(json!({ "role": "tool", "content": content }))
+            }
+        }
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, text: &str) -> Message {
+        Message::new(role, MessageContent::Text(text.to_string()))
+    }
+
+    /// 把响应体拆成给定的几段发送
+    fn response(chunks: &[&'static str]) -> reqwest::Response {
+        let chunks: Vec<Result<&'static str, std::io::Error>> =
+            chunks.iter().copied().map(Ok).collect();
+        let body = reqwest::Body::wrap_stream(futures::stream::iter(chunks));
+        axum::http::Response::new(body).into()
+    }
+
+    async fn lines_of(chunks: &[&'static str]) -> Vec<String> {
+        lines(response(chunks))
+            .map(|line| line.unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn splits_lines_across_chunks() {
+        let chunks = [
+            "{\"message\":{\"content\":\"He",
+            "llo\"}}\n{\"message\":",
+            "{\"content\":\"!\"}}\n\n",
+            "\r\n{\"done\":true}",
+        ];
+        assert_eq!(
+            lines_of(&chunks).await,
+            [
+                r#"{"message":{"content":"Hello"}}"#,
+                r#"{"message":{"content":"!"}}"#,
+                r#"{"done":true}"#,
+            ]
+        );
+        assert!(lines_of(&["\n", "  \n", " "]).await.is_empty());
+    }
+
+    #[test]
+    fn parses_content_tool_calls_and_usage() {
+        let mut next_index = 0;
+        let content = r#"{"message": {"role": "assistant", "content": "Hi"}, "done": false}"#;
+        let events = parse_line(content, &mut next_index).unwrap();
+        assert!(matches!(events.as_slice(), [ChatEvent::Content(text)] if text == "Hi"));
+
+        // 每个工具调用都是完整的，编号在整个响应中递增
+        let calls = r#"{"message": {"role": "assistant", "content": "", "tool_calls": [
+            {"function": {"name": "get_weather", "arguments": {"city": "Paris"}}},
+            {"function": {"name": "get_time", "arguments": {}}}
+        ]}, "done": false}"#;
+        let events = parse_line(calls, &mut next_index).unwrap();
+        assert_eq!(next_index, 2);
+        assert!(matches!(
+            events.as_slice(),
+            [
+                ChatEvent::ToolCall { index: 0, id: Some(first), name: Some(name), arguments: Some(arguments) },
+                ChatEvent::ToolCall { index: 1, id: Some(second), .. },
+            ] if name == "get_weather"
+                && arguments == r#"{"city":"Paris"}"#
+                && first.starts_with("call_")
+                && first != second
+        ));
+
+        let done = r#"{"message": {"role": "assistant", "content": ""}, "done": true, "prompt_eval_count": 26, "eval_count": 290}"#;
+        let events = parse_line(done, &mut next_index).unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [ChatEvent::Usage {
+                prompt_tokens: 26,
+                completion_tokens: 290
+            }]
+        ));
+    }
+
+    #[test]
+    fn stream_errors_are_reported() {
+        let error = parse_line(r#"{"error": "model 'llama9' not found"}"#, &mut 0).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LLMError>(),
+            Some(LLMError::Other(message)) if message == "model 'llama9' not found"
+        ));
+        assert!(parse_line("{\"message\":", &mut 0).is_err());
+    }
+
+    #[test]
+    fn converts_history() {
+        let history = [
+            message(Role::System, "Be brief."),
+            Message::new(
+                Role::User,
+                MessageContent::Image {
+                    text: "What is this?".to_string(),
+                    url: "data:image/png;base64,iVBORw0K".to_string(),
+                },
+            ),
+            Message::new(
+                Role::User,
+                MessageContent::Image {
+                    text: "And this?".to_string(),
+                    url: "https://example.com/cat.jpg".to_string(),
+                },
+            ),
+            message(Role::Assistant, "Checking."),
+            Message::new(
+                Role::Assistant,
+                MessageContent::Function {
+                    id: "call_1".to_string(),
+                    name: "search".to_string(),
+                    arguments: json!({ "q": "cat" }),
+                },
+            ),
+            Message::new(
+                Role::Tool,
+                MessageContent::ToolResult {
+                    call_id: "call_1".to_string(),
+                    name: "search".to_string(),
+                    content: "A cat".to_string(),
+                },
+            ),
+        ];
+
+        assert_eq!(
+            to_ollama_messages(&history),
+            [
+                json!({ "role": "system", "content": "Be brief." }),
+                json!({ "role": "user", "content": "What is this?", "images": ["iVBORw0K"] }),
+                // 不支持远程图片，只发送文字
+                json!({ "role": "user", "content": "And this?" }),
+                // 工具调用附在前一条助手消息上
+                json!({
+                    "role": "assistant",
+                    "content": "Checking.",
+                    "tool_calls": [{ "function": { "name": "search", "arguments": { "q": "cat" } } }],
+                }),
+                json!({ "role": "tool", "content": "A cat" }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::Result;
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
+    ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPartImageArgs,
+    ChatCompletionRequestMessageContentPartTextArgs, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+    ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions, ChatCompletionTool,
+    ChatCompletionToolType, CreateChatCompletionRequestArgs, CreateChatCompletionStreamResponse,
+    FinishReason, FunctionCall, FunctionObject, ImageDetail, ImageUrlArgs,
+};
+use async_trait::async_trait;
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use serde_json::Value;
+
+use super::{
+    check_response, http_client, Capabilities, ChatEvent, ChatProvider, ChatRequest, ChatStream,
+    ModelInfo, ProviderKind,
+};
+use crate::llm::{
+    config::LLMConfig,
+    error::LLMError,
+    message::{Message, MessageContent, Role},
+};
+
+/// OpenAI 兼容的 `/chat/completions` 后端
+///
+/// 请求和响应使用 async-openai 的类型，HTTP 请求自己发送，
+/// 这样失败时能拿到状态码、响应头和响应体用于区分错误类型。
+pub struct OpenAIProvider {
+    http: reqwest::Client,
+    api_base: String,
+    api_key: String,
+}
+
+impl OpenAIProvider {
+    pub fn new(config: &LLMConfig) -> Self {
+        Self {
+            http: http_client(),
+            api_base: config.api_base.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.api_base, path))
+            .bearer_auth(&self.api_key)
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OpenAIProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::OpenAI
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tools: true,
+            vision: true,
+            model_listing: true,
+        }
+    }
+
+    async fn stream_chat(&self, request: ChatRequest) -> Result<ChatStream> {
+        let mut args = CreateChatCompletionRequestArgs::default();
+        args.model(&request.model)
+            .temperature(request.temperature)
+            .max_tokens(request.max_tokens)
+            .messages(to_request_messages(&request.messages)?)
+            .stream(true)
+            // 最后一个片段会带上整个请求的 token 用量
+            .stream_options(ChatCompletionStreamOptions {
+                include_usage: true,
+            });
+
+        if !request.tools.is_empty() {
+            args.tools(
+                request
+                    .tools
+                    .into_iter()
+                    .map(|tool| ChatCompletionTool {
+                        r#type: ChatCompletionToolType::Function,
+                        function: FunctionObject {
+                            name: tool.name,
+                            description: Some(tool.description),
+                            parameters: Some(tool.parameters),
+                            strict: None,
+                        },
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let response = self
+            .request(reqwest::Method::POST, "/chat/completions")
+            .json(&args.build()?)
+            .send()
+            .await?;
+        let response = check_response(response).await?;
+
+        Ok(response
+            .bytes_stream()
+            .eventsource()
+            .take_while(|event| {
+                futures::future::ready(!matches!(event, Ok(event) if event.data == "[DONE]"))
+            })
+            .flat_map(|event| {
+                let events = match event {
+                    Ok(event) => match parse_chunk(&event.data) {
+                        Ok(events) => events.into_iter().map(Ok).collect(),
+                        Err(e) => vec![Err(e)],
+                    },
+                    Err(e) => vec![Err(LLMError::Network(format!("Stream error: {}", e)).into())],
+                };
+                futures::stream::iter(events)
+            })
+            .boxed())
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let response = self.request(reqwest::Method::GET, "/models").send().await?;
+        let body: Value = check_response(response).await?.json().await?;
+        Ok(body["data"]
+            .as_array()
+            .map(|models| models.iter().filter_map(model_info).collect())
+            .unwrap_or_default())
+    }
+}
+
+/// 解析 `/models` 中的一项
+///
+/// OpenAI 只返回 id；Mistral、OpenRouter、Groq 等兼容接口会附带上下文长度和功能，
+/// 字段名各不相同，能识别的都记录下来。
+fn model_info(model: &Value) -> Option<ModelInfo> {
+    let mut info = ModelInfo::new(model["id"].as_str()?);
+    info.context_window = ["context_length", "max_context_length", "context_window"]
+        .iter()
+        .find_map(|key| model[*key].as_u64())
+        .map(|size| size as u32);
+
+    let capabilities = &model["capabilities"];
+    let modalities = model["architecture"]["input_modalities"].as_array();
+    let parameters = model["supported_parameters"].as_array();
+    info.vision = capabilities["vision"]
+        .as_bool()
+        .or_else(|| modalities.map(|list| list.iter().any(|m| m == "image")));
+    info.tools = capabilities["function_calling"]
+        .as_bool()
+        .or_else(|| parameters.map(|list| list.iter().any(|p| p == "tools")));
+    Some(info)
+}
+
+/// 解析流中的一个片段；有的兼容接口在流中途以 `error` 对象报告错误
+fn parse_chunk(data: &str) -> Result<Vec<ChatEvent>> {
+    let value: Value = serde_json::from_str(data)?;
+    if let Some(error) = value.get("error") {
+        let kind = error["code"]
+            .as_str()
+            .or_else(|| error["type"].as_str())
+            .unwrap_or_default();
+        let message = error["message"].as_str().unwrap_or(data).to_string();
+        return Err(LLMError::from_event(kind, message).into());
+    }
+
+    let response: CreateChatCompletionStreamResponse = serde_json::from_value(value)?;
+    let mut events = Vec::new();
+    for choice in response.choices {
+        if let Some(content) = choice.delta.content.filter(|content| !content.is_empty()) {
+            events.push(ChatEvent::Content(content));
+        }
+        for chunk in choice.delta.tool_calls.unwrap_or_default() {
+            let (name, arguments) = chunk
+                .function
+                .map(|function| (function.name, function.arguments))
+                .unwrap_or_default();
+            events.push(ChatEvent::ToolCall {
+                index: chunk.index as usize,
+                id: chunk.id,
+                name,
+                arguments,
+            });
+        }
+        if choice.finish_reason == Some(FinishReason::ContentFilter) {
+            return Err(LLMError::ContentFilter(
+                "The reply was stopped by the content filter".to_string(),
+            )
+            .into());
+        }
+    }
+    if let Some(usage) = response.usage {
+        events.push(ChatEvent::Usage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        });
+    }
+    Ok(events)
+}
+
+/// 将会话历史转换为 OpenAI 请求消息
+///
+/// 连续的工具调用会合并到同一条 assistant 消息的 `tool_calls` 中。
+fn to_request_messages(history: &[Message]) -> Result<Vec<ChatCompletionRequestMessage>> {
+    let mut messages: Vec<ChatCompletionRequestMessage> = Vec::new();
+
+    for message in history {
+        if let MessageContent::Function {
+            id,
+            name,
+            arguments,
+        } = &message.content
+        {
+            let tool_call = ChatCompletionMessageToolCall {
+                id: id.clone(),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: name.clone(),
+                    arguments: match arguments {
+                        serde_json::Value::String(raw) => raw.clone(),
+                        other => other.to_string(),
+                    },
+                },
+            };
+
+            match messages.last_mut() {
+                Some(ChatCompletionRequestMessage::Assistant(assistant)) => {
+                    assistant
+                        .tool_calls
+                        .get_or_insert_with(Vec::new)
+                        .push(tool_call);
+                }
+                _ => messages.push(
+                    ChatCompletionRequestAssistantMessageArgs::default()
+                        .tool_calls(vec![tool_call])
+                        .build()?
+                        .into(),
+                ),
+            }
+            continue;
+        }
+
+        messages.push(to_request_message(message)?);
+    }
+
+    Ok(messages)
+}
+
+/// 将会话中的一条消息转换为 OpenAI 请求消息
+fn to_request_message(message: &Message) -> Result<ChatCompletionRequestMessage> {
+    let text = match &message.content {
+        MessageContent::Text(text) | MessageContent::Summary { text, .. } => text.clone(),
+        MessageContent::Image { text, url } => {
+            // 只有用户消息支持多段内容，其他角色仅发送文字部分
+            if let Role::User = message.role {
+                return Ok(ChatCompletionRequestUserMessageArgs::default()
+                    .content(image_content_parts(text, url)?)
+                    .build()?
+                    .into());
+            }
+            text.clone()
+        }
+        MessageContent::Function {
+            name, arguments, ..
+        } => {
+            format!("Function call: {} with args: {}", name, arguments)
+        }
+        MessageContent::ToolResult {
+            call_id, content, ..
+        } => {
+            return Ok(ChatCompletionRequestToolMessageArgs::default()
+                .tool_call_id(call_id.clone())
+                .content(content.clone())
+                .build()?
+                .into());
+        }
+    };
+
+    let request_message = match message.role {
+        Role::System => ChatCompletionRequestSystemMessageArgs::default()
+            .content(text)
+            .build()?
+            .into(),
+        Role::User | Role::Tool => ChatCompletionRequestUserMessageArgs::default()
+            .content(text)
+            .build()?
+            .into(),
+        Role::Assistant => ChatCompletionRequestAssistantMessageArgs::default()
+            .content(text)
+            .build()?
+            .into(),
+    };
+
+    Ok(request_message)
+}
+
+/// 按 vision 接口的格式构造文字 + 图片的多段内容
+fn image_content_parts(
+    text: &str,
+    url: &str,
+) -> Result<Vec<ChatCompletionRequestUserMessageContentPart>> {
+    let mut parts = Vec::new();
+    if !text.is_empty() {
+        parts.push(
+            ChatCompletionRequestMessageContentPartTextArgs::default()
+                .text(text)
+                .build()?
+                .into(),
+        );
+    }
+    parts.push(
+        ChatCompletionRequestMessageContentPartImageArgs::default()
+            .image_url(
+                ImageUrlArgs::default()
+                    .url(url)
+                    .detail(ImageDetail::Auto)
+                    .build()?,
+            )
+            .build()?
+            .into(),
+    );
+    Ok(parts)
+}
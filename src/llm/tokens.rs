@@ -0,0 +1,127 @@
+use super::message::{Message, MessageContent};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// 每条消息除正文外的固定开销（角色、分隔符等），参考 OpenAI 的计算方式
+const TOKENS_PER_MESSAGE: usize = 4;
+/// 回复开头的固定开销
+pub const TOKENS_PER_REPLY: usize = 3;
+
+/// 计算文本的 token 数，不同模型可以使用不同的实现
+pub trait Tokenizer: Send + Sync {
+    /// 编码名称，用于缓存
+    fn name(&self) -> &'static str;
+    fn count(&self, text: &str) -> usize;
+}
+
+/// 测试用的分词器，每个单词算一个 token，便于计算
+#[cfg(test)]
+pub(crate) struct Words;
+
+#[cfg(test)]
+impl Tokenizer for Words {
+    fn name(&self) -> &'static str {
+        "words"
+    }
+
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+/// tiktoken 兼容的 BPE 编码
+#[derive(Clone, Copy)]
+enum Tiktoken {
+    O200kBase,
+    Cl100kBase,
+}
+
+impl Tokenizer for Tiktoken {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::O200kBase => "o200k_base",
+            Self::Cl100kBase => "cl100k_base",
+        }
+    }
+
+    fn count(&self, text: &str) -> usize {
+        // 编码表只加载一次，之后复用同一个实例
+        let bpe = match self {
+            Self::O200kBase => tiktoken_rs::o200k_base_singleton(),
+            Self::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+        };
+        let count = bpe.lock().encode_with_special_tokens(text).len();
+        count
+    }
+}
+
+/// 模型使用的分词器；非 OpenAI 的模型没有公开的 BPE，用 cl100k 近似
+pub fn tokenizer_for_model(model: &str) -> Arc<dyn Tokenizer> {
+    use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as Encoding};
+
+    match get_tokenizer(model) {
+        Some(Encoding::O200kBase) => Arc::new(Tiktoken::O200kBase),
+        _ => Arc::new(Tiktoken::Cl100kBase),
+    }
+}
+
+/// 模型的上下文长度
+pub fn context_window(model: &str) -> usize {
+    let model = model.to_lowercase();
+    let known = [
+        ("claude", 200_000),
+        ("mistral-large", 128_000),
+        ("mistral-small", 32_000),
+        ("codestral", 256_000),
+        ("open-mistral-nemo", 128_000),
+        ("llama3.1", 128_000),
+        ("llama3.2", 128_000),
+        ("llama3", 8_192),
+        ("qwen", 32_768),
+        ("gemma", 8_192),
+    ];
+    known
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, size)| *size)
+        .unwrap_or_else(|| tiktoken_rs::model::get_context_size(&model))
+}
+
+/// 消息中计入 token 的文字
+fn message_text(message: &Message) -> String {
+    match &message.content {
+        MessageContent::Text(text)
+        | MessageContent::Image { text, .. }
+        | MessageContent::Summary { text, .. } => text.clone(),
+        MessageContent::Function {
+            name, arguments, ..
+        } => format!("{}{}", name, arguments),
+        MessageContent::ToolResult { content, .. } => content.clone(),
+    }
+}
+
+pub fn count_message(tokenizer: &dyn Tokenizer, message: &Message) -> usize {
+    tokenizer.count(&message_text(message)) + TOKENS_PER_MESSAGE
+}
+
+/// 带缓存的计数器，界面每一帧都需要显示 token 数
+#[derive(Default)]
+pub struct TokenCounter {
+    cache: HashMap<u64, usize>,
+}
+
+impl TokenCounter {
+    pub fn count(&mut self, tokenizer: &dyn Tokenizer, message: &Message) -> usize {
+        let text = message_text(message);
+        let mut hasher = DefaultHasher::new();
+        tokenizer.name().hash(&mut hasher);
+        text.hash(&mut hasher);
+
+        *self
+            .cache
+            .entry(hasher.finish())
+            .or_insert_with(|| tokenizer.count(&text) + TOKENS_PER_MESSAGE)
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub mod time;
+
+pub use time::CurrentTimeTool;
+
+/// 可以被模型调用的本地工具
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// 工具名称，模型通过它发起调用
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// 参数的 JSON Schema
+    fn parameters(&self) -> serde_json::Value;
+
+    async fn call(&self, arguments: serde_json::Value) -> Result<serde_json::Value>;
+}
+
+/// 按名称注册的工具集合
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 包含所有内置工具的注册表
+    pub fn with_builtin_tools() -> Self {
+        let mut registry = Self::new();
+        registry.register(CurrentTimeTool);
+        registry
+    }
+
+    pub fn register(&mut self, tool: impl Tool + 'static) {
+        self.tools.insert(tool.name().to_string(), Arc::new(tool));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.get(name).cloned()
+    }
+
+    pub fn tools(&self) -> impl Iterator<Item = &Arc<dyn Tool>> {
+        self.tools.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// 执行一次工具调用，失败时把错误作为结果返回给模型
+    pub async fn call(&self, name: &str, arguments: serde_json::Value) -> String {
+        let Some(tool) = self.get(name) else {
+            return serde_json::json!({ "error": format!("Unknown tool: {}", name) }).to_string();
+        };
+
+        match tool.call(arguments).await {
+            Ok(value) => value.to_string(),
+            Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{FixedOffset, Utc};
+use serde_json::json;
+
+use super::Tool;
+
+/// 返回当前日期和时间，可指定 UTC 偏移
+pub struct CurrentTimeTool;
+
+#[async_trait]
+impl Tool for CurrentTimeTool {
+    fn name(&self) -> &str {
+        "get_current_time"
+    }
+
+    fn description(&self) -> &str {
+        "Get the current date and time, optionally in a given UTC offset"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "utc_offset_hours": {
+                    "type": "number",
+                    "description": "Offset from UTC in hours, e.g. 8 for Beijing or -5 for New York",
+                },
+            },
+        })
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let offset_hours = arguments["utc_offset_hours"].as_f64().unwrap_or(0.0);
+        let offset = FixedOffset::east_opt((offset_hours * 3600.0) as i32)
+            .ok_or_else(|| anyhow::anyhow!("Invalid UTC offset: {}", offset_hours))?;
+        let now = Utc::now().with_timezone(&offset);
+
+        Ok(json!({
+            "datetime": now.to_rfc3339(),
+            "weekday": now.format("%A").to_string(),
+        }))
+    }
+}
@@ -1,13 +1,35 @@
+use clap::Parser;
 use eframe::egui;
 use tracing::info;
 
-mod chat;
-mod llm;
-mod ui;
-
-use ui::App;
+use llm_client::cli::{self, Cli};
+use llm_client::ui::App;
 
 fn main() -> Result<(), eframe::Error> {
+    let cli = Cli::parse();
+
+    // 命令行模式下日志写到标准错误，标准输出只留给回复；
+    // 终端界面占满整个屏幕，默认不输出日志；代理默认记录每个请求
+    if let Some(command) = cli.command {
+        let default_filter = match command {
+            cli::Command::Tui { .. } => "off",
+            cli::Command::Serve { .. } => "info",
+            _ => "warn",
+        };
+        tracing_subscriber::fmt()
+            .with_env_filter(
+                tracing_subscriber::EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| default_filter.into()),
+            )
+            .with_writer(std::io::stderr)
+            .init();
+        if let Err(e) = cli::run(command) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // 初始化 tracing
     tracing_subscriber::fmt()
         .with_env_filter("debug")
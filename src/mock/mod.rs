@@ -0,0 +1,365 @@
+mod script;
+
+pub use script::{MockReply, MockResponse, MockToolCall, MockUsage, Script};
+
+use crate::llm::context::ContextPolicy;
+use crate::llm::{LLMConfig, ProviderKind};
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// 脚本中没有配置模型时使用的模型名称
+pub const DEFAULT_MODEL: &str = "mock-model";
+
+/// 本地的 OpenAI 兼容模拟服务器，按脚本回复 `/chat/completions` 请求
+///
+/// 用于离线开发和测试；服务器在被丢弃时停止。
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    task: JoinHandle<()>,
+}
+
+struct MockState {
+    models: Vec<String>,
+    replies: Mutex<VecDeque<MockReply>>,
+    requests: Mutex<Vec<Value>>,
+}
+
+impl MockServer {
+    /// 在本机的随机端口上启动
+    pub async fn start(script: Script) -> Result<Self> {
+        Self::bind(SocketAddr::from(([127, 0, 0, 1], 0)), script).await
+    }
+
+    pub async fn bind(addr: SocketAddr, script: Script) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(MockState {
+            models: script.models,
+            replies: Mutex::new(script.replies.into()),
+            requests: Mutex::default(),
+        });
+
+        let app = Router::new()
+            .route("/v1/chat/completions", post(chat_completions))
+            .route("/v1/models", get(models))
+            .with_state(state.clone());
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                warn!(?e, "Mock server stopped");
+            }
+        });
+        info!(%addr, "Mock server listening");
+
+        Ok(Self { addr, state, task })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 客户端配置中使用的 API 地址
+    pub fn api_base(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+
+    /// 指向这个服务器的客户端配置
+    pub fn config(&self) -> LLMConfig {
+        LLMConfig {
+            provider: ProviderKind::OpenAI,
+            api_key: "mock".to_string(),
+            api_base: self.api_base(),
+            model: DEFAULT_MODEL.to_string(),
+            temperature: 0.7,
+            max_tokens: 1000,
+            context_window: None,
+            context_policy: ContextPolicy::default(),
+        }
+    }
+
+    /// 在脚本末尾追加一条回复
+    pub fn push(&self, reply: MockReply) {
+        self.state.replies.lock().unwrap().push_back(reply);
+    }
+
+    /// 到目前为止收到的 `/chat/completions` 请求体
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn models(State(state): State<Arc<MockState>>) -> Json<Value> {
+    let models = if state.models.is_empty() {
+        vec![DEFAULT_MODEL.to_string()]
+    } else {
+        state.models.clone()
+    };
+    let data: Vec<Value> = models
+        .iter()
+        .map(|id| json!({ "id": id, "object": "model", "created": 0, "owned_by": "mock" }))
+        .collect();
+    Json(json!({ "object": "list", "data": data }))
+}
+
+async fn chat_completions(
+    State(state): State<Arc<MockState>>,
+    Json(request): Json<Value>,
+) -> Response {
+    state.requests.lock().unwrap().push(request.clone());
+    let reply = state.replies.lock().unwrap().pop_front();
+    let reply = reply.unwrap_or_else(|| echo(&request));
+
+    let model = request["model"]
+        .as_str()
+        .unwrap_or(DEFAULT_MODEL)
+        .to_string();
+    let stream = request["stream"].as_bool().unwrap_or(false);
+    info!(%model, stream, "Mock request");
+
+    if reply.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(reply.delay_ms)).await;
+    }
+    if stream {
+        let chunk_delay = Duration::from_millis(reply.chunk_delay_ms);
+        stream_response(&model, reply.response, reply.usage, chunk_delay)
+    } else {
+        completion_response(&model, reply.response, reply.usage)
+    }
+}
+
+/// 脚本用完后回显最后一条用户消息
+fn echo(request: &Value) -> MockReply {
+    let content = request["messages"]
+        .as_array()
+        .and_then(|messages| messages.iter().rev().find(|m| m["role"] == "user"))
+        .map(|message| &message["content"]);
+    let text = match content {
+        Some(Value::String(text)) => text.clone(),
+        // 多段内容只取文字部分
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => String::new(),
+    };
+    let text = format!("echo: {}", text);
+    MockReply::text(text.split_inclusive(' ')).with_usage(10, 10)
+}
+
+fn error_response(
+    status: u16,
+    message: &str,
+    code: Option<&str>,
+    retry_after: Option<u64>,
+) -> Response {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response = (status, Json(error_body(message, code))).into_response();
+    if let Some(seconds) = retry_after {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    response
+}
+
+fn error_body(message: &str, code: Option<&str>) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": code.unwrap_or("mock_error"),
+            "code": code,
+        }
+    })
+}
+
+/// 以 SSE 流返回回复，片段之间按 `chunk_delay` 等待
+fn stream_response(
+    model: &str,
+    response: MockResponse,
+    usage: Option<MockUsage>,
+    chunk_delay: Duration,
+) -> Response {
+    let text_chunks = |chunks: Vec<String>| -> Vec<Value> {
+        chunks
+            .into_iter()
+            .map(|content| chunk(model, json!({ "content": content }), None))
+            .collect()
+    };
+
+    let (events, ending) = match response {
+        MockResponse::Text { chunks } => {
+            let mut events = text_chunks(chunks);
+            events.push(chunk(model, json!({}), Some("stop")));
+            (events, Ending::Done(usage))
+        }
+        MockResponse::ToolCalls { calls } => {
+            let mut events: Vec<Value> = calls
+                .into_iter()
+                .enumerate()
+                .map(|(index, call)| {
+                    let delta = json!({ "tool_calls": [tool_call_json(index, &call)] });
+                    chunk(model, delta, None)
+                })
+                .collect();
+            events.push(chunk(model, json!({}), Some("tool_calls")));
+            (events, Ending::Done(usage))
+        }
+        MockResponse::StreamError {
+            chunks,
+            message,
+            code,
+        } => {
+            let mut events = text_chunks(chunks);
+            events.push(error_body(&message, code.as_deref()));
+            (events, Ending::Close)
+        }
+        MockResponse::Disconnect { chunks } => (text_chunks(chunks), Ending::Disconnect),
+        MockResponse::Error {
+            status,
+            message,
+            code,
+            retry_after,
+        } => return error_response(status, &message, code.as_deref(), retry_after),
+    };
+
+    let mut frames: Vec<io::Result<String>> = events
+        .iter()
+        .map(|event| Ok(format!("data: {}\n\n", event)))
+        .collect();
+    match ending {
+        Ending::Done(usage) => {
+            if let Some(usage) = usage {
+                let event = json!({
+                    "id": "chatcmpl-mock",
+                    "object": "chat.completion.chunk",
+                    "created": 0,
+                    "model": model,
+                    "choices": [],
+                    "usage": usage_json(usage),
+                });
+                frames.push(Ok(format!("data: {}\n\n", event)));
+            }
+            frames.push(Ok("data: [DONE]\n\n".to_string()));
+        }
+        Ending::Close => {}
+        // 响应体出错时连接会被直接断开
+        Ending::Disconnect => frames.push(Err(io::Error::other("mock disconnect"))),
+    }
+
+    let body = futures::stream::iter(frames).then(move |frame| async move {
+        if !chunk_delay.is_zero() {
+            tokio::time::sleep(chunk_delay).await;
+        }
+        frame
+    });
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(body))
+        .unwrap()
+}
+
+/// 流式回复的结尾
+enum Ending {
+    /// 正常结束，可能带有用量
+    Done(Option<MockUsage>),
+    /// 不发送 `[DONE]` 直接结束
+    Close,
+    /// 断开连接
+    Disconnect,
+}
+
+/// `stream: false` 时一次返回完整的回复
+fn completion_response(model: &str, response: MockResponse, usage: Option<MockUsage>) -> Response {
+    let (message, finish_reason) = match response {
+        MockResponse::Text { chunks } => (
+            json!({ "role": "assistant", "content": chunks.concat() }),
+            "stop",
+        ),
+        MockResponse::ToolCalls { calls } => {
+            let calls: Vec<Value> = calls
+                .iter()
+                .enumerate()
+                .map(|(index, call)| tool_call_json(index, call))
+                .collect();
+            (
+                json!({ "role": "assistant", "content": null, "tool_calls": calls }),
+                "tool_calls",
+            )
+        }
+        MockResponse::Error {
+            status,
+            message,
+            code,
+            retry_after,
+        } => return error_response(status, &message, code.as_deref(), retry_after),
+        // 没有流可以中断，按服务端错误返回
+        MockResponse::StreamError { message, code, .. } => {
+            return error_response(500, &message, code.as_deref(), None);
+        }
+        MockResponse::Disconnect { .. } => {
+            return error_response(500, "mock disconnect", None, None);
+        }
+    };
+
+    let mut body = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 0,
+        "model": model,
+        "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+    });
+    if let Some(usage) = usage {
+        body["usage"] = usage_json(usage);
+    }
+    Json(body).into_response()
+}
+
+fn chunk(model: &str, delta: Value, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+    })
+}
+
+fn tool_call_json(index: usize, call: &MockToolCall) -> Value {
+    json!({
+        "index": index,
+        "id": call.id,
+        "type": "function",
+        "function": { "name": call.name, "arguments": call.arguments.to_string() },
+    })
+}
+
+fn usage_json(usage: MockUsage) -> Value {
+    json!({
+        "prompt_tokens": usage.prompt_tokens,
+        "completion_tokens": usage.completion_tokens,
+        "total_tokens": usage.prompt_tokens + usage.completion_tokens,
+    })
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// 模拟服务器的脚本：可用的模型和按顺序返回的回复
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Script {
+    /// `/models` 返回的模型，为空时返回 `mock-model`
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// 每个请求取出一条回复，用完后回显最后一条用户消息
+    #[serde(default)]
+    pub replies: Vec<MockReply>,
+}
+
+impl Script {
+    /// 从 JSON 文件读取脚本
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// 一次请求的回复内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MockResponse {
+    /// 按片段流式返回文字
+    Text { chunks: Vec<String> },
+    /// 返回工具调用
+    ToolCalls { calls: Vec<MockToolCall> },
+    /// 以 HTTP 错误状态响应
+    Error {
+        status: u16,
+        message: String,
+        /// 错误对象中的 `code`，例如 `context_length_exceeded`
+        #[serde(default)]
+        code: Option<String>,
+        /// `Retry-After` 响应头的秒数
+        #[serde(default)]
+        retry_after: Option<u64>,
+    },
+    /// 先返回一些片段，再在流中发送错误对象
+    StreamError {
+        #[serde(default)]
+        chunks: Vec<String>,
+        message: String,
+        #[serde(default)]
+        code: Option<String>,
+    },
+    /// 先返回一些片段，然后不结束流直接断开连接
+    Disconnect {
+        #[serde(default)]
+        chunks: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MockUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+/// 脚本中的一条回复，可以附带用量和延迟
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockReply {
+    #[serde(flatten)]
+    pub response: MockResponse,
+    /// 成功的流式回复在最后一个片段中报告的用量
+    #[serde(default)]
+    pub usage: Option<MockUsage>,
+    /// 开始响应前等待的毫秒数
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// 每个片段之间等待的毫秒数
+    #[serde(default)]
+    pub chunk_delay_ms: u64,
+}
+
+impl MockReply {
+    pub fn new(response: MockResponse) -> Self {
+        Self {
+            response,
+            usage: None,
+            delay_ms: 0,
+            chunk_delay_ms: 0,
+        }
+    }
+
+    pub fn text<S: Into<String>>(chunks: impl IntoIterator<Item = S>) -> Self {
+        Self::new(MockResponse::Text {
+            chunks: chunks.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    pub fn tool_call(id: &str, name: &str, arguments: serde_json::Value) -> Self {
+        Self::new(MockResponse::ToolCalls {
+            calls: vec![MockToolCall {
+                id: id.to_string(),
+                name: name.to_string(),
+                arguments,
+            }],
+        })
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::new(MockResponse::Error {
+            status,
+            message: message.to_string(),
+            code: None,
+            retry_after: None,
+        })
+    }
+
+    pub fn stream_error<S: Into<String>>(
+        chunks: impl IntoIterator<Item = S>,
+        code: &str,
+        message: &str,
+    ) -> Self {
+        Self::new(MockResponse::StreamError {
+            chunks: chunks.into_iter().map(Into::into).collect(),
+            message: message.to_string(),
+            code: Some(code.to_string()),
+        })
+    }
+
+    pub fn disconnect<S: Into<String>>(chunks: impl IntoIterator<Item = S>) -> Self {
+        Self::new(MockResponse::Disconnect {
+            chunks: chunks.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    pub fn with_usage(mut self, prompt_tokens: u32, completion_tokens: u32) -> Self {
+        self.usage = Some(MockUsage {
+            prompt_tokens,
+            completion_tokens,
+        });
+        self
+    }
+
+    /// 只对 `Error` 回复有效
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        if let MockResponse::Error { retry_after, .. } = &mut self.response {
+            *retry_after = Some(seconds);
+        }
+        self
+    }
+
+    /// 只对 `Error` 和 `StreamError` 回复有效
+    pub fn with_code(mut self, error_code: &str) -> Self {
+        if let MockResponse::Error { code, .. } | MockResponse::StreamError { code, .. } =
+            &mut self.response
+        {
+            *code = Some(error_code.to_string());
+        }
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay_ms = delay.as_millis() as u64;
+        self
+    }
+
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay_ms = delay.as_millis() as u64;
+        self
+    }
+}
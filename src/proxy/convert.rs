@@ -0,0 +1,180 @@
+use crate::llm::{Message, MessageContent, Role, Usage};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// 客户端发来的 `/chat/completions` 请求中代理用到的部分
+#[derive(Debug, Deserialize)]
+pub struct ProxyRequest {
+    /// 配置档案名称或模型名称，为空时使用默认配置档案
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<Value>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    /// 代理不转发客户端的工具定义
+    #[serde(default)]
+    pub tools: Vec<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+impl ProxyRequest {
+    pub fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|options| options.include_usage)
+    }
+
+    /// 转换成会话消息；assistant 消息中的工具调用拆成单独的消息
+    pub fn to_messages(&self) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+        for message in &self.messages {
+            let text = content_text(&message["content"]);
+            match message["role"].as_str().unwrap_or_default() {
+                "system" | "developer" => {
+                    messages.push(Message::new(Role::System, MessageContent::Text(text)))
+                }
+                "user" => {
+                    let content = match image_url(&message["content"]) {
+                        Some(url) => MessageContent::Image { text, url },
+                        None => MessageContent::Text(text),
+                    };
+                    messages.push(Message::new(Role::User, content));
+                }
+                "assistant" => {
+                    if !text.is_empty() {
+                        messages.push(Message::new(Role::Assistant, MessageContent::Text(text)));
+                    }
+                    for call in message["tool_calls"].as_array().into_iter().flatten() {
+                        let arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+                        messages.push(Message::new(
+                            Role::Assistant,
+                            MessageContent::Function {
+                                id: call["id"].as_str().unwrap_or_default().to_string(),
+                                name: call["function"]["name"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .to_string(),
+                                arguments: serde_json::from_str(arguments)
+                                    .unwrap_or_else(|_| Value::String(arguments.to_string())),
+                            },
+                        ));
+                    }
+                }
+                "tool" => {
+                    let call_id = message["tool_call_id"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string();
+                    let name = tool_name(&messages, &call_id);
+                    messages.push(Message::new(
+                        Role::Tool,
+                        MessageContent::ToolResult {
+                            call_id,
+                            name,
+                            content: text,
+                        },
+                    ));
+                }
+                role => return Err(anyhow::anyhow!("Unsupported message role: {:?}", role)),
+            }
+        }
+        if messages.is_empty() {
+            return Err(anyhow::anyhow!("The request has no messages"));
+        }
+        Ok(messages)
+    }
+}
+
+/// 字符串内容，或多段内容中的文字部分
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 多段内容中的第一张图片
+fn image_url(content: &Value) -> Option<String> {
+    content
+        .as_array()?
+        .iter()
+        .find_map(|part| part["image_url"]["url"].as_str())
+        .map(str::to_string)
+}
+
+/// 工具结果只带调用 id，名称从之前的工具调用中查找
+fn tool_name(messages: &[Message], call_id: &str) -> String {
+    messages
+        .iter()
+        .rev()
+        .find_map(|message| match &message.content {
+            MessageContent::Function { id, name, .. } if id == call_id => Some(name.clone()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// 流式响应中的一个片段
+pub fn chunk(id: &str, model: &str, delta: Value, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+    })
+}
+
+/// `stream_options.include_usage` 要求的最后一个片段
+pub fn usage_chunk(id: &str, usage: &Usage) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": chrono::Utc::now().timestamp(),
+        "model": usage.model,
+        "choices": [],
+        "usage": usage_json(usage),
+    })
+}
+
+/// 非流式请求的完整响应
+pub fn completion(id: &str, model: &str, text: &str, usage: Option<&Usage>) -> Value {
+    let mut body = json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": text },
+            "finish_reason": "stop",
+        }],
+    });
+    if let Some(usage) = usage {
+        body["usage"] = usage_json(usage);
+    }
+    body
+}
+
+fn usage_json(usage: &Usage) -> Value {
+    json!({
+        "prompt_tokens": usage.prompt_tokens,
+        "completion_tokens": usage.completion_tokens,
+        "total_tokens": usage.prompt_tokens + usage.completion_tokens,
+    })
+}
@@ -0,0 +1,418 @@
+mod convert;
+
+use crate::chat::ledger::{LedgerEntry, UsageLedger};
+use crate::chat::{ChatSession, SessionManager};
+use crate::cli::Context;
+use crate::config::AppConfig;
+use crate::llm::{
+    ConfigOverrides, LLMClient, LLMError, Message, MessageContent, Role, StreamMessage,
+};
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use convert::ProxyRequest;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// 记录的会话标题最多保留的字符数
+const MAX_TITLE_CHARS: usize = 50;
+
+/// 本地的 OpenAI 兼容代理，用应用中的配置档案转发请求，并把每个请求记录为会话
+///
+/// 请求中的 `model` 是配置档案名称时使用该档案，否则作为模型名称交给默认配置档案。
+/// 客户端的工具定义不会被转发。
+#[derive(Clone)]
+pub struct Proxy {
+    state: Arc<ProxyState>,
+}
+
+struct ProxyState {
+    config: AppConfig,
+    default_profile: String,
+    /// 每个配置档案的客户端，不带本地工具
+    clients: HashMap<String, LLMClient>,
+    sessions: Mutex<SessionManager>,
+    ledger: Mutex<UsageLedger>,
+}
+
+impl Proxy {
+    pub fn new(
+        config: AppConfig,
+        profile: Option<&str>,
+        sessions: SessionManager,
+        ledger: UsageLedger,
+    ) -> Result<Self> {
+        let default_profile = match profile {
+            Some(name) => config
+                .profile(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown profile: {}", name))?,
+            None => config.default_profile(),
+        };
+        let mut clients: HashMap<String, LLMClient> = config
+            .profiles
+            .iter()
+            .map(|profile| {
+                let client = LLMClient::new(profile.to_llm_config());
+                (profile.name.clone(), client)
+            })
+            .collect();
+        clients
+            .entry(default_profile.name.clone())
+            .or_insert_with(|| LLMClient::new(default_profile.to_llm_config()));
+
+        Ok(Self {
+            state: Arc::new(ProxyState {
+                default_profile: default_profile.name.clone(),
+                config,
+                clients,
+                sessions: Mutex::new(sessions),
+                ledger: Mutex::new(ledger),
+            }),
+        })
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/v1/chat/completions", post(chat_completions))
+            .route("/v1/models", get(models))
+            .with_state(self.state.clone())
+    }
+
+    /// 代理记录的会话，最新的在前
+    pub fn sessions(&self) -> Vec<ChatSession> {
+        let sessions = self.state.sessions.lock().unwrap();
+        sessions.get_all_sessions().into_iter().cloned().collect()
+    }
+}
+
+/// 在 `listen` 上运行代理，直到按下 Ctrl-C
+pub(crate) async fn run(context: Context, listen: SocketAddr, profile: Option<&str>) -> Result<()> {
+    if !listen.ip().is_loopback() {
+        warn!(%listen, "The proxy is reachable from other machines and uses your API keys");
+    }
+    let proxy = Proxy::new(context.config, profile, context.sessions, context.ledger)?;
+    let listener = TcpListener::bind(listen).await?;
+    eprintln!("Proxy listening on http://{}/v1", listener.local_addr()?);
+
+    axum::serve(listener, proxy.router())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+impl ProxyState {
+    /// 按请求中的 `model` 选择客户端，返回客户端、配置覆盖和实际使用的模型
+    fn route(&self, model: &str) -> (&LLMClient, ConfigOverrides, String) {
+        if let Some(profile) = self.config.profile(model) {
+            if let Some(client) = self.clients.get(&profile.name) {
+                return (client, ConfigOverrides::default(), profile.model.clone());
+            }
+        }
+
+        let profile = self
+            .config
+            .profile(&self.default_profile)
+            .unwrap_or_else(|| self.config.default_profile());
+        let client = &self.clients[&self.default_profile];
+        if model.is_empty() {
+            return (client, ConfigOverrides::default(), profile.model.clone());
+        }
+        let overrides = ConfigOverrides {
+            model: Some(model.to_string()),
+            ..Default::default()
+        };
+        (client, overrides, model.to_string())
+    }
+
+    /// 把一次请求和回复保存为会话，并记录用量
+    fn record(&self, mut history: Vec<Message>, reply: Message) {
+        let usage = reply.usage.clone();
+        let mut sessions = self.sessions.lock().unwrap();
+        let id = sessions.create_session(title(&history));
+        if let Some(session) = sessions.get_session_mut(&id) {
+            if let Some(Message {
+                role: Role::System,
+                content: MessageContent::Text(prompt),
+                ..
+            }) = history.first()
+            {
+                session.settings.system_prompt = prompt.clone();
+                history.remove(0);
+            }
+            for message in history {
+                session.add_message(message);
+            }
+            session.add_message(reply);
+        }
+        sessions.persist_changes();
+        drop(sessions);
+
+        if let Some(usage) = usage {
+            let entry = LedgerEntry::new(&id, &usage);
+            if let Err(e) = self.ledger.lock().unwrap().record(entry) {
+                warn!(?e, "Failed to record usage");
+            }
+        }
+    }
+}
+
+/// 以第一条用户消息作为记录的会话标题
+fn title(history: &[Message]) -> String {
+    let text = history
+        .iter()
+        .find(|message| matches!(message.role, Role::User))
+        .and_then(|message| match &message.content {
+            MessageContent::Text(text) | MessageContent::Image { text, .. } => Some(text.trim()),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let text: String = text.chars().take(MAX_TITLE_CHARS).collect();
+    format!("Proxy: {}", text.lines().next().unwrap_or_default())
+}
+
+/// 配置档案名称作为可选的模型
+async fn models(State(state): State<Arc<ProxyState>>) -> Json<Value> {
+    let data: Vec<Value> = state
+        .config
+        .profiles
+        .iter()
+        .map(|profile| {
+            json!({ "id": profile.name, "object": "model", "created": 0, "owned_by": "llm-client" })
+        })
+        .collect();
+    Json(json!({ "object": "list", "data": data }))
+}
+
+async fn chat_completions(
+    State(state): State<Arc<ProxyState>>,
+    Json(request): Json<ProxyRequest>,
+) -> Response {
+    let history = match request.to_messages() {
+        Ok(history) => history,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                &e.to_string(),
+                None,
+            )
+        }
+    };
+    if !request.tools.is_empty() {
+        warn!(
+            tools = request.tools.len(),
+            "Ignoring tool definitions, the proxy does not forward tools"
+        );
+    }
+
+    let (client, mut overrides, model) = state.route(&request.model);
+    overrides.temperature = request.temperature;
+    info!(%model, messages = history.len(), stream = request.stream, "Proxy request");
+
+    let mut rx = match client
+        .send_message_streaming(&history.iter().cloned().collect(), overrides)
+        .await
+    {
+        Ok(rx) => rx,
+        Err(e) => return llm_error_response(&LLMError::classify(&e)),
+    };
+    let id = format!("chatcmpl-{}", Uuid::new_v4().simple());
+
+    if !request.stream {
+        return match collect(&mut rx).await {
+            Ok(reply) => {
+                let text = match &reply.content {
+                    MessageContent::Text(text) => text.clone(),
+                    _ => String::new(),
+                };
+                let body = convert::completion(&id, &model, &text, reply.usage.as_ref());
+                state.record(history, reply);
+                Json(body).into_response()
+            }
+            Err(error) => llm_error_response(&error),
+        };
+    }
+
+    // 等到第一段文字或结果再发送响应头，请求失败时可以返回对应的状态码
+    let first = loop {
+        match rx.recv().await {
+            Some(StreamMessage::Retrying { .. } | StreamMessage::Intermediate(_)) => continue,
+            Some(StreamMessage::Error(error)) => return llm_error_response(&error),
+            Some(message) => break message,
+            None => return llm_error_response(&unexpected_end()),
+        }
+    };
+
+    let (tx, frames) = mpsc::channel(16);
+    let relay = Relay {
+        state,
+        history,
+        id,
+        model,
+        include_usage: request.include_usage(),
+    };
+    tokio::spawn(relay.run(first, rx, tx));
+
+    let body = futures::stream::unfold(frames, |mut frames| async move {
+        frames
+            .recv()
+            .await
+            .map(|frame| (Ok::<_, Infallible>(frame), frames))
+    });
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(body))
+        .unwrap()
+}
+
+/// 读取完整的回复
+async fn collect(rx: &mut mpsc::Receiver<StreamMessage>) -> Result<Message, LLMError> {
+    while let Some(message) = rx.recv().await {
+        match message {
+            StreamMessage::Done(reply) => return Ok(reply),
+            StreamMessage::Error(error) => return Err(error),
+            _ => {}
+        }
+    }
+    Err(unexpected_end())
+}
+
+fn unexpected_end() -> LLMError {
+    LLMError::Other("The reply ended unexpectedly".to_string())
+}
+
+/// 把一次流式回复转成 SSE 片段转发给客户端
+struct Relay {
+    state: Arc<ProxyState>,
+    history: Vec<Message>,
+    id: String,
+    model: String,
+    include_usage: bool,
+}
+
+impl Relay {
+    /// 回复完成后记录会话；客户端断开时停止转发，上游请求也随之取消
+    async fn run(
+        self,
+        first: StreamMessage,
+        mut rx: mpsc::Receiver<StreamMessage>,
+        tx: mpsc::Sender<String>,
+    ) {
+        let mut next = Some(first);
+        let mut sent_role = false;
+        loop {
+            let message = match next.take() {
+                Some(message) => message,
+                None => match rx.recv().await {
+                    Some(message) => message,
+                    None => return,
+                },
+            };
+            match message {
+                StreamMessage::Chunk(text) => {
+                    // 第一个片段带上角色，和 OpenAI 的格式一致
+                    let delta = if sent_role {
+                        json!({ "content": text })
+                    } else {
+                        sent_role = true;
+                        json!({ "role": "assistant", "content": text })
+                    };
+                    let chunk = convert::chunk(&self.id, &self.model, delta, None);
+                    if tx.send(frame(&chunk)).await.is_err() {
+                        return;
+                    }
+                }
+                StreamMessage::Done(reply) => {
+                    let mut frames = vec![frame(&convert::chunk(
+                        &self.id,
+                        &self.model,
+                        json!({}),
+                        Some("stop"),
+                    ))];
+                    if let Some(usage) = reply.usage.as_ref().filter(|_| self.include_usage) {
+                        frames.push(frame(&convert::usage_chunk(&self.id, usage)));
+                    }
+                    frames.push("data: [DONE]\n\n".to_string());
+                    for frame in frames {
+                        let _ = tx.send(frame).await;
+                    }
+                    self.state.record(self.history, reply);
+                    return;
+                }
+                StreamMessage::Error(error) => {
+                    // 响应已经开始，只能在流中报告错误
+                    let (_, kind) = error_status(&error);
+                    let _ = tx.send(frame(&error_body(kind, &error.to_string()))).await;
+                    return;
+                }
+                // 已经转发的文字无法撤回，重试的回复会接在后面造成重复，
+                // 所以转发开始后的重试按错误结束，由客户端决定是否重新请求
+                StreamMessage::Retrying { error, .. } if sent_role => {
+                    warn!(%error, "Upstream retried after the reply started, ending the stream");
+                    let (_, kind) = error_status(&error);
+                    let _ = tx.send(frame(&error_body(kind, &error.to_string()))).await;
+                    return;
+                }
+                StreamMessage::Retrying { .. } | StreamMessage::Intermediate(_) => {}
+            }
+        }
+    }
+}
+
+fn frame(value: &Value) -> String {
+    format!("data: {}\n\n", value)
+}
+
+/// 按 OpenAI 的习惯把错误映射为状态码和错误类型
+fn error_status(error: &LLMError) -> (StatusCode, &'static str) {
+    match error {
+        LLMError::Auth(_) => (StatusCode::UNAUTHORIZED, "authentication_error"),
+        LLMError::RateLimit { .. } => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_exceeded"),
+        LLMError::Quota(_) => (StatusCode::TOO_MANY_REQUESTS, "insufficient_quota"),
+        LLMError::ContextLength(_) => (StatusCode::BAD_REQUEST, "context_length_exceeded"),
+        LLMError::ContentFilter(_) => (StatusCode::BAD_REQUEST, "content_filter"),
+        LLMError::Server { .. } | LLMError::Network(_) => (StatusCode::BAD_GATEWAY, "server_error"),
+        LLMError::Other(_) => (StatusCode::INTERNAL_SERVER_ERROR, "api_error"),
+    }
+}
+
+fn llm_error_response(error: &LLMError) -> Response {
+    warn!(%error, "Proxy request failed");
+    let (status, kind) = error_status(error);
+    error_response(status, kind, &error.to_string(), error.retry_after())
+}
+
+fn error_response(
+    status: StatusCode,
+    kind: &str,
+    message: &str,
+    retry_after: Option<Duration>,
+) -> Response {
+    let mut response = (status, Json(error_body(kind, message))).into_response();
+    if let Some(delay) = retry_after {
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(delay.as_secs_f64().ceil() as u64),
+        );
+    }
+    response
+}
+
+fn error_body(kind: &str, message: &str) -> Value {
+    json!({ "error": { "message": message, "type": kind, "code": kind } })
+}
@@ -0,0 +1,412 @@
+use super::input::Input;
+use crate::chat::ledger::LedgerEntry;
+use crate::chat::{title, ChatSession};
+use crate::cli::Context;
+use crate::llm::{Completion, LLMClient, LLMError, Message, MessageContent, Role, StreamMessage};
+use anyhow::Result;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// 后台任务发回界面的消息
+pub enum AppEvent {
+    /// 某次请求的流式回复，编号用于丢弃已停止请求的残留消息
+    Stream(u64, StreamMessage),
+    Title(String, Result<Completion>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Sessions,
+    Input,
+}
+
+/// 正在进行的请求
+pub struct Streaming {
+    pub id: u64,
+    pub session_id: String,
+    pub content: String,
+    task: JoinHandle<()>,
+}
+
+pub struct TuiApp {
+    pub context: Context,
+    client: LLMClient,
+    /// 正在查看的会话，新对话在发送第一条消息时才创建
+    pub current: Option<String>,
+    pub focus: Focus,
+    pub input: Input,
+    /// 对话区域距离底部滚动的行数，为 0 时跟随最新内容
+    pub scroll: u16,
+    pub streaming: Option<Streaming>,
+    /// 状态栏中的错误或重试提示
+    pub status: Option<String>,
+    /// 等待确认删除的会话
+    pub confirm_delete: Option<String>,
+    pub quit: bool,
+    next_request: u64,
+    events: mpsc::UnboundedSender<AppEvent>,
+}
+
+impl TuiApp {
+    pub fn new(
+        context: Context,
+        client: LLMClient,
+        events: mpsc::UnboundedSender<AppEvent>,
+    ) -> Self {
+        let current = context
+            .sessions
+            .get_all_sessions()
+            .into_iter()
+            .max_by_key(|session| session.updated_at)
+            .map(|session| session.id.clone());
+        Self {
+            context,
+            client,
+            current,
+            focus: Focus::Input,
+            input: Input::default(),
+            scroll: 0,
+            streaming: None,
+            status: None,
+            confirm_delete: None,
+            quit: false,
+            next_request: 0,
+            events,
+        }
+    }
+
+    /// 会话列表，最近更新的在前
+    pub fn sessions(&self) -> Vec<&ChatSession> {
+        let mut sessions = self.context.sessions.get_all_sessions();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+        sessions
+    }
+
+    pub fn current_session(&self) -> Option<&ChatSession> {
+        self.current
+            .as_deref()
+            .and_then(|id| self.context.sessions.get_session(id))
+    }
+
+    pub fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Key(key) if key.kind != KeyEventKind::Release => self.handle_key(key),
+            Event::Paste(text) => {
+                self.focus = Focus::Input;
+                self.input.insert_str(&text);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        if let Some(id) = self.confirm_delete.take() {
+            self.status = None;
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                self.delete_session(&id);
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('q') if ctrl => {
+                self.stop();
+                self.quit = true;
+                return;
+            }
+            KeyCode::Char('n') if ctrl => {
+                self.new_chat();
+                return;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Sessions => Focus::Input,
+                    Focus::Input => Focus::Sessions,
+                };
+                return;
+            }
+            KeyCode::Esc if self.streaming.is_some() => {
+                self.stop();
+                return;
+            }
+            KeyCode::PageUp => {
+                self.scroll = self.scroll.saturating_add(10);
+                return;
+            }
+            KeyCode::PageDown => {
+                self.scroll = self.scroll.saturating_sub(10);
+                return;
+            }
+            _ => {}
+        }
+
+        match self.focus {
+            Focus::Sessions => self.sessions_key(key),
+            Focus::Input => self.input_key(key),
+        }
+    }
+
+    fn sessions_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.select_relative(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select_relative(1),
+            KeyCode::Enter => self.focus = Focus::Input,
+            KeyCode::Char('n') => self.new_chat(),
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(session) = self.current_session() {
+                    let prompt = format!("Delete \"{}\"? (y/n)", session.title);
+                    self.confirm_delete = Some(session.id.clone());
+                    self.status = Some(prompt);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn input_key(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let newline = key
+            .modifiers
+            .intersects(KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match key.code {
+            // 大多数终端无法区分 Shift+Enter，Alt+Enter 和 Ctrl+J 也可以换行
+            KeyCode::Enter if newline => self.input.insert('\n'),
+            KeyCode::Char('j') if ctrl => self.input.insert('\n'),
+            KeyCode::Enter => self.send(),
+            KeyCode::Char(c) if !ctrl => self.input.insert(c),
+            KeyCode::Backspace => self.input.backspace(),
+            KeyCode::Delete => self.input.delete(),
+            KeyCode::Left => self.input.left(),
+            KeyCode::Right => self.input.right(),
+            KeyCode::Home => self.input.home(),
+            KeyCode::End => self.input.end(),
+            // 在第一行和最后一行继续按方向键时滚动对话
+            KeyCode::Up if !self.input.up() => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::Down if !self.input.down() => self.scroll = self.scroll.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn select_relative(&mut self, step: isize) {
+        let ids: Vec<String> = self
+            .sessions()
+            .iter()
+            .map(|session| session.id.clone())
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        let index = match self
+            .current
+            .as_ref()
+            .and_then(|id| ids.iter().position(|other| other == id))
+        {
+            Some(index) => (index as isize + step).clamp(0, ids.len() as isize - 1) as usize,
+            None => 0,
+        };
+        self.current = Some(ids[index].clone());
+        self.scroll = 0;
+    }
+
+    fn new_chat(&mut self) {
+        self.current = None;
+        self.focus = Focus::Input;
+        self.scroll = 0;
+    }
+
+    fn delete_session(&mut self, id: &str) {
+        if self
+            .streaming
+            .as_ref()
+            .is_some_and(|streaming| streaming.session_id == id)
+        {
+            self.stop();
+        }
+        if let Err(e) = self.context.sessions.delete_session(id) {
+            self.status = Some(format!("Failed to delete session: {}", e));
+        }
+        if self.current.as_deref() == Some(id) {
+            self.current = None;
+        }
+    }
+
+    /// 发送输入框中的消息，没有打开的会话时新建一个
+    fn send(&mut self) {
+        if self.input.is_empty() {
+            return;
+        }
+        if self.streaming.is_some() {
+            self.status = Some("Wait for the reply or press Esc to stop it".to_string());
+            return;
+        }
+
+        let id = match self.current.clone() {
+            Some(id) => id,
+            None => self.context.sessions.create_session("New Chat".to_string()),
+        };
+        let text = self.input.text().trim_end().to_string();
+        let Some(session) = self.context.sessions.get_session_mut(&id) else {
+            return;
+        };
+        session.add_message(Message::new(Role::User, MessageContent::Text(text)));
+        let history = session.request_messages();
+        let overrides = session.settings.overrides.clone();
+        self.context.sessions.persist_changes();
+
+        self.input.clear();
+        self.current = Some(id.clone());
+        self.scroll = 0;
+        self.status = None;
+
+        self.next_request += 1;
+        let request = self.next_request;
+        let client = self.client.clone();
+        let events = self.events.clone();
+        info!(id = %id, "Sending message");
+        let task = tokio::spawn(async move {
+            match client.send_message_streaming(&history, overrides).await {
+                Ok(mut rx) => {
+                    while let Some(message) = rx.recv().await {
+                        if events.send(AppEvent::Stream(request, message)).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let error = StreamMessage::Error(LLMError::classify(&e));
+                    let _ = events.send(AppEvent::Stream(request, error));
+                }
+            }
+        });
+        self.streaming = Some(Streaming {
+            id: request,
+            session_id: id,
+            content: String::new(),
+            task,
+        });
+    }
+
+    /// 停止正在进行的回复，已经收到的部分作为一条标记为停止的消息保留
+    fn stop(&mut self) {
+        let Some(streaming) = self.streaming.take() else {
+            return;
+        };
+        info!("Stopping streaming response");
+        streaming.task.abort();
+        if !streaming.content.is_empty() {
+            let mut message =
+                Message::new(Role::Assistant, MessageContent::Text(streaming.content));
+            message.stopped = true;
+            self.add_message(&streaming.session_id, message);
+        }
+    }
+
+    pub fn apply_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Stream(request, message) => {
+                if self
+                    .streaming
+                    .as_ref()
+                    .is_some_and(|streaming| streaming.id == request)
+                {
+                    self.apply_stream(message);
+                }
+            }
+            AppEvent::Title(id, result) => match result {
+                Ok(reply) => {
+                    if let Some(usage) = &reply.usage {
+                        if let Err(e) = self.context.ledger.record(LedgerEntry::new(&id, usage)) {
+                            warn!(?e, "Failed to record usage");
+                        }
+                    }
+                    if let Some(session) = self.context.sessions.get_session_mut(&id) {
+                        if !session.title_locked {
+                            session.rename(reply.text);
+                            self.context.sessions.persist_changes();
+                        }
+                    }
+                }
+                Err(e) => warn!(?e, id = %id, "Failed to generate session title"),
+            },
+        }
+    }
+
+    fn apply_stream(&mut self, message: StreamMessage) {
+        let Some(streaming) = &mut self.streaming else {
+            return;
+        };
+        match message {
+            StreamMessage::Chunk(chunk) => {
+                self.status = None;
+                streaming.content.push_str(&chunk);
+            }
+            StreamMessage::Intermediate(message) => {
+                // 已经流式显示的文字会包含在这条消息中
+                streaming.content.clear();
+                let id = streaming.session_id.clone();
+                self.add_message(&id, message);
+            }
+            StreamMessage::Done(message) => {
+                let id = streaming.session_id.clone();
+                self.streaming = None;
+                self.status = None;
+                if let Some(usage) = &message.usage {
+                    if let Err(e) = self.context.ledger.record(LedgerEntry::new(&id, usage)) {
+                        warn!(?e, "Failed to record usage");
+                    }
+                }
+                self.add_message(&id, message);
+                self.request_title(&id);
+            }
+            StreamMessage::Retrying {
+                error,
+                attempt,
+                delay,
+            } => {
+                // 这一轮会重新请求，丢弃已经显示的部分回复
+                streaming.content.clear();
+                self.status = Some(format!(
+                    "{} — retrying in {:.0}s (attempt {})",
+                    error.hint(),
+                    delay.as_secs_f32().ceil(),
+                    attempt
+                ));
+            }
+            StreamMessage::Error(error) => {
+                self.streaming = None;
+                self.status = Some(error.to_string());
+            }
+        }
+    }
+
+    fn add_message(&mut self, id: &str, message: Message) {
+        if let Some(session) = self.context.sessions.get_session_mut(id) {
+            session.add_message(message);
+            self.context.sessions.persist_changes();
+        }
+    }
+
+    /// 在后台为会话生成标题
+    fn request_title(&self, id: &str) {
+        let prompt = self.context.config.title_prompt.trim().to_string();
+        let Some(session) = self.context.sessions.get_session(id) else {
+            return;
+        };
+        if prompt.is_empty() || session.title_locked {
+            return;
+        }
+
+        let client = self.client.clone();
+        let messages = session.messages.clone();
+        let events = self.events.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            let result = title::generate_title(&client, &messages, &prompt).await;
+            let _ = events.send(AppEvent::Title(id, result));
+        });
+    }
+}
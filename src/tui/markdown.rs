@@ -0,0 +1,237 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use std::mem;
+
+/// 把 Markdown 转换成终端中显示的行
+///
+/// 只处理标题、强调、代码、列表、引用和链接；软换行按原样换行，
+/// 表格等其他结构显示为原始文字。
+pub fn render(text: &str) -> Vec<Line<'static>> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut writer = Writer::default();
+    for event in Parser::new_ext(text, options) {
+        writer.event(event);
+    }
+    writer.finish()
+}
+
+#[derive(Default)]
+struct Writer {
+    lines: Vec<Line<'static>>,
+    // 正在拼接的一行
+    spans: Vec<Span<'static>>,
+    // 嵌套的行内样式
+    styles: Vec<Style>,
+    // 每层列表下一个条目的编号，无序列表为 None
+    lists: Vec<Option<u64>>,
+    // 条目的第一行要显示的符号
+    marker: Option<String>,
+    quote_depth: usize,
+    in_code_block: bool,
+    // 正在处理的链接地址和链接文字
+    link: Option<(String, String)>,
+}
+
+impl Writer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                for line in text.lines() {
+                    self.push(Span::styled(format!("  {}", line), code_style()));
+                    self.flush();
+                }
+            }
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                if let Some((_, link_text)) = &mut self.link {
+                    link_text.push_str(&text);
+                }
+                let style = self.style();
+                for (index, part) in text.split('\n').enumerate() {
+                    if index > 0 {
+                        self.flush();
+                    }
+                    if !part.is_empty() {
+                        self.push(Span::styled(part.to_string(), style));
+                    }
+                }
+            }
+            Event::Code(code) => {
+                if let Some((_, link_text)) = &mut self.link {
+                    link_text.push_str(&code);
+                }
+                self.push(Span::styled(code.to_string(), code_style()));
+            }
+            Event::SoftBreak | Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                self.lines.push(Line::styled("─".repeat(24), dim()));
+                self.blank();
+            }
+            Event::TaskListMarker(checked) => {
+                self.push(Span::raw(if checked { "[x] " } else { "[ ] " }));
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush();
+                let style = Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+                self.styles.push(match level {
+                    HeadingLevel::H1 => style.add_modifier(Modifier::UNDERLINED),
+                    _ => style,
+                });
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                self.in_code_block = true;
+                if let CodeBlockKind::Fenced(language) = kind {
+                    if !language.is_empty() {
+                        self.lines
+                            .push(Line::styled(format!("  {}", language), dim()));
+                    }
+                }
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.marker = Some(marker);
+            }
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth += 1;
+            }
+            Tag::Emphasis => self.patch_style(Style::new().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.patch_style(Style::new().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => {
+                self.patch_style(Style::new().add_modifier(Modifier::CROSSED_OUT))
+            }
+            Tag::Link { dest_url, .. } => {
+                self.patch_style(
+                    Style::new()
+                        .fg(Color::Blue)
+                        .add_modifier(Modifier::UNDERLINED),
+                );
+                self.link = Some((dest_url.to_string(), String::new()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush();
+                // 列表中的段落之间不留空行
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.flush();
+                self.blank();
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                self.blank();
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Item => self.flush(),
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.blank();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                // 链接文字不是地址本身时在后面附上地址
+                if let Some((url, text)) = self.link.take() {
+                    if text != url {
+                        self.push(Span::styled(format!(" ({})", url), dim()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    fn patch_style(&mut self, style: Style) {
+        self.styles.push(self.style().patch(style));
+    }
+
+    /// 加入一段文字，行首先加上引用线、缩进和列表符号
+    fn push(&mut self, span: Span<'static>) {
+        if self.spans.is_empty() {
+            if self.quote_depth > 0 {
+                self.spans
+                    .push(Span::styled("│ ".repeat(self.quote_depth), dim()));
+            }
+            if !self.lists.is_empty() {
+                self.spans
+                    .push(Span::raw("  ".repeat(self.lists.len() - 1)));
+                let marker = self.marker.take().unwrap_or_else(|| "  ".to_string());
+                self.spans.push(Span::styled(marker, dim()));
+            }
+        }
+        self.spans.push(span);
+    }
+
+    fn flush(&mut self) {
+        if !self.spans.is_empty() {
+            self.lines.push(Line::from(mem::take(&mut self.spans)));
+        }
+    }
+
+    /// 块之间的空行，不会连续出现
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|line| line.spans.is_empty()) || self.lines.is_empty() {
+            return;
+        }
+        self.lines.push(Line::default());
+    }
+
+    fn finish(mut self) -> Vec<Line<'static>> {
+        self.flush();
+        while self.lines.last().is_some_and(|line| line.spans.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+fn code_style() -> Style {
+    Style::new().fg(Color::Yellow)
+}
+
+fn dim() -> Style {
+    Style::new().fg(Color::DarkGray)
+}
@@ -0,0 +1,49 @@
+mod app;
+mod input;
+mod markdown;
+mod view;
+
+use crate::cli::Context;
+use anyhow::Result;
+use app::{AppEvent, TuiApp};
+use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste, EventStream};
+use crossterm::execute;
+use futures::StreamExt;
+use ratatui::DefaultTerminal;
+use std::io;
+use tokio::sync::mpsc;
+
+/// 全屏终端界面，与图形界面共用会话、配置和用量账本
+pub(crate) async fn run(context: Context, profile: Option<&str>) -> Result<()> {
+    let client = context.client(profile)?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut app = TuiApp::new(context, client, tx);
+
+    let mut terminal = ratatui::try_init()?;
+    // 粘贴的多行文字作为一个事件收到，不会被换行符提前发送
+    execute!(io::stdout(), EnableBracketedPaste)?;
+    let result = event_loop(&mut terminal, &mut app, &mut rx).await;
+    // 出错时也要恢复终端
+    let _ = execute!(io::stdout(), DisableBracketedPaste);
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut TuiApp,
+    rx: &mut mpsc::UnboundedReceiver<AppEvent>,
+) -> Result<()> {
+    let mut events = EventStream::new();
+    while !app.quit {
+        terminal.draw(|frame| view::draw(frame, app))?;
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => app.handle_event(event?),
+                None => break,
+            },
+            Some(event) = rx.recv() => app.apply_event(event),
+        }
+    }
+    Ok(())
+}
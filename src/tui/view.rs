@@ -0,0 +1,290 @@
+use super::app::{Focus, TuiApp};
+use super::markdown;
+use crate::chat::ChatSession;
+use crate::llm::{Message, MessageContent, Role};
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+/// 输入框最多显示的行数，更多的内容需要滚动
+const MAX_INPUT_LINES: u16 = 8;
+/// 工具结果最多显示的行数
+const TOOL_RESULT_LINES: usize = 3;
+
+pub fn draw(frame: &mut Frame, app: &mut TuiApp) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [sessions, chat] =
+        Layout::horizontal([Constraint::Length(30), Constraint::Min(20)]).areas(main);
+    let input_height = saturate(app.input.line_count()).clamp(1, MAX_INPUT_LINES) + 2;
+    let [history, input] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(input_height)]).areas(chat);
+
+    draw_sessions(frame, app, sessions);
+    draw_history(frame, app, history);
+    draw_input(frame, app, input);
+    draw_status(frame, app, status);
+}
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused {
+        Style::new().fg(Color::Cyan)
+    } else {
+        Style::new().fg(Color::DarkGray)
+    };
+    Block::bordered().title(title).border_style(style)
+}
+
+fn dim() -> Style {
+    Style::new().fg(Color::DarkGray)
+}
+
+fn draw_sessions(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let streaming = app
+        .streaming
+        .as_ref()
+        .map(|streaming| streaming.session_id.as_str());
+    let sessions = app.sessions();
+    let items: Vec<ListItem> = sessions
+        .iter()
+        .map(|session| {
+            let marker = if Some(session.id.as_str()) == streaming {
+                "… "
+            } else {
+                ""
+            };
+            ListItem::new(format!("{}{}", marker, session.title))
+        })
+        .collect();
+
+    let mut state = ListState::default().with_selected(
+        app.current
+            .as_ref()
+            .and_then(|id| sessions.iter().position(|session| session.id == *id)),
+    );
+    let list = List::new(items)
+        .block(block("Sessions".to_string(), app.focus == Focus::Sessions))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_history(frame: &mut Frame, app: &mut TuiApp, area: Rect) {
+    let session = app.current_session();
+    let title = session.map_or("New Chat".to_string(), |session| session.title.clone());
+    let streaming = app
+        .streaming
+        .as_ref()
+        .filter(|streaming| app.current.as_ref() == Some(&streaming.session_id))
+        .map(|streaming| streaming.content.as_str());
+
+    let mut lines = match session {
+        Some(session) => session_lines(session),
+        None => Vec::new(),
+    };
+    if let Some(content) = streaming {
+        lines.push(header(&Role::Assistant, None));
+        if content.is_empty() {
+            lines.push(Line::styled("…", dim()));
+        } else {
+            lines.extend(markdown::render(content));
+            if let Some(last) = lines.last_mut() {
+                last.push_span(Span::styled("▌", dim()));
+            }
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Line::styled(
+            "Type a message and press Enter to start chatting",
+            dim(),
+        ));
+    }
+
+    // 根据换行后的总行数计算从顶部开始的偏移
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    let inner = Block::bordered().inner(area);
+    let total = saturate(paragraph.line_count(inner.width));
+    let max_scroll = total.saturating_sub(inner.height);
+    app.scroll = app.scroll.min(max_scroll);
+
+    let mut block = block(title, false);
+    if app.scroll > 0 {
+        block = block.title_bottom(Line::styled(
+            format!(" ↓ {} more lines ", app.scroll),
+            dim(),
+        ));
+    }
+    frame.render_widget(
+        paragraph.block(block).scroll((max_scroll - app.scroll, 0)),
+        area,
+    );
+}
+
+fn draw_input(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let focused = app.focus == Focus::Input;
+    let block = block("Message".to_string(), focused);
+    let inner = block.inner(area);
+
+    if app.input.text().is_empty() {
+        let hint = Paragraph::new(Line::styled(
+            "Enter to send · Alt+Enter for a new line",
+            dim(),
+        ));
+        frame.render_widget(hint.block(block), area);
+    } else {
+        // 让光标始终留在可见范围内
+        let (row, column) = app.input.cursor_position();
+        let top = saturate(row).saturating_sub(inner.height.saturating_sub(1));
+        let left = saturate(column).saturating_sub(inner.width.saturating_sub(1));
+        frame.render_widget(
+            Paragraph::new(app.input.text())
+                .block(block)
+                .scroll((top, left)),
+            area,
+        );
+    }
+
+    if focused {
+        let (row, column) = app.input.cursor_position();
+        frame.set_cursor_position(Position::new(
+            inner.x + saturate(column).min(inner.width.saturating_sub(1)),
+            inner.y + saturate(row).min(inner.height.saturating_sub(1)),
+        ));
+    }
+}
+
+fn draw_status(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let line = match &app.status {
+        Some(status) => Line::styled(status.clone(), Style::new().fg(Color::Yellow)),
+        None => {
+            let help = match app.focus {
+                Focus::Input => {
+                    "Enter send · Tab sessions · PgUp/PgDn scroll · Ctrl+N new chat · Ctrl+C quit"
+                }
+                Focus::Sessions => {
+                    "↑/↓ select · Enter open · n new chat · d delete · Tab message · Ctrl+C quit"
+                }
+            };
+            let mut spans = Vec::new();
+            if app.streaming.is_some() {
+                spans.push(Span::styled(
+                    "Generating… Esc to stop · ",
+                    Style::new().fg(Color::Cyan),
+                ));
+            }
+            spans.push(Span::styled(help, dim()));
+            Line::from(spans)
+        }
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+/// 会话当前分支上的全部消息
+fn session_lines(session: &ChatSession) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    for message in &session.messages {
+        message_lines(&mut lines, message);
+        lines.push(Line::default());
+    }
+    lines
+}
+
+fn message_lines(lines: &mut Vec<Line<'static>>, message: &Message) {
+    match &message.content {
+        MessageContent::Text(text) => {
+            lines.push(header(&message.role, Some(message)));
+            match message.role {
+                Role::Assistant => lines.extend(markdown::render(text)),
+                _ => lines.extend(text.lines().map(|line| Line::raw(line.to_string()))),
+            }
+        }
+        MessageContent::Image { text, url } => {
+            lines.push(header(&message.role, Some(message)));
+            lines.extend(text.lines().map(|line| Line::raw(line.to_string())));
+            // 内嵌的图片数据没有必要显示
+            let label = if url.starts_with("data:") {
+                "[image]".to_string()
+            } else {
+                format!("[image: {}]", url)
+            };
+            lines.push(Line::styled(label, dim()));
+        }
+        MessageContent::Function {
+            name, arguments, ..
+        } => {
+            lines.push(Line::styled(
+                format!("⚙ {}({})", name, truncate(&arguments.to_string(), 200)),
+                dim(),
+            ));
+        }
+        MessageContent::ToolResult { name, content, .. } => {
+            let mut result = content.lines();
+            lines.push(Line::styled(
+                format!("↳ {}: {}", name, result.next().unwrap_or_default()),
+                dim(),
+            ));
+            for line in result.by_ref().take(TOOL_RESULT_LINES - 1) {
+                lines.push(Line::styled(format!("  {}", line), dim()));
+            }
+            if result.next().is_some() {
+                lines.push(Line::styled("  …", dim()));
+            }
+        }
+        MessageContent::Summary { text, original } => {
+            lines.push(Line::styled(
+                format!("📝 Summary of {} earlier messages", original.len()),
+                dim().add_modifier(Modifier::ITALIC),
+            ));
+            lines.extend(markdown::render(text));
+        }
+    }
+    if message.stopped {
+        lines.push(Line::styled(
+            "(stopped)",
+            dim().add_modifier(Modifier::ITALIC),
+        ));
+    }
+}
+
+/// 消息标题行：角色、回复所用的模型和时间
+fn header(role: &Role, message: Option<&Message>) -> Line<'static> {
+    let (name, color) = match role {
+        Role::User => ("You", Color::Green),
+        Role::Assistant => ("Assistant", Color::Cyan),
+        Role::System => ("System", Color::Magenta),
+        Role::Tool => ("Tool", Color::Magenta),
+    };
+    let mut spans = vec![Span::styled(
+        name,
+        Style::new().fg(color).add_modifier(Modifier::BOLD),
+    )];
+    if let Some(message) = message {
+        if let Some(usage) = &message.usage {
+            spans.push(Span::styled(format!(" · {}", usage.model), dim()));
+        }
+        spans.push(Span::styled(
+            format!(
+                " · {}",
+                message
+                    .timestamp
+                    .with_timezone(&chrono::Local)
+                    .format("%H:%M")
+            ),
+            dim(),
+        ));
+    }
+    Line::from(spans)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+/// 行数和位置超出终端坐标的范围时取最大值，而不是截断
+fn saturate(value: usize) -> u16 {
+    u16::try_from(value).unwrap_or(u16::MAX)
+}
@@ -1,9 +1,22 @@
-use super::components::{chat::Chat, settings::Settings, sidebar::Sidebar};
-use super::state::UIState;
-use crate::chat::SessionManager;
-use crate::llm::{LLMClient, LLMConfig};
+use super::components::{Chat, Settings, Sidebar, UsagePanel};
+use super::state::{SettingsState, UIState};
+use crate::chat::export::ExportFormat;
+use crate::chat::ledger::{LedgerEntry, UsageLedger};
+use crate::chat::session::SessionSettings;
+use crate::chat::{summary, title, SessionManager, SessionStore};
+use crate::config::AppConfig;
+use crate::llm::context::ContextPolicy;
+use crate::llm::models::ModelCache;
+use crate::llm::{tokens, Completion, LLMClient, ToolRegistry, Usage};
+use chrono::{DateTime, Utc};
 use eframe::egui;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+type SummaryResult = (String, usize, DateTime<Utc>, anyhow::Result<Completion>);
 
 pub struct App {
     llm_client: LLMClient,
@@ -11,31 +24,310 @@ pub struct App {
     sidebar: Sidebar,
     chat: Chat,
     settings: Settings,
-    runtime: Arc<tokio::runtime::Runtime>,
     session_manager: SessionManager,
+    ledger: UsageLedger,
+    model_cache: ModelCache,
+    usage_panel: UsagePanel,
+    runtime: Arc<tokio::runtime::Runtime>,
+    settings_open: bool,
+    config_path: Option<PathBuf>,
+    // 后台生成的标题：(会话 id, 结果)
+    title_tx: mpsc::UnboundedSender<(String, anyhow::Result<Completion>)>,
+    title_rx: mpsc::UnboundedReceiver<(String, anyhow::Result<Completion>)>,
+    // 正在生成标题的会话
+    titles_pending: HashSet<String>,
+    // 后台生成的总结：(会话 id, 被总结的消息数, 最后一条被总结消息的时间, 结果)
+    summary_tx: mpsc::UnboundedSender<SummaryResult>,
+    summary_rx: mpsc::UnboundedReceiver<SummaryResult>,
+    // 正在总结的会话
+    summaries_pending: HashSet<String>,
+    // 当前搜索结果对应的查询
+    search_query: String,
 }
 
 impl App {
     pub fn new(
-        _cc: &eframe::CreationContext<'_>,
+        cc: &eframe::CreationContext<'_>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        egui_extras::install_image_loaders(&cc.egui_ctx);
         let runtime = Arc::new(tokio::runtime::Runtime::new()?);
-        let config = LLMConfig::default();
 
-        // 创建一个默认会话
-        let mut session_manager = SessionManager::new();
-        let _default_session_id = session_manager.create_session("New Chat".to_string());
+        // 读取配置文件，失败时使用内置的默认配置，并且不再写回这个文件，
+        // 避免保存设置时用默认配置覆盖用户手动编辑的内容
+        let mut config_path = AppConfig::default_path();
+        let mut config_error = None;
+        let app_config = match config_path.as_deref().map(AppConfig::load_or_create) {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                error!(?e, "Failed to load configuration file");
+                let path = config_path.take().expect("path checked above");
+                config_error = Some(format!(
+                    "Could not read {}, using the default settings; changes will not be saved: {:#}",
+                    path.display(),
+                    e
+                ));
+                AppConfig::default()
+            }
+            None => AppConfig::default(),
+        };
+        let active_profile = app_config.default_profile().clone();
+        info!(profile = %active_profile.name, "Using profile");
+
+        // 从磁盘恢复会话，失败时退回到仅内存保存
+        let mut session_manager = match SessionStore::default_dir()
+            .ok_or_else(|| anyhow::anyhow!("No data directory available"))
+            .and_then(SessionStore::new)
+            .and_then(|store| {
+                info!(dir = %store.dir().display(), "Using session store");
+                SessionManager::with_store(store)
+            }) {
+            Ok(manager) => manager,
+            Err(e) => {
+                error!(?e, "Failed to load saved sessions");
+                SessionManager::new()
+            }
+        };
+
+        // 读取用量账本，失败时只在内存中记录
+        let ledger = match UsageLedger::default_path()
+            .ok_or_else(|| anyhow::anyhow!("No data directory available"))
+            .and_then(UsageLedger::open)
+        {
+            Ok(ledger) => {
+                if let Some(path) = ledger.path() {
+                    info!(path = %path.display(), "Using usage ledger");
+                }
+                ledger
+            }
+            Err(e) => {
+                error!(?e, "Failed to load usage ledger");
+                UsageLedger::default()
+            }
+        };
+
+        // 读取模型列表缓存，失败时只在内存中缓存
+        let model_cache = match ModelCache::default_path()
+            .ok_or_else(|| anyhow::anyhow!("No data directory available"))
+            .and_then(ModelCache::open)
+        {
+            Ok(cache) => cache,
+            Err(e) => {
+                error!(?e, "Failed to load model cache");
+                ModelCache::default()
+            }
+        };
+
+        // 没有历史会话时创建一个默认会话
+        if session_manager.get_current_session().is_none() {
+            session_manager.create_session("New Chat".to_string());
+        }
+
+        let settings = SettingsState {
+            config: app_config,
+            active_profile: active_profile.name.clone(),
+        };
+        let mut state = UIState {
+            settings: settings.clone(),
+            config_error,
+            ..Default::default()
+        };
+        state.budget_warning = budget_warning(&ledger, &state.settings.config);
+
+        let (title_tx, title_rx) = mpsc::unbounded_channel();
+        let (summary_tx, summary_rx) = mpsc::unbounded_channel();
 
         Ok(Self {
-            llm_client: LLMClient::new(config),
-            state: UIState::default(),
+            llm_client: LLMClient::new(active_profile.to_llm_config())
+                .with_tools(ToolRegistry::with_builtin_tools()),
+            state,
             sidebar: Sidebar::new(),
-            chat: Chat::new(),
-            settings: Settings::default(),
-            runtime,
+            chat: Chat::new(runtime.clone()),
+            settings: Settings::new(settings, runtime.clone()),
             session_manager,
+            ledger,
+            model_cache,
+            usage_panel: UsagePanel::new(),
+            runtime,
+            settings_open: false,
+            config_path,
+            title_tx,
+            title_rx,
+            titles_pending: HashSet::new(),
+            summary_tx,
+            summary_rx,
+            summaries_pending: HashSet::new(),
+            search_query: String::new(),
         })
     }
+
+    /// 把一次请求的用量记入账本，并更新预算提示
+    fn record_usage(&mut self, id: &str, usage: &Usage) {
+        if let Err(e) = self.ledger.record(LedgerEntry::new(id, usage)) {
+            error!(?e, "Failed to record usage");
+        }
+        self.state.budget_warning = budget_warning(&self.ledger, &self.state.settings.config);
+    }
+
+    /// 在后台为会话生成标题
+    fn request_title(&mut self, ctx: &egui::Context, id: String) {
+        let prompt = self.state.settings.config.title_prompt.trim().to_string();
+        let Some(session) = self.session_manager.get_session(&id) else {
+            return;
+        };
+        if prompt.is_empty() || session.title_locked || !self.titles_pending.insert(id.clone()) {
+            return;
+        }
+
+        info!(id = %id, "Generating session title");
+        let client = self.llm_client.clone();
+        let messages = session.messages.clone();
+        let tx = self.title_tx.clone();
+        let ctx = ctx.clone();
+        self.runtime.spawn(async move {
+            let result = title::generate_title(&client, &messages, &prompt).await;
+            let _ = tx.send((id, result));
+            ctx.request_repaint();
+        });
+    }
+
+    /// 会话接近上下文长度时，在后台把较早的消息总结成一条消息
+    fn request_summary(&mut self, ctx: &egui::Context, id: String) {
+        let profile = self.state.settings.active_profile();
+        if profile.context_policy != ContextPolicy::Summarize {
+            return;
+        }
+        let Some(session) = self.session_manager.get_session(&id) else {
+            return;
+        };
+
+        let model = session
+            .settings
+            .overrides
+            .model
+            .clone()
+            .unwrap_or_else(|| profile.model.clone());
+        let budget = profile
+            .to_llm_config()
+            .context_window(&model)
+            .saturating_sub(profile.max_tokens as usize);
+        let tokenizer = tokens::tokenizer_for_model(&model);
+        let Some(count) = summary::summary_cut(session, tokenizer.as_ref(), budget) else {
+            return;
+        };
+        if !self.summaries_pending.insert(id.clone()) {
+            return;
+        }
+
+        info!(id = %id, count, "Summarizing earlier messages");
+        let client = self.llm_client.clone();
+        let messages: Vec<_> = session.messages.iter().take(count).cloned().collect();
+        let last = messages[count - 1].timestamp;
+        let tx = self.summary_tx.clone();
+        let ctx = ctx.clone();
+        self.runtime.spawn(async move {
+            let result = summary::summarize(&client, &messages).await;
+            let _ = tx.send((id, count, last, result));
+            ctx.request_repaint();
+        });
+    }
+
+    /// 应用已经生成好的总结，被总结的消息在此期间改变过时放弃
+    fn apply_summaries(&mut self) {
+        while let Ok((id, count, last, result)) = self.summary_rx.try_recv() {
+            self.summaries_pending.remove(&id);
+            let text = match result {
+                Ok(reply) => {
+                    // 总结没有用上也已经产生了费用
+                    if let Some(usage) = &reply.usage {
+                        self.record_usage(&id, usage);
+                    }
+                    reply.text
+                }
+                Err(e) => {
+                    error!(?e, id = %id, "Failed to summarize session");
+                    continue;
+                }
+            };
+            let Some(session) = self.session_manager.get_session_mut(&id) else {
+                continue;
+            };
+            if session
+                .messages
+                .get(count - 1)
+                .is_some_and(|message| message.timestamp == last)
+            {
+                session.summarize_prefix(count, text);
+            }
+        }
+    }
+
+    /// 选择保存位置并导出会话
+    fn export_session(&self, id: &str, format: ExportFormat) -> anyhow::Result<()> {
+        let Some(session) = self.session_manager.get_session(id) else {
+            return Ok(());
+        };
+        let file_name: String = session
+            .title
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == ' ' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(format!("{}.{}", file_name.trim(), format.extension()))
+            .add_filter(format.label(), &[format.extension()])
+            .save_file()
+        else {
+            return Ok(());
+        };
+
+        let contents = self.session_manager.export_session(id, format)?;
+        std::fs::write(&path, contents)?;
+        info!(path = %path.display(), "Exported session");
+        Ok(())
+    }
+
+    /// 选择文件并导入其中的会话
+    fn import_sessions(&mut self) -> anyhow::Result<()> {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+        else {
+            return Ok(());
+        };
+
+        let count = self
+            .session_manager
+            .import_sessions(&std::fs::read_to_string(&path)?)?;
+        info!(count, path = %path.display(), "Imported sessions");
+        Ok(())
+    }
+
+    /// 应用已经生成好的标题，用户在此期间手动改过名的会话保持不变
+    fn apply_titles(&mut self) {
+        while let Ok((id, result)) = self.title_rx.try_recv() {
+            self.titles_pending.remove(&id);
+            match result {
+                Ok(reply) => {
+                    if let Some(usage) = &reply.usage {
+                        self.record_usage(&id, usage);
+                    }
+                    if self
+                        .session_manager
+                        .get_session(&id)
+                        .is_some_and(|session| !session.title_locked)
+                    {
+                        let _ = self.session_manager.rename_session(&id, reply.text);
+                    }
+                }
+                Err(e) => error!(?e, id = %id, "Failed to generate session title"),
+            }
+        }
+    }
 }
 
 impl eframe::App for App {
@@ -46,10 +338,58 @@ impl eframe::App for App {
             self.state.new_chat_requested = false;
         }
 
+        if let Some(name) = self.state.new_chat_preset.take() {
+            if let Some(preset) = self.state.settings.config.preset(&name) {
+                self.session_manager.create_session(preset.name.clone());
+                if let Some(session) = self.session_manager.get_current_session_mut() {
+                    session.set_settings(SessionSettings::from_preset(preset));
+                }
+            }
+        }
+
         if let Some(id) = self.state.delete_chat_requested.take() {
-            let _ = self.session_manager.delete_session(&id);
+            if let Err(e) = self.session_manager.delete_session(&id) {
+                error!(?e, "Failed to delete session");
+            }
+            if self.session_manager.get_current_session().is_none() {
+                self.session_manager.create_session("New Chat".to_string());
+            }
+        }
+
+        if let Some((id, index)) = self.state.fork_requested.take() {
+            if let Err(e) = self.session_manager.fork_session(&id, index) {
+                error!(?e, "Failed to fork session");
+            }
+        }
+
+        if let Some((id, title)) = self.state.rename_requested.take() {
+            if let Err(e) = self.session_manager.rename_session(&id, title) {
+                error!(?e, "Failed to rename session");
+            }
+        }
+
+        if let Some((id, format)) = self.state.export_requested.take() {
+            if let Err(e) = self.export_session(&id, format) {
+                error!(?e, "Failed to export session");
+                self.state.chat_state.error = Some(format!("Export failed: {}", e));
+            }
         }
 
+        if std::mem::take(&mut self.state.import_requested) {
+            if let Err(e) = self.import_sessions() {
+                error!(?e, "Failed to import sessions");
+                self.state.chat_state.error = Some(format!("Import failed: {}", e));
+            }
+        }
+
+        self.apply_titles();
+        self.apply_summaries();
+
+        self.state.current_chat_id = self
+            .session_manager
+            .get_current_session()
+            .map(|session| session.id.clone());
+
         egui::SidePanel::left("sidebar")
             .default_width(200.0)
             .show(ctx, |ui| {
@@ -60,22 +400,119 @@ impl eframe::App for App {
                 );
             });
 
+        // 查询或消息有变化时重新搜索
+        let index_changed = self.session_manager.refresh_index();
+        if index_changed || self.state.search_query != self.search_query {
+            self.search_query = self.state.search_query.clone();
+            self.state.search_results = if self.search_query.trim().is_empty() {
+                Vec::new()
+            } else {
+                self.session_manager.search(&self.search_query)
+            };
+            ctx.request_repaint();
+        }
+
+        // 侧边栏中切换了会话
+        if let Some(id) = self.state.current_chat_id.clone() {
+            if self.session_manager.get_current_session().map(|s| &s.id) != Some(&id) {
+                let _ = self.session_manager.switch_session(id);
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             if let Some(session) = self.session_manager.get_current_session_mut() {
-                self.chat
-                    .ui(ui, &mut self.state, self.llm_client.clone(), session);
+                let models = self
+                    .model_cache
+                    .models(self.state.settings.active_profile());
+                self.chat.ui(
+                    ui,
+                    &mut self.state,
+                    self.llm_client.clone(),
+                    session,
+                    models,
+                );
             }
         });
 
+        // 流式回复写入发出请求的会话，不一定是当前显示的会话
+        self.chat
+            .process_stream(&mut self.state, &mut self.session_manager);
+
+        if let Some((profile, models)) = self.state.models_fetched.take() {
+            info!(profile = %profile.name, count = models.len(), "Listed models");
+            if let Err(e) = self.model_cache.insert(&profile, models) {
+                error!(?e, "Failed to save model cache");
+            }
+        }
+
+        if let Some((id, usage)) = self.state.usage_to_record.take() {
+            self.record_usage(&id, &usage);
+        }
+
+        if let Some(id) = self.state.title_requested.take() {
+            self.request_title(ctx, id);
+        }
+        if let Some(id) = self.state.summary_requested.take() {
+            self.request_summary(ctx, id);
+        }
+
+        // 打开设置窗口时从正在使用的配置开始编辑
+        if self.state.show_settings && !self.settings_open {
+            self.settings.reset(&self.state.settings);
+        }
+        self.settings_open = self.state.show_settings;
+
         // 设置窗口
         if self.state.show_settings {
             let mut show_settings = self.state.show_settings;
             egui::Window::new("Settings")
                 .open(&mut show_settings)
                 .show(ctx, |ui| {
-                    self.settings.ui(ui, &mut self.state);
+                    self.settings.ui(ui, &mut self.state, &self.model_cache);
                 });
             self.state.show_settings = show_settings;
         }
+
+        if self.state.show_usage {
+            let mut show_usage = self.state.show_usage;
+            egui::Window::new("Usage")
+                .open(&mut show_usage)
+                .show(ctx, |ui| {
+                    self.usage_panel.ui(
+                        ui,
+                        &self.ledger,
+                        &self.state.settings.config,
+                        &self.session_manager.get_all_sessions(),
+                    );
+                });
+            self.state.show_usage = show_usage;
+        }
+
+        // 保存设置后写回配置文件，并重新配置正在使用的客户端
+        if std::mem::take(&mut self.state.settings_saved) {
+            self.state.budget_warning = budget_warning(&self.ledger, &self.state.settings.config);
+            if let Some(path) = &self.config_path {
+                if let Err(e) = self.state.settings.config.save(path) {
+                    error!(?e, "Failed to save configuration file");
+                }
+            }
+
+            let config = self.state.settings.active_profile().to_llm_config();
+            self.runtime.block_on(self.llm_client.update_config(config));
+        }
+
+        self.session_manager.persist_changes();
     }
 }
+
+/// 本月费用达到预算时的提示
+fn budget_warning(ledger: &UsageLedger, config: &AppConfig) -> Option<String> {
+    let budget = config.monthly_budget.filter(|budget| *budget > 0.0)?;
+    let spent = ledger.month_total(&config.prices).cost;
+    (spent >= budget).then(|| {
+        format!(
+            "⚠ ${:.2} spent this month, over the ${:.2} budget",
+            spent, budget
+        )
+    })
+}
@@ -1,37 +1,71 @@
 use eframe::egui::{self, ScrollArea, Ui};
-use std::{collections::VecDeque, sync::Arc};
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
 use tokio::sync::mpsc;
-use tokio::sync::watch;
-use tracing::{debug, error, info, warn};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
 
 use crate::{
-    chat::ChatSession,
+    chat::{session::SessionSettings, ChatSession, SessionManager},
     llm::{
-        client::LLMClient,
-        message::{Message, MessageContent, Role, StreamMessage},
+        image,
+        provider::ModelInfo,
+        tokens::{self, TokenCounter, Tokenizer},
+        LLMClient, LLMError, Message, MessageContent, Role, StreamMessage,
+    },
+    ui::{
+        components::{
+            markdown::Markdown, model_picker::optional_model_picker, settings::optional_temperature,
+        },
+        state::UIState,
     },
-    ui::state::UIState,
 };
 
-pub enum ChatMessage {
-    StreamChunk(String),
-    // 其他消息类型...
+/// 对历史消息的操作，在渲染完消息列表后执行
+enum MessageAction {
+    SwitchVariant { index: usize, target: usize },
+    StartEdit(usize),
+    CancelEdit,
+    SubmitEdit(usize),
+    Regenerate,
+    Fork(usize),
 }
 
 pub struct Chat {
-    messages: Vec<Message>,
     streaming_content: Option<String>,
     response_rx: Option<mpsc::Receiver<StreamMessage>>,
+    // 发出请求的会话，回复写入这个会话而不是当前显示的会话
+    streaming_session: Option<String>,
+    // 界面上点击了停止，在处理流时执行
+    stop_requested: bool,
+    // 正在进行的请求，停止时中止它
+    task: Option<JoinHandle<()>>,
+    // 正在编辑的用户消息位置和内容
+    editing: Option<(usize, String)>,
     runtime: Arc<tokio::runtime::Runtime>,
+    // 已经解码并注册到 egui 的 data URL 图片
+    loaded_images: HashSet<u64>,
+    // 解码失败的 data URL 图片，不再重复解码
+    invalid_images: HashSet<u64>,
+    markdown: Markdown,
+    token_counter: TokenCounter,
 }
 
 impl Chat {
-    pub fn new() -> Self {
+    pub fn new(runtime: Arc<tokio::runtime::Runtime>) -> Self {
         Self {
-            messages: Vec::new(),
             streaming_content: None,
             response_rx: None,
-            runtime: Arc::new(tokio::runtime::Runtime::new().expect("Failed to create runtime")),
+            streaming_session: None,
+            stop_requested: false,
+            task: None,
+            editing: None,
+            runtime,
+            loaded_images: HashSet::new(),
+            invalid_images: HashSet::new(),
+            markdown: Markdown::default(),
+            token_counter: TokenCounter::default(),
         }
     }
 
@@ -41,42 +75,114 @@ impl Chat {
         state: &mut UIState,
         client: LLMClient,
         session: &mut ChatSession,
+        models: &[ModelInfo],
     ) {
+        session_settings_ui(ui, session, models);
+
+        // 按会话实际使用的模型计算 token 数
+        let model = session
+            .settings
+            .overrides
+            .model
+            .clone()
+            .unwrap_or_else(|| state.settings.active_profile().model.clone());
+        let tokenizer = tokens::tokenizer_for_model(&model);
+        let token_counts: Vec<usize> = session
+            .messages
+            .iter()
+            .map(|message| self.token_counter.count(tokenizer.as_ref(), message))
+            .collect();
+
         let available_height = ui.available_height();
-        let input_area_height = 100.0;
+        let input_area_height = if state.chat_attachment.is_some() {
+            240.0
+        } else {
+            150.0
+        };
 
         ui.vertical(|ui| {
+            let mut action = None;
+
             // 聊天历史记录区域
             ScrollArea::vertical()
                 .auto_shrink([false; 2])
                 .stick_to_bottom(true)
                 .max_height(available_height - input_area_height)
                 .show(ui, |ui| {
+                    let is_sending = state.chat_state.is_sending;
+                    let last_index = session.messages.len().saturating_sub(1);
+
                     // 显示历史消息
-                    for message in &session.messages {
-                        self.render_message(ui, message);
+                    for (index, message) in session.messages.iter().enumerate() {
+                        match &mut self.editing {
+                            Some((edit_index, text)) if *edit_index == index => {
+                                action = edit_box(ui, index, text, is_sending).or(action.take());
+                            }
+                            _ => {
+                                let response =
+                                    self.render_message(ui, message, Some(token_counts[index]));
+                                if state.scroll_to_message == Some(index) {
+                                    response.scroll_to_me(Some(egui::Align::TOP));
+                                }
+                                let can_regenerate =
+                                    index == last_index && !matches!(message.role, Role::User);
+                                action = message_controls(
+                                    ui,
+                                    session,
+                                    index,
+                                    message,
+                                    can_regenerate,
+                                    is_sending,
+                                )
+                                .or(action.take());
+                            }
+                        }
                         ui.add_space(8.0);
                     }
 
+                    state.scroll_to_message = None;
+
+                    // 当前分支为空时（例如重新生成失败）也要能切换回其他分支
+                    if !is_sending {
+                        ui.horizontal(|ui| {
+                            action = variant_switcher(ui, session, session.messages.len())
+                                .or(action.take());
+                        });
+                    }
+
                     // 显示正在流式传输的消息
-                    if let Some(content) = &self.streaming_content {
+                    let streaming_here = self.streaming_session.as_ref() == Some(&session.id);
+                    if let Some(content) = self.streaming_content.clone().filter(|_| streaming_here)
+                    {
                         self.render_message(
                             ui,
-                            &Message {
-                                role: Role::Assistant,
-                                content: MessageContent::Text(content.clone()),
-                                timestamp: chrono::Utc::now(),
-                            },
+                            &Message::new(Role::Assistant, MessageContent::Text(content)),
+                            None,
                         );
                     }
                 });
 
+            if let Some(action) = action {
+                self.apply_action(ui.ctx(), action, state, &client, session);
+            }
+
             ui.separator();
 
+            self.token_usage_ui(
+                ui,
+                state,
+                session,
+                tokenizer.as_ref(),
+                &model,
+                &token_counts,
+            );
+
             // 输入区域容器
             egui::Frame::none()
                 .fill(ui.style().visuals.window_fill())
                 .show(ui, |ui| {
+                    self.attachment_ui(ui, state);
+
                     ui.horizontal(|ui| {
                         let input_area = ui.available_width() - 60.0;
 
@@ -90,14 +196,20 @@ impl Chat {
                         let response = ui.add(text_edit);
 
                         ui.vertical(|ui| {
-                            let send_button = ui.add_enabled(
-                                !state.chat_input.is_empty() && !state.chat_state.is_sending,
-                                egui::Button::new(if state.chat_state.is_sending {
-                                    "⏳"
-                                } else {
-                                    "Send"
-                                }),
-                            );
+                            if state.chat_state.is_sending {
+                                let stop_button =
+                                    ui.button("⏹ Stop").on_hover_text("Stop generating (Esc)");
+                                if stop_button.clicked()
+                                    || ui.input(|i| i.key_pressed(egui::Key::Escape))
+                                {
+                                    self.stop_requested = true;
+                                }
+                                return;
+                            }
+
+                            let has_input =
+                                !state.chat_input.is_empty() || state.chat_attachment.is_some();
+                            let send_button = ui.add_enabled(has_input, egui::Button::new("Send"));
 
                             let mut should_send = false;
                             if response.lost_focus() {
@@ -109,7 +221,7 @@ impl Chat {
                                 } else if ui.input(|i| {
                                     !i.modifiers.command && i.key_pressed(egui::Key::Enter)
                                 }) {
-                                    should_send = !state.chat_input.is_empty();
+                                    should_send = has_input;
                                 }
                             }
 
@@ -117,56 +229,56 @@ impl Chat {
 
                             if should_send {
                                 info!("Preparing to send message");
-                                state.chat_state.is_sending = true;
-                                let message = Message {
-                                    role: Role::User,
-                                    content: MessageContent::Text(state.chat_input.clone()),
-                                    timestamp: chrono::Utc::now(),
+                                let content = match state.chat_attachment.take() {
+                                    Some(url) => MessageContent::Image {
+                                        text: state.chat_input.clone(),
+                                        url,
+                                    },
+                                    None => MessageContent::Text(state.chat_input.clone()),
                                 };
+                                let message = Message::new(Role::User, content);
 
-                                debug!(?message, "Created user message");
+                                log_user_message(&message);
                                 state.chat_input.clear();
-                                session.add_message(message.clone());
+                                session.add_message(message);
                                 info!("Added message to session");
-
-                                let (tx, rx) = mpsc::channel(10);
-                                self.response_rx = Some(rx);
-                                self.streaming_content = Some(String::new());
-                                debug!("Set up streaming channel");
-
-                                let client_clone = client.clone();
-                                let ctx = ui.ctx().clone();
-                                self.runtime.spawn(async move {
-                                    client_clone.set_response_tx(tx.clone()).await;
-
-                                    info!("Starting async message processing");
-                                    match client_clone.send_message_streaming(message).await {
-                                        Ok(mut stream_rx) => {
-                                            info!("Successfully created message stream");
-                                            while let Some(message) = stream_rx.recv().await {
-                                                debug!(?message, "Received stream message");
-                                                if let Err(e) = tx.send(message).await {
-                                                    error!(
-                                                        ?e,
-                                                        "Failed to send message through channel"
-                                                    );
-                                                    break;
-                                                }
-                                                ctx.request_repaint();
-                                            }
-                                            info!("Stream processing completed");
-                                        }
-                                        Err(e) => {
-                                            error!(?e, "Failed to create message stream");
-                                            let _ =
-                                                tx.send(StreamMessage::Error(e.to_string())).await;
-                                        }
-                                    }
-                                });
+                                self.start_request(ui.ctx(), state, &client, session);
                             }
                         });
                     });
 
+                    if let Some(error) = &state.config_error {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.label(
+                                egui::RichText::new("⚠ Configuration file could not be read")
+                                    .color(egui::Color32::RED),
+                            )
+                            .on_hover_text(error);
+                        });
+                    }
+
+                    if let Some(warning) = &state.budget_warning {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.label(egui::RichText::new(warning).color(egui::Color32::ORANGE));
+                        });
+                    }
+
+                    if let Some(status) = &state.chat_state.retry_status {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.label(egui::RichText::new(status).weak());
+                        });
+                    }
+
+                    if let Some(error) = &state.chat_state.api_error {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if error.needs_settings() && ui.button("Open Settings").clicked() {
+                                state.show_settings = true;
+                            }
+                            ui.label(egui::RichText::new(error.hint()).color(egui::Color32::RED))
+                                .on_hover_text(error.message());
+                        });
+                    }
+
                     if let Some(error) = &state.chat_state.error {
                         error!(?error, "Displaying error message");
                         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -175,60 +287,668 @@ impl Chat {
                     }
                 });
         });
+    }
 
-        // 处理流式响应
-        if let Some(rx) = &mut self.response_rx {
-            while let Ok(message) = rx.try_recv() {
-                match message {
-                    StreamMessage::Chunk(chunk) => {
-                        if let Some(content) = &mut self.streaming_content {
-                            content.push_str(&chunk);
+    /// 把当前分支上的全部消息发送给模型
+    fn start_request(
+        &mut self,
+        ctx: &egui::Context,
+        state: &mut UIState,
+        client: &LLMClient,
+        session: &ChatSession,
+    ) {
+        state.chat_state.is_sending = true;
+        state.chat_state.error = None;
+        state.chat_state.api_error = None;
+        state.chat_state.retry_status = None;
+        let history = session.request_messages();
+        let overrides = session.settings.overrides.clone();
+
+        // 同时只能有一个回复，之前的请求如果还在进行就中止它
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        let (tx, rx) = mpsc::channel(10);
+        self.response_rx = Some(rx);
+        self.streaming_session = Some(session.id.clone());
+        self.streaming_content = Some(String::new());
+        debug!("Set up streaming channel");
+
+        let client = client.clone();
+        let ctx = ctx.clone();
+        self.task = Some(self.runtime.spawn(async move {
+            info!("Starting async message processing");
+            match client.send_message_streaming(&history, overrides).await {
+                Ok(mut stream_rx) => {
+                    info!("Successfully created message stream");
+                    while let Some(message) = stream_rx.recv().await {
+                        log_stream_message(&message);
+                        if let Err(e) = tx.send(message).await {
+                            error!(?e, "Failed to send message through channel");
+                            break;
                         }
+                        ctx.request_repaint();
                     }
-                    StreamMessage::Done(message) => {
-                        session.add_message(message);
-                        self.streaming_content = None;
-                        state.chat_state.is_sending = false;
-                    }
-                    StreamMessage::Error(error) => {
-                        error!(?error, "Stream error");
-                        self.streaming_content = None;
-                        state.chat_state.is_sending = false;
-                        state.chat_state.error = Some(error);
-                    }
+                    info!("Stream processing completed");
+                }
+                Err(e) => {
+                    error!(?e, "Failed to create message stream");
+                    let _ = tx.send(StreamMessage::Error(LLMError::classify(&e))).await;
+                    ctx.request_repaint();
+                }
+            }
+        }));
+    }
+
+    fn apply_action(
+        &mut self,
+        ctx: &egui::Context,
+        action: MessageAction,
+        state: &mut UIState,
+        client: &LLMClient,
+        session: &mut ChatSession,
+    ) {
+        match action {
+            MessageAction::SwitchVariant { index, target } => {
+                session.switch_variant(index, target);
+            }
+            MessageAction::StartEdit(index) => {
+                if let Some(message) = session.messages.get(index) {
+                    let text = match &message.content {
+                        MessageContent::Text(text) | MessageContent::Image { text, .. } => {
+                            text.clone()
+                        }
+                        _ => return,
+                    };
+                    self.editing = Some((index, text));
                 }
             }
+            MessageAction::CancelEdit => self.editing = None,
+            MessageAction::SubmitEdit(index) => {
+                if state.chat_state.is_sending {
+                    return;
+                }
+                let Some((_, text)) = self.editing.take() else {
+                    return;
+                };
+                let Some(original) = session.messages.get(index) else {
+                    return;
+                };
+                // 编辑后保留原消息中的图片
+                let content = match &original.content {
+                    MessageContent::Image { url, .. } => MessageContent::Image {
+                        text,
+                        url: url.clone(),
+                    },
+                    _ => MessageContent::Text(text),
+                };
+
+                info!(index, "Resending edited message");
+                session.branch_from(index);
+                session.add_message(Message::new(Role::User, content));
+                self.start_request(ctx, state, client, session);
+            }
+            MessageAction::Fork(index) => {
+                state.fork_requested = Some((session.id.clone(), index));
+            }
+            MessageAction::Regenerate => {
+                // 从最后一条用户消息之后开始重新生成
+                let index = session
+                    .messages
+                    .iter()
+                    .rposition(|message| matches!(message.role, Role::User))
+                    .map_or(0, |index| index + 1);
+
+                info!(index, "Regenerating reply");
+                session.branch_from(index);
+                self.start_request(ctx, state, client, session);
+            }
         }
     }
 
-    fn render_message(&self, ui: &mut Ui, message: &Message) {
-        ui.horizontal(|ui| {
-            match message.role {
-                Role::User => {
-                    ui.label("You: ");
+    /// 处理已经收到的流式响应，写入发出请求的会话
+    ///
+    /// 每帧在显示聊天界面之后调用；回复期间切换会话不影响回复写入的位置。
+    pub fn process_stream(&mut self, state: &mut UIState, sessions: &mut SessionManager) {
+        let Some(id) = self.streaming_session.clone() else {
+            return;
+        };
+        let Some(session) = sessions.get_session_mut(&id) else {
+            // 会话在回复期间被删除，回复已经没有去处
+            info!(id = %id, "Dropping reply for deleted session");
+            self.stop_requested = false;
+            self.cancel(state);
+            return;
+        };
+
+        if std::mem::take(&mut self.stop_requested) {
+            self.stop_streaming(state, session);
+        } else {
+            self.drain_stream(state, session);
+        }
+    }
+
+    fn drain_stream(&mut self, state: &mut UIState, session: &mut ChatSession) {
+        while let Some(message) = self.response_rx.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.handle_stream_message(message, state, session);
+        }
+    }
+
+    fn handle_stream_message(
+        &mut self,
+        message: StreamMessage,
+        state: &mut UIState,
+        session: &mut ChatSession,
+    ) {
+        match message {
+            StreamMessage::Chunk(chunk) => {
+                state.chat_state.retry_status = None;
+                if let Some(content) = &mut self.streaming_content {
+                    content.push_str(&chunk);
                 }
-                Role::Assistant => {
-                    ui.label("AI: ");
+            }
+            StreamMessage::Intermediate(message) => {
+                // 已经流式显示的文字会包含在这条消息中
+                session.add_message(message);
+                self.streaming_content = Some(String::new());
+            }
+            StreamMessage::Done(message) => {
+                if let Some(usage) = &message.usage {
+                    state.usage_to_record = Some((session.id.clone(), usage.clone()));
                 }
-                Role::System => {
-                    ui.label("System: ");
+                session.add_message(message);
+                if !session.title_locked {
+                    state.title_requested = Some(session.id.clone());
                 }
+                state.summary_requested = Some(session.id.clone());
+                self.finish(state);
+            }
+            StreamMessage::Retrying {
+                error,
+                attempt,
+                delay,
+            } => {
+                // 这一轮会重新请求，丢弃已经显示的部分回复
+                self.streaming_content = Some(String::new());
+                state.chat_state.retry_status = Some(format!(
+                    "{} — retrying in {:.0}s (attempt {})",
+                    error.hint(),
+                    delay.as_secs_f32().ceil(),
+                    attempt
+                ));
+            }
+            StreamMessage::Error(error) => {
+                error!(%error, "Stream error");
+                self.finish(state);
+                state.chat_state.api_error = Some(error);
             }
+        }
+    }
+
+    /// 停止正在进行的回复，已经收到的部分作为一条标记为停止的消息保留
+    fn stop_streaming(&mut self, state: &mut UIState, session: &mut ChatSession) {
+        info!("Stopping streaming response");
+        if let Some(task) = self.task.take() {
+            // 中止任务会关闭通道，客户端随之丢弃底层的 HTTP 流
+            task.abort();
+        }
+
+        // 通道中剩下的消息可能已经完成了回复
+        self.drain_stream(state, session);
+
+        if let Some(content) = self.streaming_content.take() {
+            if !content.is_empty() {
+                let mut message = Message::new(Role::Assistant, MessageContent::Text(content));
+                message.stopped = true;
+                session.add_message(message);
+            }
+        }
+        self.finish(state);
+    }
+
+    /// 丢弃正在进行的回复
+    fn cancel(&mut self, state: &mut UIState) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.finish(state);
+    }
+
+    /// 回复结束后清理流式状态
+    fn finish(&mut self, state: &mut UIState) {
+        self.response_rx = None;
+        self.streaming_content = None;
+        self.streaming_session = None;
+        self.task = None;
+        state.chat_state.is_sending = false;
+        state.chat_state.retry_status = None;
+    }
+
+    /// 图片附件：选择本地文件或填写远程 URL
+    fn attachment_ui(&mut self, ui: &mut Ui, state: &mut UIState) {
+        ui.horizontal(|ui| {
+            if ui.button("🖼 Attach image").clicked() {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Images", &["png", "jpg", "jpeg", "gif", "webp"])
+                    .pick_file()
+                {
+                    match image::data_url_from_file(&path) {
+                        Ok(url) => {
+                            state.chat_attachment = Some(url);
+                            state.chat_state.error = None;
+                        }
+                        Err(e) => {
+                            error!(?e, "Failed to read image");
+                            state.chat_state.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+
+            ui.add(
+                egui::TextEdit::singleline(&mut state.image_url_input)
+                    .hint_text("or image URL...")
+                    .desired_width(240.0),
+            );
+            if ui
+                .add_enabled(
+                    !state.image_url_input.trim().is_empty(),
+                    egui::Button::new("Add"),
+                )
+                .clicked()
+            {
+                state.chat_attachment = Some(state.image_url_input.trim().to_string());
+                state.image_url_input.clear();
+            }
+        });
+
+        if let Some(url) = state.chat_attachment.clone() {
+            ui.horizontal(|ui| {
+                self.show_image(ui, &url, 80.0);
+                if ui.small_button("✖").on_hover_text("Remove image").clicked() {
+                    state.chat_attachment = None;
+                }
+            });
+        }
+    }
+
+    fn render_message(
+        &mut self,
+        ui: &mut Ui,
+        message: &Message,
+        token_count: Option<usize>,
+    ) -> egui::Response {
+        let role = match message.role {
+            Role::User => "You",
+            Role::Assistant => "AI",
+            Role::System => "System",
+            Role::Tool => "Tool",
+        };
+
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(role).strong());
+                if let Some(count) = token_count {
+                    let label = ui.label(
+                        egui::RichText::new(format!("{} tokens", count))
+                            .small()
+                            .weak(),
+                    );
+                    // 后端返回的实际用量，包括这次回复发送的整个上下文
+                    if let Some(usage) = &message.usage {
+                        label.on_hover_text(format!(
+                            "{}: {} prompt + {} completion tokens billed",
+                            usage.model, usage.prompt_tokens, usage.completion_tokens
+                        ));
+                    }
+                }
+            });
 
             match &message.content {
                 MessageContent::Text(text) => {
-                    ui.label(text);
+                    self.show_text(ui, &message.role, text);
+                    if message.stopped {
+                        ui.label(egui::RichText::new("(stopped)").weak().italics());
+                    }
                 }
-                MessageContent::Image { text, .. } => {
+                MessageContent::Image { text, url } => {
                     debug!("Rendering image message");
-                    ui.label(text);
-                    // TODO: 显示图片
+                    if !text.is_empty() {
+                        self.show_text(ui, &message.role, text);
+                    }
+                    self.show_image(ui, url, 240.0);
                 }
-                MessageContent::Function { name, arguments } => {
+                MessageContent::Function {
+                    name, arguments, ..
+                } => {
                     debug!(name, "Rendering function call message");
-                    ui.label(format!("Function call: {} with args: {}", name, arguments));
+                    ui.label(
+                        egui::RichText::new(format!("🔧 {}({})", name, arguments)).monospace(),
+                    );
+                }
+                MessageContent::ToolResult { name, content, .. } => {
+                    egui::CollapsingHeader::new(format!("Result of {}", name))
+                        .id_salt(message.timestamp)
+                        .show(ui, |ui| {
+                            ui.label(egui::RichText::new(content).monospace());
+                        });
+                }
+                MessageContent::Summary { text, original } => {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "📝 Summary of {} earlier messages",
+                            original.len()
+                        ))
+                        .italics(),
+                    );
+                    self.markdown.show(ui, text);
+                    egui::CollapsingHeader::new("Original messages")
+                        .id_salt(message.timestamp)
+                        .show(ui, |ui| {
+                            for message in original {
+                                self.render_message(ui, message, None);
+                                ui.add_space(8.0);
+                            }
+                        });
                 }
             }
+        })
+        .response
+    }
+
+    /// 会话的 token 统计，以及下一次请求占用的上下文
+    fn token_usage_ui(
+        &mut self,
+        ui: &mut Ui,
+        state: &UIState,
+        session: &ChatSession,
+        tokenizer: &dyn Tokenizer,
+        model: &str,
+        token_counts: &[usize],
+    ) {
+        let profile = state.settings.active_profile();
+        let (mut prompt, mut completion) = (0, 0);
+        for (message, count) in session.messages.iter().zip(token_counts) {
+            match message.role {
+                Role::Assistant => completion += count,
+                _ => prompt += count,
+            }
+        }
+
+        let system_prompt = &session.settings.system_prompt;
+        let system_tokens = if system_prompt.trim().is_empty() {
+            0
+        } else {
+            self.token_counter.count(
+                tokenizer,
+                &Message::new(Role::System, MessageContent::Text(system_prompt.clone())),
+            )
+        };
+        let context = system_tokens + prompt + completion + tokens::TOKENS_PER_REPLY;
+        let context_window = profile
+            .context_window
+            .map(|size| size as usize)
+            .unwrap_or_else(|| tokens::context_window(model));
+        let budget = context_window.saturating_sub(profile.max_tokens as usize);
+
+        let text = format!(
+            "{} prompt · {} completion tokens · context {} / {}",
+            prompt + system_tokens,
+            completion,
+            context,
+            context_window
+        );
+        if context > budget {
+            ui.label(
+                egui::RichText::new(text)
+                    .small()
+                    .color(egui::Color32::ORANGE),
+            )
+            .on_hover_text(format!(
+                "The conversation no longer fits next to a {}-token reply. \
+                     Policy: {}.",
+                profile.max_tokens,
+                profile.context_policy.label()
+            ));
+        } else {
+            ui.label(egui::RichText::new(text).small().weak());
+        }
+    }
+
+    /// 用户输入按原样显示，其他消息按 Markdown 渲染
+    fn show_text(&mut self, ui: &mut Ui, role: &Role, text: &str) {
+        match role {
+            Role::User => {
+                ui.label(text);
+            }
+            _ => self.markdown.show(ui, text),
+        }
+    }
+
+    /// 显示缩略图，data URL 会先解码并注册到 egui 的字节缓存中
+    fn show_image(&mut self, ui: &mut Ui, url: &str, max_size: f32) {
+        let uri = if url.starts_with("data:") {
+            let mut hasher = DefaultHasher::new();
+            url.hash(&mut hasher);
+            let key = hasher.finish();
+            let uri = format!("bytes://chat-image-{:x}", key);
+
+            // 只在第一次显示时解码，成功后才记为已加载，失败的也不再重试
+            if !self.loaded_images.contains(&key) && !self.invalid_images.contains(&key) {
+                match image::decode_data_url(url) {
+                    Some(bytes) => {
+                        ui.ctx().include_bytes(uri.clone(), bytes);
+                        self.loaded_images.insert(key);
+                    }
+                    None => {
+                        self.invalid_images.insert(key);
+                    }
+                }
+            }
+            if self.invalid_images.contains(&key) {
+                ui.label("[invalid image data]");
+                return;
+            }
+            uri
+        } else {
+            url.to_string()
+        };
+
+        ui.add(
+            egui::Image::new(uri)
+                .max_size(egui::vec2(max_size, max_size))
+                .rounding(4.0),
+        );
+    }
+}
+
+/// 记录新的用户消息；图片是完整的 data URL，只记录类型和长度
+fn log_user_message(message: &Message) {
+    match &message.content {
+        MessageContent::Image { text, url } => debug!(
+            role = ?message.role,
+            text_len = text.len(),
+            media_type = image::data_url_media_type(url),
+            url_len = url.len(),
+            "Created user message with image"
+        ),
+        _ => debug!(role = ?message.role, "Created user message"),
+    }
+}
+
+/// 记录收到的流式消息，不记录正文
+fn log_stream_message(message: &StreamMessage) {
+    match message {
+        StreamMessage::Chunk(chunk) => debug!(len = chunk.len(), "Received stream chunk"),
+        StreamMessage::Intermediate(message) | StreamMessage::Done(message) => debug!(
+            role = ?message.role,
+            usage = ?message.usage,
+            "Received stream message"
+        ),
+        StreamMessage::Error(error) | StreamMessage::Retrying { error, .. } => {
+            debug!(%error, "Received stream error")
+        }
+    }
+}
+
+/// 消息下方的操作按钮：切换分支、编辑、重新生成
+fn message_controls(
+    ui: &mut Ui,
+    session: &ChatSession,
+    index: usize,
+    message: &Message,
+    can_regenerate: bool,
+    is_sending: bool,
+) -> Option<MessageAction> {
+    if is_sending {
+        return None;
+    }
+
+    let mut action = None;
+    ui.horizontal(|ui| {
+        action = variant_switcher(ui, session, index);
+
+        let editable = matches!(message.role, Role::User)
+            && matches!(
+                message.content,
+                MessageContent::Text(_) | MessageContent::Image { .. }
+            );
+        if editable
+            && ui
+                .small_button("✏")
+                .on_hover_text("Edit and resend")
+                .clicked()
+        {
+            action = Some(MessageAction::StartEdit(index));
+        }
+
+        if can_regenerate && ui.small_button("🔄").on_hover_text("Regenerate").clicked() {
+            action = Some(MessageAction::Regenerate);
+        }
+
+        if ui
+            .small_button("⑂")
+            .on_hover_text("Fork from here")
+            .clicked()
+        {
+            action = Some(MessageAction::Fork(index));
+        }
+    });
+    action
+}
+
+/// 在有多个候选分支的位置显示 "< 2/3 >"
+fn variant_switcher(ui: &mut Ui, session: &ChatSession, index: usize) -> Option<MessageAction> {
+    let (active, count) = session.variant_info(index)?;
+    let mut action = None;
+
+    if ui
+        .add_enabled(active > 0, egui::Button::new("<").small())
+        .clicked()
+    {
+        action = Some(MessageAction::SwitchVariant {
+            index,
+            target: active - 1,
+        });
+    }
+    ui.label(format!("{}/{}", active + 1, count));
+    if ui
+        .add_enabled(active + 1 < count, egui::Button::new(">").small())
+        .clicked()
+    {
+        action = Some(MessageAction::SwitchVariant {
+            index,
+            target: active + 1,
+        });
+    }
+    action
+}
+
+/// 编辑用户消息的输入框
+/// 编辑用户消息的输入框；回复进行中时不能重新发送
+fn edit_box(
+    ui: &mut Ui,
+    index: usize,
+    text: &mut String,
+    is_sending: bool,
+) -> Option<MessageAction> {
+    let mut action = None;
+    ui.vertical(|ui| {
+        ui.add(
+            egui::TextEdit::multiline(text)
+                .desired_width(f32::INFINITY)
+                .desired_rows(2),
+        );
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!text.is_empty() && !is_sending, egui::Button::new("Send"))
+                .on_disabled_hover_text("Wait for the current reply to finish")
+                .clicked()
+            {
+                action = Some(MessageAction::SubmitEdit(index));
+            }
+            if ui.button("Cancel").clicked() {
+                action = Some(MessageAction::CancelEdit);
+            }
         });
+    });
+    action
+}
+
+/// 会话的系统提示词以及模型和温度覆盖
+fn session_settings_ui(ui: &mut Ui, session: &mut ChatSession, models: &[ModelInfo]) {
+    // 编辑中的设置先保存在草稿里，输入框失去焦点后才写入会话，
+    // 避免每次按键都更新会话的修改时间
+    let draft_id = ui.make_persistent_id(("session_settings", &session.id));
+    let mut settings: SessionSettings = ui
+        .data_mut(|data| data.get_temp(draft_id))
+        .unwrap_or_else(|| session.settings.clone());
+    let title = match &settings.preset {
+        Some(preset) => format!("Session settings · {}", preset),
+        None => "Session settings".to_string(),
+    };
+
+    let mut responses = Vec::new();
+    egui::CollapsingHeader::new(title)
+        .id_salt("session_settings")
+        .show(ui, |ui| {
+            ui.label("System prompt:");
+            responses.push(
+                ui.add(
+                    egui::TextEdit::multiline(&mut settings.system_prompt)
+                        .desired_rows(3)
+                        .desired_width(f32::INFINITY)
+                        .hint_text("No system prompt"),
+                ),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Model:");
+                responses.push(optional_model_picker(
+                    ui,
+                    "session_model",
+                    &mut settings.overrides.model,
+                    "profile default",
+                    models,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Temperature:");
+                responses.push(optional_temperature(
+                    ui,
+                    &mut settings.overrides.temperature,
+                ));
+            });
+        });
+
+    let editing = responses
+        .iter()
+        .any(|response| response.has_focus() || response.dragged());
+    if editing {
+        ui.data_mut(|data| data.insert_temp(draft_id, settings));
+        return;
+    }
+    ui.data_mut(|data| data.remove::<SessionSettings>(draft_id));
+    if settings != session.settings {
+        session.set_settings(settings);
     }
 }
@@ -0,0 +1,167 @@
+//! 按需把公式渲染成图片，Markdown 中的公式以 `math://` 图片的形式显示
+
+use super::{math, typeset};
+use anyhow::Result;
+use eframe::egui::{
+    self,
+    load::{ImageLoadResult, ImageLoader, ImagePoll, LoadError, SizeHint},
+    mutex::Mutex,
+    Color32, ColorImage,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const SCHEME: &str = "math://";
+
+/// 公式图片的地址：`math://{d|i}/{字号}/{rrggbbaa}/{百分号编码的 LaTeX}`
+pub fn uri(source: &str, display: bool, size: f32, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut uri = format!(
+        "{}{}/{}/{:02x}{:02x}{:02x}{:02x}/",
+        SCHEME,
+        if display { "d" } else { "i" },
+        size,
+        r,
+        g,
+        b,
+        a
+    );
+    for byte in source.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// 渲染 `math://` 图片并缓存结果，其他地址交给别的加载器
+#[derive(Default)]
+pub struct MathLoader {
+    cache: Mutex<HashMap<String, Arc<ColorImage>>>,
+}
+
+impl MathLoader {
+    pub const ID: &'static str = egui::generate_loader_id!(MathLoader);
+}
+
+impl ImageLoader for MathLoader {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn load(&self, _ctx: &egui::Context, uri: &str, _: SizeHint) -> ImageLoadResult {
+        let Some(rest) = uri.strip_prefix(SCHEME) else {
+            return Err(LoadError::NotSupported);
+        };
+        if let Some(image) = self.cache.lock().get(uri) {
+            return Ok(ImagePoll::Ready {
+                image: image.clone(),
+            });
+        }
+
+        let image = Arc::new(render(rest).map_err(|e| LoadError::Loading(e.to_string()))?);
+        self.cache.lock().insert(uri.to_string(), image.clone());
+        Ok(ImagePoll::Ready { image })
+    }
+
+    fn forget(&self, uri: &str) {
+        self.cache.lock().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.cache.lock().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.cache
+            .lock()
+            .values()
+            .map(|image| image.pixels.len() * std::mem::size_of::<Color32>())
+            .sum()
+    }
+}
+
+/// 解析去掉 `math://` 之后的地址并渲染
+fn render(rest: &str) -> Result<ColorImage> {
+    let mut parts = rest.splitn(4, '/');
+    let (Some(mode), Some(size), Some(color), Some(source)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(anyhow::anyhow!("Invalid formula URI"));
+    };
+
+    let size: f32 = size.parse()?;
+    let channel = |index: usize| {
+        color
+            .get(index * 2..index * 2 + 2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid color {}", color))
+    };
+    let color = Color32::from_rgba_unmultiplied(channel(0)?, channel(1)?, channel(2)?, channel(3)?);
+
+    let node = math::parse(&decode(source)?)?;
+    typeset::render(&node, mode == "d", size, color)
+}
+
+fn decode(encoded: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid percent encoding"))?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uris_round_trip() {
+        let source = r"\frac{a}{b} + \text{é}";
+        let uri = uri(source, true, 14.5, Color32::from_rgb(0x12, 0xab, 0xef));
+        assert!(uri.starts_with("math://d/14.5/12abefff/"));
+        // 地址中没有会被 Markdown 当作语法的字符
+        assert!(!uri.contains(['(', ')', ' ', '\\', '{', '}']));
+
+        let encoded = uri.rsplit('/').next().unwrap();
+        assert_eq!(decode(encoded).unwrap(), source);
+    }
+
+    #[test]
+    fn renders_only_math_uris() {
+        let loader = MathLoader::default();
+        let ctx = egui::Context::default();
+        assert!(matches!(
+            loader.load(&ctx, "https://example.com/a.png", SizeHint::default()),
+            Err(LoadError::NotSupported)
+        ));
+
+        let uri = uri("x^2", false, 16.0, Color32::WHITE);
+        let Ok(ImagePoll::Ready { image }) = loader.load(&ctx, &uri, SizeHint::default()) else {
+            panic!("formula was not rendered");
+        };
+        assert!(image.size[0] > 0 && loader.byte_size() > 0);
+        loader.forget(&uri);
+        assert_eq!(loader.byte_size(), 0);
+
+        let invalid = super::uri(r"\foo", false, 16.0, Color32::WHITE);
+        assert!(matches!(
+            loader.load(&ctx, &invalid, SizeHint::default()),
+            Err(LoadError::Loading(_))
+        ));
+    }
+}
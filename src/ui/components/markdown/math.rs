@@ -0,0 +1,1039 @@
+//! 解析常用的 LaTeX 数学子集，得到用于排版的语法树
+
+use anyhow::Result;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// 公式中的元素
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// 字母变量，以斜体显示
+    Variable(char),
+    /// 数字、运算符等直立显示的符号，`Class` 决定与前后元素的间距
+    Symbol(char, Class),
+    /// `\text{...}` 等直立显示的文字
+    Text(String),
+    /// 函数名和大型运算符；`limits` 为真时上下标在显示模式中放在正上方和正下方
+    Operator {
+        text: String,
+        large: bool,
+        limits: bool,
+    },
+    /// 水平空白，单位是 em
+    Space(f32),
+    /// `{...}` 组或一串元素
+    Row(Vec<Node>),
+    Scripts {
+        base: Box<Node>,
+        sup: Option<Box<Node>>,
+        sub: Option<Box<Node>>,
+    },
+    /// 分数；`rule` 为假时没有分数线（`\binom`），`display` 指定分子分母的样式（`\dfrac`、`\tfrac`）
+    Fraction {
+        numerator: Box<Node>,
+        denominator: Box<Node>,
+        rule: bool,
+        display: Option<bool>,
+    },
+    Radical {
+        index: Option<Box<Node>>,
+        radicand: Box<Node>,
+    },
+    /// 上方的重音符号；`stretch` 为真时横向拉伸到与内容同宽
+    Accent {
+        base: Box<Node>,
+        accent: char,
+        stretch: bool,
+    },
+    Overline(Box<Node>),
+    Underline(Box<Node>),
+    /// 高度随内容变化的一对定界符，`None` 表示不显示（`\left.`）
+    Fenced {
+        open: Option<char>,
+        body: Box<Node>,
+        close: Option<char>,
+    },
+    /// `\big(` 等固定大小的定界符，`size` 是相对于字号的高度
+    Delimiter {
+        delimiter: char,
+        size: f32,
+    },
+    /// 矩阵和对齐环境
+    Table(Table),
+}
+
+/// 决定元素之间间距的类别，与 TeX 的原子类型对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Ord,
+    Op,
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+    Inner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub rows: Vec<Vec<Node>>,
+    /// 各列的对齐方式，列数更多时循环使用
+    pub align: Vec<Align>,
+    /// 相邻两列之间的间距（em），同样循环使用
+    pub gaps: Vec<f32>,
+    /// 单元格是否使用显示样式
+    pub display: bool,
+}
+
+/// 解析公式，遇到不支持的语法时返回错误
+pub fn parse(source: &str) -> Result<Node> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+    };
+    let (rows, stop) = parser.rows()?;
+    match stop {
+        Stop::End => {}
+        Stop::Brace => return Err(anyhow::anyhow!("Unbalanced '}}'")),
+        stop => return Err(unexpected(&stop)),
+    }
+
+    // 没有 `\\` 时是普通的一行，否则每行居中排列
+    if let [row] = rows.as_slice() {
+        if let [cell] = row.as_slice() {
+            return Ok(cell.clone());
+        }
+    }
+    Ok(Node::Table(Table {
+        rows,
+        align: vec![Align::Center],
+        gaps: vec![1.0],
+        display: true,
+    }))
+}
+
+/// 一串元素结束的原因
+#[derive(Debug)]
+enum Stop {
+    End,
+    Brace,
+    Bracket,
+    Cell,
+    Line,
+    Environment(String),
+    Right(Option<char>),
+}
+
+fn unexpected(stop: &Stop) -> anyhow::Error {
+    match stop {
+        Stop::End => anyhow::anyhow!("Unexpected end of formula"),
+        Stop::Brace => anyhow::anyhow!("Unexpected '}}'"),
+        Stop::Bracket => anyhow::anyhow!("Unexpected ']'"),
+        Stop::Cell => anyhow::anyhow!("Unexpected '&'"),
+        Stop::Line => anyhow::anyhow!("Unexpected '\\\\'"),
+        Stop::Environment(name) => anyhow::anyhow!("Unexpected \\end{{{}}}", name),
+        Stop::Right(_) => anyhow::anyhow!("\\right without \\left"),
+    }
+}
+
+/// 命令解析的结果
+enum Command {
+    Node(Node),
+    Stop(Stop),
+    /// 只影响样式或编号的命令，例如 `\displaystyle`、`\nonumber`
+    Nothing,
+    /// `\limits` 和 `\nolimits`
+    Limits(bool),
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    /// 解析到组、环境或公式结束，按 `&` 和 `\\` 分成单元格
+    fn rows(&mut self) -> Result<(Vec<Vec<Node>>, Stop)> {
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            let (nodes, stop) = self.list(false)?;
+            cells.push(row(nodes));
+            match stop {
+                Stop::Cell => {}
+                Stop::Line => rows.push(std::mem::take(&mut cells)),
+                stop => {
+                    // 最后一行末尾的 `\\` 不产生空行
+                    let empty = matches!(cells.as_slice(), [Node::Row(nodes)] if nodes.is_empty());
+                    if !empty || rows.is_empty() {
+                        rows.push(cells);
+                    }
+                    return Ok((rows, stop));
+                }
+            }
+        }
+    }
+
+    /// 解析一串元素，`bracket` 为真时遇到 `]` 结束（可选参数）
+    fn list(&mut self, bracket: bool) -> Result<(Vec<Node>, Stop)> {
+        let mut nodes: Vec<Node> = Vec::new();
+
+        loop {
+            let Some(c) = self.chars.next() else {
+                return Ok((nodes, Stop::End));
+            };
+
+            let node = match c {
+                c if c.is_whitespace() => continue,
+                '%' => {
+                    while self.chars.next_if(|&c| c != '\n').is_some() {}
+                    continue;
+                }
+                '}' => return Ok((nodes, Stop::Brace)),
+                ']' if bracket => return Ok((nodes, Stop::Bracket)),
+                '&' => return Ok((nodes, Stop::Cell)),
+                '{' => self.group()?,
+                '^' | '_' => {
+                    let script = self.argument()?;
+                    let base = nodes.pop().unwrap_or(Node::Row(Vec::new()));
+                    attach(base, script, c == '^')
+                }
+                '\\' => match self.command()? {
+                    Command::Node(node) => node,
+                    Command::Stop(stop) => return Ok((nodes, stop)),
+                    Command::Nothing => continue,
+                    Command::Limits(value) => {
+                        match nodes.last_mut() {
+                            Some(Node::Operator { limits, .. }) => *limits = value,
+                            _ => return Err(anyhow::anyhow!("\\limits must follow an operator")),
+                        }
+                        continue;
+                    }
+                },
+                c => character(c),
+            };
+            nodes.push(node);
+        }
+    }
+
+    /// `{` 之后的组
+    fn group(&mut self) -> Result<Node> {
+        match self.list(false)? {
+            (nodes, Stop::Brace) => Ok(row(nodes)),
+            (_, Stop::End) => Err(anyhow::anyhow!("Missing '}}'")),
+            (_, stop) => Err(unexpected(&stop)),
+        }
+    }
+
+    /// 命令或上下标的参数：一个字符、一个命令或一个 `{...}` 组
+    fn argument(&mut self) -> Result<Node> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some('{') => self.group(),
+            Some('\\') => match self.command()? {
+                Command::Node(node) => Ok(node),
+                _ => Err(anyhow::anyhow!("Missing argument")),
+            },
+            Some('}' | '^' | '_' | '&') | None => Err(anyhow::anyhow!("Missing argument")),
+            Some(c) => Ok(character(c)),
+        }
+    }
+
+    /// 可选参数 `[...]`
+    fn optional_argument(&mut self) -> Result<Option<Node>> {
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'[').is_none() {
+            return Ok(None);
+        }
+        match self.list(true)? {
+            (nodes, Stop::Bracket) => Ok(Some(row(nodes))),
+            (_, Stop::End) => Err(anyhow::anyhow!("Missing ']'")),
+            (_, stop) => Err(unexpected(&stop)),
+        }
+    }
+
+    /// 原样读取 `{...}` 中的文本，用于 `\text` 等
+    fn raw_group(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        if self.chars.next() != Some('{') {
+            return Err(anyhow::anyhow!("Expected '{{'"));
+        }
+
+        let mut depth = 0;
+        let mut text = String::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(text),
+                '}' => depth -= 1,
+                // 文字中转义的字符
+                '\\' if self.chars.peek().is_some_and(|c| "{}$%&#_ ".contains(*c)) => {
+                    text.extend(self.chars.next());
+                    continue;
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+        Err(anyhow::anyhow!("Missing '}}'"))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+            name.push(c);
+        }
+        name
+    }
+
+    /// `\` 之后的命令
+    fn command(&mut self) -> Result<Command> {
+        let name = self.name();
+
+        if name.is_empty() {
+            let node = match self.chars.next() {
+                Some(',') => Node::Space(3.0 / 18.0),
+                Some(':' | '>') => Node::Space(4.0 / 18.0),
+                Some(';') => Node::Space(5.0 / 18.0),
+                Some('!') => Node::Space(-3.0 / 18.0),
+                Some(' ') => Node::Space(1.0 / 3.0),
+                Some('\\') => return Ok(Command::Stop(Stop::Line)),
+                Some('{') => Node::Symbol('{', Class::Open),
+                Some('}') => Node::Symbol('}', Class::Close),
+                Some('|') => Node::Symbol('‖', Class::Ord),
+                Some(c @ ('%' | '$' | '&' | '#' | '_')) => Node::Symbol(c, Class::Ord),
+                Some(c) => return Err(anyhow::anyhow!("Unknown command \\{}", c)),
+                None => return Err(anyhow::anyhow!("Trailing backslash")),
+            };
+            return Ok(Command::Node(node));
+        }
+
+        if let Some(node) = symbol(&name) {
+            return Ok(Command::Node(node));
+        }
+
+        let node = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => Node::Fraction {
+                numerator: Box::new(self.argument()?),
+                denominator: Box::new(self.argument()?),
+                rule: true,
+                display: match name.as_str() {
+                    "dfrac" | "cfrac" => Some(true),
+                    "tfrac" => Some(false),
+                    _ => None,
+                },
+            },
+            "binom" | "dbinom" | "tbinom" => Node::Fenced {
+                open: Some('('),
+                body: Box::new(Node::Fraction {
+                    numerator: Box::new(self.argument()?),
+                    denominator: Box::new(self.argument()?),
+                    rule: false,
+                    display: match name.as_str() {
+                        "dbinom" => Some(true),
+                        "tbinom" => Some(false),
+                        _ => None,
+                    },
+                }),
+                close: Some(')'),
+            },
+            "sqrt" => Node::Radical {
+                index: self.optional_argument()?.map(Box::new),
+                radicand: Box::new(self.argument()?),
+            },
+            "text" | "textrm" | "textit" | "textbf" | "textsf" | "texttt" | "textnormal"
+            | "mbox" | "hbox" => Node::Text(self.raw_group()?),
+            "operatorname" => {
+                let limits = self.chars.next_if_eq(&'*').is_some();
+                Node::Operator {
+                    text: self.raw_group()?,
+                    large: false,
+                    limits,
+                }
+            }
+            "mathrm" | "mathbf" | "mathsf" | "mathtt" | "mathup" => upright(self.argument()?),
+            "mathit" | "boldsymbol" | "bm" | "mathcal" | "mathscr" | "mathfrak" => {
+                self.argument()?
+            }
+            "mathbb" => double_struck(self.argument()?),
+            "hat" | "check" | "tilde" | "bar" | "breve" | "acute" | "grave" | "dot" | "ddot"
+            | "vec" | "widehat" | "widetilde" | "overrightarrow" | "overleftarrow" => {
+                let (accent, stretch) = match name.as_str() {
+                    "hat" => ('ˆ', false),
+                    "widehat" => ('ˆ', true),
+                    "check" => ('ˇ', false),
+                    "tilde" => ('˜', false),
+                    "widetilde" => ('˜', true),
+                    "bar" => ('¯', false),
+                    "breve" => ('˘', false),
+                    "acute" => ('´', false),
+                    "grave" => ('`', false),
+                    "dot" => ('˙', false),
+                    "ddot" => ('¨', false),
+                    "vec" => ('→', false),
+                    "overrightarrow" => ('→', true),
+                    _ => ('←', true),
+                };
+                Node::Accent {
+                    base: Box::new(self.argument()?),
+                    accent,
+                    stretch,
+                }
+            }
+            "overline" => Node::Overline(Box::new(self.argument()?)),
+            "underline" => Node::Underline(Box::new(self.argument()?)),
+            "not" => negate(self.argument()?)?,
+            "begin" => self.environment()?,
+            "end" => return Ok(Command::Stop(Stop::Environment(self.raw_group()?))),
+            "left" => {
+                let open = self.delimiter()?;
+                match self.list(false)? {
+                    (nodes, Stop::Right(close)) => Node::Fenced {
+                        open,
+                        body: Box::new(row(nodes)),
+                        close,
+                    },
+                    (_, Stop::End) => return Err(anyhow::anyhow!("\\left without \\right")),
+                    (_, stop) => return Err(unexpected(&stop)),
+                }
+            }
+            "right" => return Ok(Command::Stop(Stop::Right(self.delimiter()?))),
+            "middle" => match self.delimiter()? {
+                Some(delimiter) => Node::Symbol(delimiter, Class::Ord),
+                None => return Ok(Command::Nothing),
+            },
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "Bigl" | "biggl" | "Biggl" | "bigr"
+            | "Bigr" | "biggr" | "Biggr" | "bigm" | "Bigm" | "biggm" | "Biggm" => {
+                let size = match name.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => 1.2,
+                    "Big" => 1.8,
+                    "bigg" => 2.4,
+                    _ => 3.0,
+                };
+                match self.delimiter()? {
+                    Some(delimiter) => Node::Delimiter { delimiter, size },
+                    None => return Ok(Command::Nothing),
+                }
+            }
+            "displaystyle" | "textstyle" | "scriptstyle" | "nonumber" | "notag" => {
+                return Ok(Command::Nothing)
+            }
+            "label" | "tag" => {
+                self.raw_group()?;
+                return Ok(Command::Nothing);
+            }
+            "limits" => return Ok(Command::Limits(true)),
+            "nolimits" => return Ok(Command::Limits(false)),
+            "quad" => Node::Space(1.0),
+            "qquad" => Node::Space(2.0),
+            "enspace" => Node::Space(0.5),
+            "thinspace" => Node::Space(3.0 / 18.0),
+            "medspace" => Node::Space(4.0 / 18.0),
+            "thickspace" => Node::Space(5.0 / 18.0),
+            "negthinspace" => Node::Space(-3.0 / 18.0),
+            "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan"
+            | "sinh" | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "ker" | "dim"
+            | "deg" | "arg" | "hom" | "mod" | "bmod" => Node::Operator {
+                text: name.trim_start_matches('b').to_string(),
+                large: false,
+                limits: false,
+            },
+            "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr"
+            | "argmax" | "argmin" => Node::Operator {
+                text: match name.as_str() {
+                    "liminf" => "lim inf".to_string(),
+                    "limsup" => "lim sup".to_string(),
+                    "argmax" => "arg max".to_string(),
+                    "argmin" => "arg min".to_string(),
+                    _ => name,
+                },
+                large: false,
+                limits: true,
+            },
+            "pmod" => Node::Row(vec![
+                Node::Space(1.0),
+                Node::Symbol('(', Class::Open),
+                Node::Text("mod".to_string()),
+                Node::Space(1.0 / 3.0),
+                self.argument()?,
+                Node::Symbol(')', Class::Close),
+            ]),
+            _ => return Err(anyhow::anyhow!("Unknown command \\{}", name)),
+        };
+        Ok(Command::Node(node))
+    }
+
+    /// `\left`、`\right` 和 `\big` 之后的定界符，`.` 表示不显示
+    fn delimiter(&mut self) -> Result<Option<char>> {
+        self.skip_whitespace();
+        let delimiter = match self.chars.next() {
+            Some('.') => return Ok(None),
+            Some(c @ ('(' | ')' | '[' | ']' | '|' | '/')) => c,
+            Some('<') => '⟨',
+            Some('>') => '⟩',
+            Some('\\') => match self.name().as_str() {
+                "" => match self.chars.next() {
+                    Some(c @ ('{' | '}')) => c,
+                    Some('|') => '‖',
+                    _ => return Err(anyhow::anyhow!("Unknown delimiter")),
+                },
+                "langle" => '⟨',
+                "rangle" => '⟩',
+                "lfloor" => '⌊',
+                "rfloor" => '⌋',
+                "lceil" => '⌈',
+                "rceil" => '⌉',
+                "lbrace" => '{',
+                "rbrace" => '}',
+                "vert" | "lvert" | "rvert" => '|',
+                "Vert" | "lVert" | "rVert" => '‖',
+                name => return Err(anyhow::anyhow!("Unknown delimiter \\{}", name)),
+            },
+            _ => return Err(anyhow::anyhow!("Missing delimiter")),
+        };
+        Ok(Some(delimiter))
+    }
+
+    /// `\begin{...}` 之后的环境，返回矩阵或对齐的表格
+    fn environment(&mut self) -> Result<Node> {
+        let name = self.raw_group()?;
+        let matrix = |open: Option<char>, close: Option<char>| (open, close, Align::Center);
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" | "array" | "aligned" | "align" | "align*" | "split"
+            | "gather" | "gather*" | "gathered" | "equation" | "equation*" | "multline"
+            | "multline*" | "alignat" | "alignat*" | "alignedat" | "eqnarray" | "eqnarray*" => {
+                matrix(None, None)
+            }
+            "pmatrix" => matrix(Some('('), Some(')')),
+            "bmatrix" => matrix(Some('['), Some(']')),
+            "Bmatrix" => matrix(Some('{'), Some('}')),
+            "vmatrix" => matrix(Some('|'), Some('|')),
+            "Vmatrix" => matrix(Some('‖'), Some('‖')),
+            "cases" | "dcases" => (Some('{'), None, Align::Left),
+            _ => return Err(anyhow::anyhow!("Unknown environment {}", name)),
+        };
+
+        let (align, gaps, display) = match name.trim_end_matches('*') {
+            "array" => {
+                let align = self
+                    .raw_group()?
+                    .chars()
+                    .filter_map(|c| match c {
+                        'l' => Some(Align::Left),
+                        'c' => Some(Align::Center),
+                        'r' => Some(Align::Right),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                (align, vec![1.0], false)
+            }
+            "aligned" | "align" | "split" | "alignat" | "alignedat" => {
+                if name.starts_with("align") && name.contains("at") {
+                    // 列数参数
+                    self.raw_group()?;
+                }
+                (vec![Align::Right, Align::Left], vec![0.0, 2.0], true)
+            }
+            "eqnarray" => (
+                vec![Align::Right, Align::Center, Align::Left],
+                vec![0.3],
+                true,
+            ),
+            "gather" | "gathered" | "equation" | "multline" => {
+                (vec![Align::Center], vec![0.0], true)
+            }
+            "dcases" => (vec![align], vec![1.0], true),
+            _ => (vec![align], vec![1.0], false),
+        };
+
+        let rows = match self.rows()? {
+            (rows, Stop::Environment(end)) if end == name => rows,
+            (_, Stop::Environment(end)) => {
+                return Err(anyhow::anyhow!(
+                    "\\begin{{{}}} ended by \\end{{{}}}",
+                    name,
+                    end
+                ))
+            }
+            (_, Stop::End) => return Err(anyhow::anyhow!("Missing \\end{{{}}}", name)),
+            (_, stop) => return Err(unexpected(&stop)),
+        };
+        let table = Node::Table(Table {
+            rows,
+            align: if align.is_empty() {
+                vec![Align::Center]
+            } else {
+                align
+            },
+            gaps,
+            display,
+        });
+
+        Ok(if open.is_some() || close.is_some() {
+            Node::Fenced {
+                open,
+                body: Box::new(table),
+                close,
+            }
+        } else {
+            table
+        })
+    }
+}
+
+/// 只有一个元素时不需要外层的组
+fn row(mut nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        Node::Row(nodes)
+    }
+}
+
+/// 给 `base` 加上标或下标；已经有同一侧的角标时嵌套一层
+fn attach(base: Node, script: Node, sup: bool) -> Node {
+    match base {
+        Node::Scripts {
+            base,
+            sup: None,
+            sub,
+        } if sup => Node::Scripts {
+            base,
+            sup: Some(Box::new(script)),
+            sub,
+        },
+        Node::Scripts {
+            base,
+            sup: current,
+            sub: None,
+        } if !sup => Node::Scripts {
+            base,
+            sup: current,
+            sub: Some(Box::new(script)),
+        },
+        base => {
+            let script = Some(Box::new(script));
+            let (sup, sub) = if sup { (script, None) } else { (None, script) };
+            Node::Scripts {
+                base: Box::new(base),
+                sup,
+                sub,
+            }
+        }
+    }
+}
+
+/// 公式中直接出现的字符
+fn character(c: char) -> Node {
+    let class = match c {
+        c if c.is_alphabetic() => return Node::Variable(c),
+        '\'' => return Node::Symbol('′', Class::Ord),
+        '~' => return Node::Space(1.0 / 3.0),
+        '-' => return Node::Symbol('−', Class::Bin),
+        '*' => return Node::Symbol('∗', Class::Bin),
+        '+' | '±' | '×' | '÷' | '·' => Class::Bin,
+        '=' | '<' | '>' | ':' | '≤' | '≥' | '≠' | '≈' | '→' | '∈' => Class::Rel,
+        ',' | ';' => Class::Punct,
+        '(' | '[' => Class::Open,
+        ')' | ']' | '!' | '?' => Class::Close,
+        _ => Class::Ord,
+    };
+    Node::Symbol(c, class)
+}
+
+/// `\mathrm` 等：变量改为直立显示
+fn upright(node: Node) -> Node {
+    match node {
+        Node::Variable(c) => Node::Text(c.to_string()),
+        Node::Row(nodes) => Node::Row(nodes.into_iter().map(upright).collect()),
+        node => node,
+    }
+}
+
+fn double_struck(node: Node) -> Node {
+    match node {
+        Node::Variable(c) => {
+            let c = match c {
+                'R' => 'ℝ',
+                'N' => 'ℕ',
+                'Z' => 'ℤ',
+                'Q' => 'ℚ',
+                'C' => 'ℂ',
+                'P' => 'ℙ',
+                'H' => 'ℍ',
+                c => c,
+            };
+            Node::Symbol(c, Class::Ord)
+        }
+        Node::Row(nodes) => Node::Row(nodes.into_iter().map(double_struck).collect()),
+        node => node,
+    }
+}
+
+/// `\not` 加在关系符前
+fn negate(node: Node) -> Result<Node> {
+    let negated = match node {
+        Node::Symbol('=', _) => '≠',
+        Node::Symbol('<', _) => '≮',
+        Node::Symbol('>', _) => '≯',
+        Node::Symbol('∈', _) => '∉',
+        Node::Symbol('≡', _) => '≢',
+        Node::Symbol('⊂', _) => '⊄',
+        Node::Symbol('⊃', _) => '⊅',
+        Node::Symbol('⊆', _) => '⊈',
+        Node::Symbol('⊇', _) => '⊉',
+        Node::Symbol('∼', _) => '≁',
+        Node::Symbol('≈', _) => '≉',
+        Node::Symbol('|' | '∣', _) => '∤',
+        _ => return Err(anyhow::anyhow!("Unsupported \\not")),
+    };
+    Ok(Node::Symbol(negated, Class::Rel))
+}
+
+fn symbol(name: &str) -> Option<Node> {
+    use Class::*;
+
+    let variable = |c| Some(Node::Variable(c));
+    let large = |text: &str, limits| {
+        Some(Node::Operator {
+            text: text.to_string(),
+            large: true,
+            limits,
+        })
+    };
+    let (c, class) = match name {
+        // 小写希腊字母和变量一样用斜体
+        "alpha" => return variable('α'),
+        "beta" => return variable('β'),
+        "gamma" => return variable('γ'),
+        "delta" => return variable('δ'),
+        "epsilon" => return variable('ϵ'),
+        "varepsilon" => return variable('ε'),
+        "zeta" => return variable('ζ'),
+        "eta" => return variable('η'),
+        "theta" => return variable('θ'),
+        "vartheta" => return variable('ϑ'),
+        "iota" => return variable('ι'),
+        "kappa" => return variable('κ'),
+        "lambda" => return variable('λ'),
+        "mu" => return variable('μ'),
+        "nu" => return variable('ν'),
+        "xi" => return variable('ξ'),
+        "pi" => return variable('π'),
+        "varpi" => return variable('ϖ'),
+        "rho" => return variable('ρ'),
+        "varrho" => return variable('ϱ'),
+        "sigma" => return variable('σ'),
+        "varsigma" => return variable('ς'),
+        "tau" => return variable('τ'),
+        "upsilon" => return variable('υ'),
+        "phi" => return variable('ϕ'),
+        "varphi" => return variable('φ'),
+        "chi" => return variable('χ'),
+        "psi" => return variable('ψ'),
+        "omega" => return variable('ω'),
+        "Gamma" => ('Γ', Ord),
+        "Delta" => ('Δ', Ord),
+        "Theta" => ('Θ', Ord),
+        "Lambda" => ('Λ', Ord),
+        "Xi" => ('Ξ', Ord),
+        "Pi" => ('Π', Ord),
+        "Sigma" => ('Σ', Ord),
+        "Upsilon" => ('Υ', Ord),
+        "Phi" => ('Φ', Ord),
+        "Psi" => ('Ψ', Ord),
+        "Omega" => ('Ω', Ord),
+        // 大型运算符
+        "sum" => return large("∑", true),
+        "prod" => return large("∏", true),
+        "coprod" => return large("∐", true),
+        "bigcup" => return large("⋃", true),
+        "bigcap" => return large("⋂", true),
+        "bigvee" => return large("⋁", true),
+        "bigwedge" => return large("⋀", true),
+        "bigoplus" => return large("⨁", true),
+        "bigotimes" => return large("⨂", true),
+        "int" => return large("∫", false),
+        "iint" => return large("∬", false),
+        "iiint" => return large("∭", false),
+        "oint" => return large("∮", false),
+        // 二元运算
+        "pm" => ('±', Bin),
+        "mp" => ('∓', Bin),
+        "times" => ('×', Bin),
+        "div" => ('÷', Bin),
+        "cdot" => ('·', Bin),
+        "ast" => ('∗', Bin),
+        "star" => ('⋆', Bin),
+        "circ" => ('∘', Bin),
+        "bullet" => ('∙', Bin),
+        "oplus" => ('⊕', Bin),
+        "ominus" => ('⊖', Bin),
+        "otimes" => ('⊗', Bin),
+        "odot" => ('⊙', Bin),
+        "cup" => ('∪', Bin),
+        "cap" => ('∩', Bin),
+        "setminus" => ('∖', Bin),
+        "wedge" | "land" => ('∧', Bin),
+        "vee" | "lor" => ('∨', Bin),
+        // 关系
+        "leq" | "le" | "leqslant" => ('≤', Rel),
+        "geq" | "ge" | "geqslant" => ('≥', Rel),
+        "neq" | "ne" => ('≠', Rel),
+        "approx" => ('≈', Rel),
+        "equiv" => ('≡', Rel),
+        "sim" => ('∼', Rel),
+        "simeq" => ('≃', Rel),
+        "cong" => ('≅', Rel),
+        "propto" => ('∝', Rel),
+        "ll" => ('≪', Rel),
+        "gg" => ('≫', Rel),
+        "in" => ('∈', Rel),
+        "notin" => ('∉', Rel),
+        "ni" => ('∋', Rel),
+        "subset" => ('⊂', Rel),
+        "supset" => ('⊃', Rel),
+        "subseteq" => ('⊆', Rel),
+        "supseteq" => ('⊇', Rel),
+        "perp" => ('⊥', Rel),
+        "parallel" => ('∥', Rel),
+        "mid" => ('∣', Rel),
+        "models" => ('⊨', Rel),
+        "vdash" => ('⊢', Rel),
+        "coloneqq" => ('≔', Rel),
+        // 箭头
+        "to" | "rightarrow" => ('→', Rel),
+        "leftarrow" | "gets" => ('←', Rel),
+        "leftrightarrow" => ('↔', Rel),
+        "Rightarrow" => ('⇒', Rel),
+        "Leftarrow" => ('⇐', Rel),
+        "Leftrightarrow" => ('⇔', Rel),
+        "implies" => ('⟹', Rel),
+        "impliedby" => ('⟸', Rel),
+        "iff" => ('⟺', Rel),
+        "mapsto" => ('↦', Rel),
+        "uparrow" => ('↑', Rel),
+        "downarrow" => ('↓', Rel),
+        "longrightarrow" => ('⟶', Rel),
+        "longleftarrow" => ('⟵', Rel),
+        "longmapsto" => ('⟼', Rel),
+        // 其他符号
+        "infty" => ('∞', Ord),
+        "partial" => ('∂', Ord),
+        "nabla" => ('∇', Ord),
+        "forall" => ('∀', Ord),
+        "exists" => ('∃', Ord),
+        "nexists" => ('∄', Ord),
+        "neg" | "lnot" => ('¬', Ord),
+        "emptyset" | "varnothing" => ('∅', Ord),
+        "aleph" => ('ℵ', Ord),
+        "hbar" => ('ℏ', Ord),
+        "ell" => ('ℓ', Ord),
+        "Re" => ('ℜ', Ord),
+        "Im" => ('ℑ', Ord),
+        "angle" => ('∠', Ord),
+        "triangle" => ('△', Ord),
+        "degree" => ('°', Ord),
+        "prime" => ('′', Ord),
+        "dots" | "ldots" => ('…', Inner),
+        "cdots" => ('⋯', Inner),
+        "vdots" => ('⋮', Ord),
+        "ddots" => ('⋱', Inner),
+        "therefore" => ('∴', Rel),
+        "because" => ('∵', Rel),
+        "colon" => (':', Punct),
+        // 定界符
+        "langle" => ('⟨', Open),
+        "rangle" => ('⟩', Close),
+        "lfloor" => ('⌊', Open),
+        "rfloor" => ('⌋', Close),
+        "lceil" => ('⌈', Open),
+        "rceil" => ('⌉', Close),
+        "lbrace" => ('{', Open),
+        "rbrace" => ('}', Close),
+        "lvert" => ('|', Open),
+        "rvert" => ('|', Close),
+        "vert" => ('|', Ord),
+        "lVert" => ('‖', Open),
+        "rVert" => ('‖', Close),
+        "Vert" => ('‖', Ord),
+        _ => return None,
+    };
+    Some(Node::Symbol(c, class))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(c: char) -> Node {
+        Node::Variable(c)
+    }
+
+    fn sym(c: char, class: Class) -> Node {
+        Node::Symbol(c, class)
+    }
+
+    #[test]
+    fn parses_symbols_and_classes() {
+        assert_eq!(
+            parse(r"\alpha \leq 2-x").unwrap(),
+            Node::Row(vec![
+                var('α'),
+                sym('≤', Class::Rel),
+                sym('2', Class::Ord),
+                sym('−', Class::Bin),
+                var('x'),
+            ])
+        );
+        assert_eq!(parse(r"\mathbb{R}").unwrap(), sym('ℝ', Class::Ord));
+        assert_eq!(
+            parse(r"\mathrm{d}x").unwrap(),
+            Node::Row(vec![Node::Text("d".to_string()), var('x')])
+        );
+    }
+
+    #[test]
+    fn attaches_scripts() {
+        assert_eq!(
+            parse("x_i^2").unwrap(),
+            Node::Scripts {
+                base: Box::new(var('x')),
+                sup: Some(Box::new(sym('2', Class::Ord))),
+                sub: Some(Box::new(var('i'))),
+            }
+        );
+        assert_eq!(
+            parse(r"\sum_{k=1}^n").unwrap(),
+            Node::Scripts {
+                base: Box::new(Node::Operator {
+                    text: "∑".to_string(),
+                    large: true,
+                    limits: true,
+                }),
+                sup: Some(Box::new(var('n'))),
+                sub: Some(Box::new(Node::Row(vec![
+                    var('k'),
+                    sym('=', Class::Rel),
+                    sym('1', Class::Ord),
+                ]))),
+            }
+        );
+        assert!(matches!(
+            parse(r"\int\limits_0^1").unwrap(),
+            Node::Scripts { base, .. } if matches!(*base, Node::Operator { limits: true, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_fractions_and_radicals() {
+        assert_eq!(
+            parse(r"\frac{a+b}2").unwrap(),
+            Node::Fraction {
+                numerator: Box::new(Node::Row(vec![var('a'), sym('+', Class::Bin), var('b')])),
+                denominator: Box::new(sym('2', Class::Ord)),
+                rule: true,
+                display: None,
+            }
+        );
+        assert_eq!(
+            parse(r"\sqrt[3]{x}").unwrap(),
+            Node::Radical {
+                index: Some(Box::new(sym('3', Class::Ord))),
+                radicand: Box::new(var('x')),
+            }
+        );
+        assert!(matches!(
+            parse(r"\binom{n}{k}").unwrap(),
+            Node::Fenced { open: Some('('), close: Some(')'), body }
+                if matches!(*body, Node::Fraction { rule: false, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_matrices_and_alignments() {
+        let Node::Fenced {
+            open: Some('('),
+            body,
+            close: Some(')'),
+        } = parse(r"\begin{pmatrix} a & b \\ c & d \\ \end{pmatrix}").unwrap()
+        else {
+            panic!("expected a fenced matrix");
+        };
+        let Node::Table(table) = *body else {
+            panic!("expected a table");
+        };
+        assert_eq!(
+            table.rows,
+            [vec![var('a'), var('b')], vec![var('c'), var('d')]]
+        );
+        assert_eq!(table.align, [Align::Center]);
+
+        let Node::Table(table) = parse(r"\begin{aligned} x &= 1 \\ y &= 2 \end{aligned}").unwrap()
+        else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.align, [Align::Right, Align::Left]);
+
+        let Node::Table(table) = parse(r"\begin{array}{l|r} 1 & 2 \end{array}").unwrap() else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.align, [Align::Left, Align::Right]);
+
+        // 没有环境的 `\\` 分成居中的几行
+        assert!(matches!(parse(r"a \\ b").unwrap(), Node::Table(table) if table.rows.len() == 2));
+    }
+
+    #[test]
+    fn parses_delimiters() {
+        assert_eq!(
+            parse(r"\left( x \right.").unwrap(),
+            Node::Fenced {
+                open: Some('('),
+                body: Box::new(var('x')),
+                close: None,
+            }
+        );
+        assert_eq!(
+            parse(r"\Big\langle").unwrap(),
+            Node::Delimiter {
+                delimiter: '⟨',
+                size: 1.8,
+            }
+        );
+        assert_eq!(
+            parse(r"\text{if } x").unwrap(),
+            Node::Row(vec![Node::Text("if ".to_string()), var('x')])
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_syntax() {
+        for source in [
+            r"\foo",
+            "{x",
+            "x}",
+            "x^",
+            r"x\",
+            r"\text x",
+            r"\left( x",
+            r"x \right)",
+            r"\begin{pmatrix} a",
+            r"\begin{matrix} a \end{bmatrix}",
+            r"\begin{tikzpicture}\end{tikzpicture}",
+        ] {
+            assert!(parse(source).is_err(), "{:?}", source);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+mod loader;
+mod math;
+mod typeset;
+
+use eframe::egui::{self, Color32, Ui};
+use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
+use loader::MathLoader;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Markdown 渲染器，代码块带语法高亮和复制按钮；`$...$`、`$$...$$`、`\(...\)` 和 `\[...\]`
+/// 中的公式排版后作为图片嵌在文字中
+#[derive(Default)]
+pub struct Markdown {
+    cache: CommonMarkCache,
+}
+
+impl Markdown {
+    pub fn show(&mut self, ui: &mut Ui, text: &str) {
+        if !ui.ctx().is_loader_installed(MathLoader::ID) {
+            ui.ctx().add_image_loader(Arc::new(MathLoader::default()));
+        }
+
+        let size = egui::TextStyle::Body.resolve(ui.style()).size;
+        let text = replace_math(text, size, ui.visuals().text_color());
+        CommonMarkViewer::new()
+            .max_image_width(Some(512))
+            .show(ui, &mut self.cache, &text);
+    }
+}
+
+/// 源码中的一处替换
+type Replacement = (Range<usize>, String);
+
+/// 与 egui_commonmark 相同的解析选项，另外识别 `$` 公式
+fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_DEFINITION_LIST
+        | Options::ENABLE_MATH
+}
+
+/// 在 Markdown 事件流中找出公式，替换为公式图片，其余源码保持原样，
+/// 列表、引用等结构不受影响。代码块和行内代码中的内容不处理
+fn replace_math(text: &str, size: f32, color: Color32) -> String {
+    let mut replacements = Vec::new();
+    // 当前所在元素的源码范围，独立公式只在元素内部改用硬换行
+    let mut elements: Vec<Range<usize>> = Vec::new();
+    // 连续的文字，稍后在其中查找 `\(...\)` 和 `\[...\]`
+    let mut run: Option<Range<usize>> = None;
+    let mut code_block = false;
+
+    for (event, range) in Parser::new_ext(text, options()).into_offset_iter() {
+        if !code_block && matches!(event, Event::Text(_) | Event::SoftBreak | Event::HardBreak) {
+            run = Some(match run {
+                Some(run) => run.start..range.end,
+                None => range,
+            });
+            continue;
+        }
+
+        let element = elements.last().cloned().unwrap_or(0..text.len());
+        if let Some(run) = run.take() {
+            bracketed_math(text, run, &element, size, color, &mut replacements);
+        }
+
+        match event {
+            Event::Start(tag) => {
+                code_block |= matches!(tag, Tag::CodeBlock(_));
+                elements.push(range);
+            }
+            Event::End(tag) => {
+                code_block &= !matches!(tag, TagEnd::CodeBlock);
+                elements.pop();
+            }
+            Event::InlineMath(source) => {
+                let replacement = formula(&text[range.clone()], &source, false, size, color);
+                replacements.push((range, replacement));
+            }
+            Event::DisplayMath(source) => {
+                let replacement = formula(&text[range.clone()], &source, true, size, color);
+                break_around(text, &range, &element, &mut replacements);
+                replacements.push((range, replacement));
+            }
+            _ => {}
+        }
+    }
+    if let Some(run) = run {
+        bracketed_math(text, run, &(0..text.len()), size, color, &mut replacements);
+    }
+
+    apply(text, replacements)
+}
+
+/// 在连续的文字中查找 `\(...\)` 和 `\[...\]`。CommonMark 把 `\(` 当作转义的括号，
+/// 所以要在原始文本中查找
+fn bracketed_math(
+    text: &str,
+    run: Range<usize>,
+    element: &Range<usize>,
+    size: f32,
+    color: Color32,
+    replacements: &mut Vec<Replacement>,
+) {
+    // 转义字符的文字事件不包含前面的反斜杠
+    let mut index = if text[..run.start].ends_with('\\') {
+        run.start - 1
+    } else {
+        run.start
+    };
+
+    while let Some(offset) = text[index..run.end].find('\\') {
+        let start = index + offset;
+        let rest = &text[start..run.end];
+        if rest.starts_with("\\\\") {
+            index = start + 2;
+            continue;
+        }
+
+        let found = delimited(rest, "\\(", "\\)")
+            .map(|found| (found, false))
+            .or_else(|| delimited(rest, "\\[", "\\]").map(|found| (found, true)));
+        let Some(((source, len), display)) = found else {
+            index = start + 1;
+            continue;
+        };
+
+        let range = start..start + len;
+        let replacement = formula(&text[range.clone()], source, display, size, color);
+        if display {
+            break_around(text, &range, element, replacements);
+        }
+        index = range.end;
+        replacements.push((range, replacement));
+    }
+}
+
+/// `text` 以 `open` 开头且之后有 `close` 时，返回公式内容和整段长度
+fn delimited<'a>(text: &'a str, open: &str, close: &str) -> Option<(&'a str, usize)> {
+    let body = text.strip_prefix(open)?;
+    let end = body.find(close)?;
+    Some((&body[..end], open.len() + end + close.len()))
+}
+
+/// 公式图片的 Markdown，替代文字是 LaTeX 源码；无法解析时显示转义后的原始文本
+fn formula(raw: &str, source: &str, display: bool, size: f32, color: Color32) -> String {
+    let source = source.trim();
+    match math::parse(source) {
+        Ok(_) => {
+            let alt = source.split_whitespace().collect::<Vec<_>>().join(" ");
+            format!(
+                "![{}]({})",
+                escape_markdown(&alt),
+                loader::uri(source, display, size, color)
+            )
+        }
+        Err(_) => escape_markdown(raw),
+    }
+}
+
+/// 独立公式前后的软换行改为硬换行，让公式单独占一行。只处理同一元素内
+/// 非空的相邻行，避免在段落末尾留下反斜杠
+fn break_around(
+    text: &str,
+    formula: &Range<usize>,
+    element: &Range<usize>,
+    replacements: &mut Vec<Replacement>,
+) {
+    if element.start <= formula.start {
+        let before = text[element.start..formula.start].trim_end_matches([' ', '\t']);
+        if let Some(line) = before.strip_suffix('\n') {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let content = line.trim_end_matches([' ', '\t']);
+            if !content.is_empty() && !content.ends_with('\n') {
+                let at = element.start + line.len();
+                replacements.push((at..at, "\\".to_string()));
+            }
+        }
+    }
+
+    if formula.end <= element.end {
+        let after = &text[formula.end..element.end];
+        let rest = after.trim_start_matches([' ', '\t']);
+        let next = rest
+            .strip_prefix("\r\n")
+            .or_else(|| rest.strip_prefix('\n'));
+        if next.is_some_and(|next| !next.split('\n').next().unwrap_or("").trim().is_empty()) {
+            let at = formula.end + after.len() - rest.len();
+            replacements.push((at..at, "\\".to_string()));
+        }
+    }
+}
+
+/// 按位置应用替换，跳过重叠的部分；相邻两个公式之间的换行只插入一次反斜杠
+fn apply(text: &str, mut replacements: Vec<Replacement>) -> String {
+    replacements.sort_by_key(|(range, _)| (range.start, range.end));
+    replacements.dedup();
+
+    let mut output = String::with_capacity(text.len());
+    let mut position = 0;
+    for (range, replacement) in replacements {
+        if range.start < position {
+            continue;
+        }
+        output.push_str(&text[position..range.start]);
+        output.push_str(&replacement);
+        position = range.end;
+    }
+    output.push_str(&text[position..]);
+    output
+}
+
+/// 转义 Markdown 标点，让文本不会被当成强调、链接等语法
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(text: &str) -> String {
+        replace_math(text, 16.0, Color32::WHITE)
+    }
+
+    fn image(source: &str, display: bool) -> String {
+        format!(
+            "![{}]({})",
+            escape_markdown(source),
+            loader::uri(source, display, 16.0, Color32::WHITE)
+        )
+    }
+
+    #[test]
+    fn replaces_inline_math_with_images() {
+        assert_eq!(
+            render("so $x^2$ is"),
+            format!("so {} is", image("x^2", false))
+        );
+        assert_eq!(
+            render(r"and \(\alpha\)"),
+            format!("and {}", image(r"\alpha", false))
+        );
+        assert_eq!(
+            render(r"\(\frac{a}{b}\) first"),
+            format!("{} first", image(r"\frac{a}{b}", false))
+        );
+    }
+
+    #[test]
+    fn keeps_lists_and_blockquotes() {
+        let text = "- $a$\n- b $c$\n  1. $d$\n\n> quote $e$\n> more\n";
+        assert_eq!(
+            render(text),
+            format!(
+                "- {}\n- b {}\n  1. {}\n\n> quote {}\n> more\n",
+                image("a", false),
+                image("c", false),
+                image("d", false),
+                image("e", false)
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_currency_and_escaped_dollars() {
+        for text in ["It costs $5 and $10.", "$5 or $6", r"\$x$ y"] {
+            assert_eq!(render(text), text);
+        }
+    }
+
+    #[test]
+    fn skips_code() {
+        assert_eq!(
+            render("`$x$` and $y$"),
+            format!("`$x$` and {}", image("y", false))
+        );
+        assert_eq!(render(r"`\(x\)` b"), r"`\(x\)` b");
+
+        let fenced = "```\n$x$ and $$y$$ \\(z\\)\n```\n$z$\n";
+        assert_eq!(
+            render(fenced),
+            format!("```\n$x$ and $$y$$ \\(z\\)\n```\n{}\n", image("z", false))
+        );
+    }
+
+    #[test]
+    fn puts_display_math_on_its_own_line() {
+        assert_eq!(
+            render("before\n$$\n\\sum x\n$$\nafter"),
+            format!("before\\\n{}\\\nafter", image(r"\sum x", true))
+        );
+        assert_eq!(
+            render("before\n\\[x^2\\]\nafter"),
+            format!("before\\\n{}\\\nafter", image("x^2", true))
+        );
+        // 单独成段时不需要硬换行
+        assert_eq!(
+            render("a\n\n$$x$$\n\nb"),
+            format!("a\n\n{}\n\nb", image("x", true))
+        );
+        assert_eq!(
+            render(r"a \[ x^2 \] b"),
+            format!("a {} b", image("x^2", true))
+        );
+        // 相邻的两个公式之间只有一个硬换行
+        assert_eq!(
+            render("$$a$$\n$$b$$"),
+            format!("{}\\\n{}", image("a", true), image("b", true))
+        );
+    }
+
+    #[test]
+    fn leaves_unclosed_delimiters() {
+        for text in ["$x and y", r"\[x^2", r"\(x", "$a\n\nb$", r"\\(x\\)"] {
+            assert_eq!(render(text), text, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_source_when_parsing_fails() {
+        // 原始 LaTeX 作为文本显示，标点被转义
+        assert_eq!(render(r"$\foo$"), r"\$\\foo\$");
+        assert_eq!(render(r"\(\foo\)"), r"\\\(\\foo\\\)");
+    }
+}
@@ -0,0 +1,1245 @@
+//! 按 TeX 的规则排版公式语法树，再栅格化为图片
+
+use super::math::{Align, Class, Node, Table};
+use ab_glyph::{point, Font, FontRef, GlyphId, OutlineCurve, Point, PxScale, ScaleFont};
+use ab_glyph_rasterizer::Rasterizer;
+use anyhow::Result;
+use eframe::egui::{pos2, vec2, Color32, ColorImage, FontDefinitions, Pos2, Rect, Vec2};
+use std::sync::OnceLock;
+
+/// 斜体变量的倾斜程度（水平偏移与高度之比）
+const SLANT: f32 = 0.2;
+/// 图片四周留出的像素
+const PADDING: f32 = 1.0;
+/// 图片的最大边长，超过时视为无法渲染
+const MAX_SIZE: usize = 4096;
+
+/// TeX 的原子间距：1 为窄空格（3 mu），2 为中空格（4 mu），3 为宽空格（5 mu），
+/// 负数表示只在非上下标中使用。行列顺序与 `Class` 相同
+const SPACING: [[i8; 8]; 8] = [
+    [0, 1, -2, -3, 0, 0, 0, -1],
+    [1, 1, 0, -3, 0, 0, 0, -1],
+    [-2, -2, 0, 0, -2, 0, 0, -2],
+    [-3, -3, 0, 0, -3, 0, 0, -3],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 1, -2, -3, 0, 0, 0, -1],
+    [-1, -1, 0, -1, -1, -1, -1, -1],
+    [-1, 1, -2, -3, -1, 0, -1, -1],
+];
+
+/// 排版并栅格化公式，每个点对应一个像素。`size` 是正文字号，
+/// 图片的竖直中心与同字号正文的中线对齐，方便和文字排在同一行
+pub fn render(node: &Node, display: bool, size: f32, color: Color32) -> Result<ColorImage> {
+    let style = Style {
+        size,
+        level: 0,
+        display,
+    };
+    let layout = layout(node, style);
+
+    let middle = fonts().middle * size;
+    let mut above = layout.ascent + PADDING;
+    let mut below = layout.descent + PADDING;
+    if above - below < 2.0 * middle {
+        above = below + 2.0 * middle;
+    } else {
+        below = above - 2.0 * middle;
+    }
+    let (above, below) = (above.ceil(), below.ceil());
+
+    let width = (layout.width + 2.0 * PADDING).ceil() as usize;
+    let height = (above + below) as usize;
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(anyhow::anyhow!(
+            "Formula is too large ({}×{})",
+            width,
+            height
+        ));
+    }
+
+    let mut coverage = vec![0.0; width * height];
+    let origin = vec2(PADDING, above);
+    for item in &layout.items {
+        fill(&mut coverage, width, &item.outline(origin));
+    }
+    Ok(ColorImage {
+        size: [width, height],
+        pixels: coverage
+            .into_iter()
+            .map(|coverage| color.gamma_multiply(coverage))
+            .collect(),
+    })
+}
+
+struct Face {
+    font: FontRef<'static>,
+    /// 1 em 对应的 `PxScale`，与 egui 的字号换算一致
+    scale: f32,
+}
+
+/// 与 egui 默认字体相同的字体，依次查找字符
+struct Fonts {
+    faces: Vec<Face>,
+    /// 数学轴（分数线、运算符的中线）高于基线的距离，单位 em
+    axis: f32,
+    /// 正文行的中线高于基线的距离，单位 em
+    middle: f32,
+}
+
+fn fonts() -> &'static Fonts {
+    static DEFINITIONS: OnceLock<FontDefinitions> = OnceLock::new();
+    static FONTS: OnceLock<Fonts> = OnceLock::new();
+
+    FONTS.get_or_init(|| {
+        let definitions = DEFINITIONS.get_or_init(FontDefinitions::default);
+        let faces: Vec<Face> = [
+            "Ubuntu-Light",
+            "Hack",
+            "NotoEmoji-Regular",
+            "emoji-icon-font",
+        ]
+        .into_iter()
+        .filter_map(|name| {
+            let data = definitions.font_data.get(name)?;
+            let font = FontRef::try_from_slice_and_index(&data.font, data.index).ok()?;
+            let scale = font.height_unscaled() / font.units_per_em()? * data.tweak.scale;
+            Some(Face { font, scale })
+        })
+        .collect();
+
+        let (axis, middle) = match faces.first() {
+            Some(face) => {
+                let scaled = face.font.as_scaled(face.scale);
+                let plus = face.font.outline(face.font.glyph_id('+'));
+                let axis = plus.map_or(0.25, |outline| {
+                    (outline.bounds.min.y + outline.bounds.max.y) / 2.0 * scaled.v_scale_factor()
+                });
+                let middle = (scaled.ascent() + scaled.descent() - scaled.line_gap()) / 2.0;
+                (axis, middle)
+            }
+            None => (0.25, 0.3),
+        };
+        Fonts {
+            faces,
+            axis,
+            middle,
+        }
+    })
+}
+
+/// 一个字形的位置信息
+struct Glyph {
+    face: usize,
+    id: GlyphId,
+    scale: PxScale,
+    advance: f32,
+    /// 墨迹范围，y 向下，原点在基线起点；没有轮廓（空格）时为 `None`
+    ink: Option<Rect>,
+}
+
+impl Fonts {
+    /// 在第一个包含该字符的字体中查找字形，`x` 和 `y` 是横向和纵向的字号
+    fn glyph(&self, c: char, x: f32, y: f32) -> Option<Glyph> {
+        let (face, id) = self.faces.iter().enumerate().find_map(|(index, face)| {
+            let id = face.font.glyph_id(c);
+            (id.0 != 0).then_some((index, id))
+        })?;
+
+        let font = &self.faces[face];
+        let scale = PxScale {
+            x: x * font.scale,
+            y: y * font.scale,
+        };
+        let scaled = font.font.as_scaled(scale);
+        // 轮廓范围的纵坐标向上，`min.y` 是顶部，这里换算成向下的坐标
+        let ink = font.font.outline(id).map(|outline| {
+            let (h, v) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+            let (top, bottom) = (-outline.bounds.min.y * v, -outline.bounds.max.y * v);
+            Rect::from_min_max(
+                pos2(outline.bounds.min.x * h, top.min(bottom)),
+                pos2(outline.bounds.max.x * h, top.max(bottom)),
+            )
+        });
+        Some(Glyph {
+            face,
+            id,
+            scale,
+            advance: scaled.h_advance(id),
+            ink,
+        })
+    }
+}
+
+/// 字体中没有的字符改用相近的字符
+fn substitute(c: char) -> Option<&'static str> {
+    Some(match c {
+        '⋃' => "∪",
+        '⋂' => "∩",
+        '⋁' => "∨",
+        '⋀' => "∧",
+        '⨁' => "⊕",
+        '⨂' => "⊗",
+        '∐' => "∏",
+        '∬' => "∫∫",
+        '∭' => "∫∫∫",
+        '∮' => "∫",
+        '⟹' => "⇒",
+        '⟸' => "⇐",
+        '⟺' => "⇔",
+        '⟶' => "→",
+        '⟵' => "←",
+        '⟼' => "↦",
+        '≔' => ":=",
+        '≪' => "<<",
+        '≫' => ">>",
+        '∥' => "‖",
+        '⊨' => "|=",
+        '∤' => "∣",
+        '∗' | '⋆' => "*",
+        '∙' => "•",
+        '∖' => "\\",
+        '⋯' => "···",
+        '⋮' => ":",
+        '⋱' => "···",
+        'ϵ' => "ε",
+        'ϑ' => "θ",
+        'ϖ' => "π",
+        'ϱ' => "ρ",
+        'ϕ' => "φ",
+        'ℏ' => "ħ",
+        'ℜ' => "Re",
+        'ℑ' => "Im",
+        // 没有双线体时用普通的大写字母
+        'ℝ' => "R",
+        'ℕ' => "N",
+        'ℤ' => "Z",
+        'ℚ' => "Q",
+        'ℂ' => "C",
+        'ℙ' => "P",
+        'ℍ' => "H",
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    /// 正文字号
+    size: f32,
+    /// 0 为正文，1 为上下标，2 为二级上下标
+    level: usize,
+    display: bool,
+}
+
+impl Style {
+    fn em(self) -> f32 {
+        self.size * [1.0, 0.7, 0.5][self.level]
+    }
+
+    fn script(self) -> Self {
+        Self {
+            level: (self.level + 1).min(2),
+            display: false,
+            ..self
+        }
+    }
+
+    /// 分子和分母的样式
+    fn fraction(self) -> Self {
+        if self.display {
+            Self {
+                display: false,
+                ..self
+            }
+        } else {
+            self.script()
+        }
+    }
+
+    /// 分数线等横线的粗细，至少一个像素
+    fn rule(self) -> f32 {
+        (self.size * 0.05).max(1.0)
+    }
+
+    fn axis(self) -> f32 {
+        fonts().axis * self.em()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Glyph {
+        face: usize,
+        id: GlyphId,
+        scale: PxScale,
+        /// 基线起点
+        position: Pos2,
+        slant: f32,
+    },
+    Rule(Rect),
+    Line {
+        from: Pos2,
+        to: Pos2,
+        width: f32,
+    },
+}
+
+impl Item {
+    fn translate(self, offset: Vec2) -> Self {
+        match self {
+            Item::Glyph {
+                face,
+                id,
+                scale,
+                position,
+                slant,
+            } => Item::Glyph {
+                face,
+                id,
+                scale,
+                position: position + offset,
+                slant,
+            },
+            Item::Rule(rect) => Item::Rule(rect.translate(offset)),
+            Item::Line { from, to, width } => Item::Line {
+                from: from + offset,
+                to: to + offset,
+                width,
+            },
+        }
+    }
+
+    /// 转换为图片坐标中的轮廓
+    fn outline(&self, origin: Vec2) -> Vec<OutlineCurve> {
+        let at = |p: Pos2| point(p.x, p.y);
+        match self {
+            Item::Glyph {
+                face,
+                id,
+                scale,
+                position,
+                slant,
+            } => {
+                let font = &fonts().faces[*face].font;
+                let Some(outline) = font.outline(*id) else {
+                    return Vec::new();
+                };
+                let scaled = font.as_scaled(*scale);
+                let (h, v) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+                let position = *position + origin;
+                let transform =
+                    |p: Point| point(position.x + p.x * h + slant * p.y * v, position.y - p.y * v);
+                outline
+                    .curves
+                    .into_iter()
+                    .map(|curve| match curve {
+                        OutlineCurve::Line(a, b) => OutlineCurve::Line(transform(a), transform(b)),
+                        OutlineCurve::Quad(a, b, c) => {
+                            OutlineCurve::Quad(transform(a), transform(b), transform(c))
+                        }
+                        OutlineCurve::Cubic(a, b, c, d) => OutlineCurve::Cubic(
+                            transform(a),
+                            transform(b),
+                            transform(c),
+                            transform(d),
+                        ),
+                    })
+                    .collect()
+            }
+            Item::Rule(rect) => {
+                // 横线对齐到整像素，避免发虚
+                let rect = rect.translate(origin);
+                let top = rect.min.y.round();
+                let bottom = rect.max.y.round().max(top + 1.0);
+                polygon(&[
+                    at(pos2(rect.min.x, top)),
+                    at(pos2(rect.max.x, top)),
+                    at(pos2(rect.max.x, bottom)),
+                    at(pos2(rect.min.x, bottom)),
+                ])
+            }
+            Item::Line { from, to, width } => {
+                let (from, to) = (*from + origin, *to + origin);
+                let normal = (to - from).normalized().rot90() * (width / 2.0);
+                polygon(&[
+                    at(from + normal),
+                    at(to + normal),
+                    at(to - normal),
+                    at(from - normal),
+                ])
+            }
+        }
+    }
+}
+
+fn polygon(points: &[Point]) -> Vec<OutlineCurve> {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| OutlineCurve::Line(*a, *b))
+        .collect()
+}
+
+/// 把一个轮廓的覆盖率叠加到图片上
+fn fill(coverage: &mut [f32], width: usize, curves: &[OutlineCurve]) {
+    let points = curves.iter().flat_map(|curve| match curve {
+        OutlineCurve::Line(a, b) => vec![*a, *b],
+        OutlineCurve::Quad(a, b, c) => vec![*a, *b, *c],
+        OutlineCurve::Cubic(a, b, c, d) => vec![*a, *b, *c, *d],
+    });
+    let (min, max) = points.fold(
+        (point(f32::MAX, f32::MAX), point(f32::MIN, f32::MIN)),
+        |(min, max), p| {
+            (
+                point(min.x.min(p.x), min.y.min(p.y)),
+                point(max.x.max(p.x), max.y.max(p.y)),
+            )
+        },
+    );
+    if min.x > max.x || !(min.x.is_finite() && max.x.is_finite()) {
+        return;
+    }
+
+    let (left, top) = (min.x.floor(), min.y.floor());
+    let mut rasterizer = Rasterizer::new(
+        (max.x - left).ceil() as usize + 1,
+        (max.y - top).ceil() as usize + 1,
+    );
+    let shift = |p: Point| point(p.x - left, p.y - top);
+    for curve in curves {
+        match *curve {
+            OutlineCurve::Line(a, b) => rasterizer.draw_line(shift(a), shift(b)),
+            OutlineCurve::Quad(a, b, c) => rasterizer.draw_quad(shift(a), shift(b), shift(c)),
+            OutlineCurve::Cubic(a, b, c, d) => {
+                rasterizer.draw_cubic(shift(a), shift(b), shift(c), shift(d))
+            }
+        }
+    }
+
+    let height = coverage.len() / width;
+    rasterizer.for_each_pixel_2d(|x, y, alpha| {
+        let x = left as i64 + x as i64;
+        let y = top as i64 + y as i64;
+        if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+            let pixel = &mut coverage[y as usize * width + x as usize];
+            *pixel = 1.0 - (1.0 - *pixel) * (1.0 - alpha.min(1.0));
+        }
+    });
+}
+
+/// 排版结果，坐标 y 向下，原点在基线起点
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    width: f32,
+    /// 基线以上的高度
+    ascent: f32,
+    /// 基线以下的深度
+    descent: f32,
+    items: Vec<Item>,
+}
+
+impl Layout {
+    fn space(width: f32) -> Self {
+        Self {
+            width,
+            ..Default::default()
+        }
+    }
+
+    /// 把 `other` 的基线起点放在 `(x, y)`
+    fn add(&mut self, other: Layout, x: f32, y: f32) {
+        let offset = vec2(x, y);
+        self.items
+            .extend(other.items.into_iter().map(|item| item.translate(offset)));
+        self.width = self.width.max(x + other.width);
+        self.ascent = self.ascent.max(other.ascent - y);
+        self.descent = self.descent.max(other.descent + y);
+    }
+
+    fn rule(&mut self, rect: Rect) {
+        self.width = self.width.max(rect.max.x);
+        self.ascent = self.ascent.max(-rect.min.y);
+        self.descent = self.descent.max(rect.max.y);
+        self.items.push(Item::Rule(rect));
+    }
+
+    fn line(&mut self, from: Pos2, to: Pos2, width: f32) {
+        let bounds = Rect::from_two_pos(from, to).expand(width / 2.0);
+        self.width = self.width.max(bounds.max.x);
+        self.ascent = self.ascent.max(-bounds.min.y);
+        self.descent = self.descent.max(bounds.max.y);
+        self.items.push(Item::Line { from, to, width });
+    }
+
+    /// 放置一个字形；倾斜的字形宽度包含超出步进的部分
+    fn glyph(glyph: &Glyph, slant: f32) -> Self {
+        let mut layout = Self::space(glyph.advance);
+        if let Some(ink) = glyph.ink {
+            layout.ascent = (-ink.min.y).max(0.0);
+            layout.descent = ink.max.y.max(0.0);
+            if slant > 0.0 {
+                layout.width = layout.width.max(ink.max.x + slant * layout.ascent);
+            }
+        }
+        layout.items.push(Item::Glyph {
+            face: glyph.face,
+            id: glyph.id,
+            scale: glyph.scale,
+            position: Pos2::ZERO,
+            slant,
+        });
+        layout
+    }
+}
+
+/// 排版一段文字，缺少的字符依次尝试替代字符和 `◻`
+fn text(text: &str, em: f32, slant: f32) -> Layout {
+    let fonts = fonts();
+    let mut layout = Layout::default();
+    let mut x = 0.0;
+    let mut previous: Option<(usize, GlyphId)> = None;
+
+    for c in text.chars() {
+        let Some(glyph) = fonts.glyph(c, em, em) else {
+            let replacement = match (substitute(c), c) {
+                (Some(replacement), _) => replacement,
+                (None, '◻') => "?",
+                (None, '?') => continue,
+                (None, _) => "◻",
+            };
+            let replacement = self::text(replacement, em, slant);
+            let width = replacement.width;
+            layout.add(replacement, x, 0.0);
+            x += width;
+            previous = None;
+            continue;
+        };
+
+        if let Some((face, id)) = previous.filter(|(face, _)| *face == glyph.face) {
+            x += fonts.faces[face]
+                .font
+                .as_scaled(glyph.scale)
+                .kern(id, glyph.id);
+        }
+        previous = Some((glyph.face, glyph.id));
+        let glyph = Layout::glyph(&glyph, slant);
+        let width = glyph.width;
+        layout.add(glyph, x, 0.0);
+        x += width;
+    }
+    layout.width = x;
+    layout
+}
+
+fn layout(node: &Node, style: Style) -> Layout {
+    let em = style.em();
+    match node {
+        Node::Variable(c) => text(c.encode_utf8(&mut [0; 4]), em, SLANT),
+        // 字体中没有尖括号，和 `\left\langle` 一样画出来
+        Node::Symbol(c @ ('⟨' | '⟩'), _) => self::delimiter(*c, 0.0, style),
+        Node::Symbol(c, _) => text(c.encode_utf8(&mut [0; 4]), em, 0.0),
+        Node::Text(content) => text(content, em, 0.0),
+        Node::Operator {
+            text: name, large, ..
+        } if *large => large_operator(name, style),
+        Node::Operator { text: name, .. } => text(name, em, 0.0),
+        Node::Space(width) => Layout::space(width * em),
+        Node::Row(nodes) => row(nodes, style),
+        Node::Scripts { base, sup, sub } => scripts(base, sup.as_deref(), sub.as_deref(), style),
+        Node::Fraction {
+            numerator,
+            denominator,
+            rule,
+            display,
+        } => fraction(numerator, denominator, *rule, *display, style),
+        Node::Radical { index, radicand } => radical(index.as_deref(), radicand, style),
+        Node::Accent {
+            base,
+            accent,
+            stretch,
+        } => self::accent(base, *accent, *stretch, style),
+        Node::Overline(base) => {
+            let t = style.rule();
+            let mut layout = layout(base, style);
+            let top = layout.ascent + 3.0 * t;
+            let width = layout.width;
+            layout.rule(Rect::from_min_max(pos2(0.0, -top - t), pos2(width, -top)));
+            layout.ascent += t;
+            layout
+        }
+        Node::Underline(base) => {
+            let t = style.rule();
+            let mut layout = layout(base, style);
+            let top = layout.descent + 2.0 * t;
+            let width = layout.width;
+            layout.rule(Rect::from_min_max(pos2(0.0, top), pos2(width, top + t)));
+            layout.descent += t;
+            layout
+        }
+        Node::Fenced { open, body, close } => fenced(*open, body, *close, style),
+        Node::Delimiter { delimiter, size } => self::delimiter(*delimiter, size * em, style),
+        Node::Table(table) => self::table(table, style),
+    }
+}
+
+/// 决定间距的类别，空白不参与
+fn class(node: &Node) -> Option<Class> {
+    Some(match node {
+        Node::Symbol(_, class) => *class,
+        Node::Operator { .. } => Class::Op,
+        Node::Space(_) => return None,
+        Node::Scripts { base, .. } => return class(base),
+        Node::Fraction { .. } | Node::Fenced { .. } => Class::Inner,
+        Node::Delimiter { delimiter, .. } => match delimiter {
+            '(' | '[' | '{' | '⟨' | '⌊' | '⌈' => Class::Open,
+            ')' | ']' | '}' | '⟩' | '⌋' | '⌉' => Class::Close,
+            _ => Class::Ord,
+        },
+        _ => Class::Ord,
+    })
+}
+
+/// 按 TeX 的规则确定每个元素的类别：没有左操作数或右操作数的二元运算符当作普通符号
+fn classes(nodes: &[Node]) -> Vec<Option<Class>> {
+    let mut classes: Vec<Option<Class>> = nodes.iter().map(class).collect();
+
+    let mut previous = None;
+    for class in classes.iter_mut().flatten() {
+        if *class == Class::Bin
+            && matches!(
+                previous,
+                None | Some(Class::Bin | Class::Op | Class::Rel | Class::Open | Class::Punct)
+            )
+        {
+            *class = Class::Ord;
+        }
+        previous = Some(*class);
+    }
+
+    let mut next = None;
+    for class in classes.iter_mut().rev().flatten() {
+        if *class == Class::Bin
+            && matches!(next, None | Some(Class::Rel | Class::Close | Class::Punct))
+        {
+            *class = Class::Ord;
+        }
+        next = Some(*class);
+    }
+    classes
+}
+
+fn spacing(left: Class, right: Class, style: Style) -> f32 {
+    let code = SPACING[left as usize][right as usize];
+    if code < 0 && style.level > 0 {
+        return 0.0;
+    }
+    let mu = [0.0, 3.0, 4.0, 5.0][code.unsigned_abs() as usize];
+    mu * style.em() / 18.0
+}
+
+fn row(nodes: &[Node], style: Style) -> Layout {
+    let mut layout = Layout::default();
+    let mut x = 0.0;
+    let mut previous = None;
+
+    for (node, class) in nodes.iter().zip(classes(nodes)) {
+        if let (Some(previous), Some(class)) = (previous, class) {
+            x += spacing(previous, class, style);
+        }
+        let child = self::layout(node, style);
+        let width = child.width;
+        layout.add(child, x, 0.0);
+        x += width;
+        if class.is_some() {
+            previous = class;
+        }
+    }
+    layout.width = x.max(0.0);
+    layout
+}
+
+/// 大型运算符放大后以数学轴为中心
+fn large_operator(name: &str, style: Style) -> Layout {
+    let integral = name.starts_with(['∫', '∬', '∭', '∮']);
+    let factor = match (style.display, integral) {
+        (true, true) => 2.0,
+        (true, false) => 1.6,
+        (false, true) => 1.3,
+        (false, false) => 1.1,
+    };
+    let glyphs = text(name, style.em() * factor, 0.0);
+    let center = (glyphs.ascent - glyphs.descent) / 2.0;
+    let mut layout = Layout::space(glyphs.width);
+    layout.add(glyphs, 0.0, center - style.axis());
+    layout
+}
+
+fn scripts(base: &Node, sup: Option<&Node>, sub: Option<&Node>, style: Style) -> Layout {
+    if style.display && matches!(base, Node::Operator { limits: true, .. }) {
+        return limits(base, sup, sub, style);
+    }
+
+    let em = style.em();
+    let script = style.script();
+    let t = style.rule();
+    let base_layout = layout(base, style);
+    let sup = sup.map(|node| layout(node, script));
+    let sub = sub.map(|node| layout(node, script));
+
+    // 复合的底数按它的高度和深度放置角标
+    let character = matches!(base, Node::Variable(_) | Node::Symbol(..) | Node::Text(_));
+    let (mut up, mut down) = if character {
+        (0.0, 0.0)
+    } else {
+        (
+            base_layout.ascent - 0.386 * script.em(),
+            base_layout.descent + 0.05 * script.em(),
+        )
+    };
+
+    if let Some(sup) = &sup {
+        let shift = if style.display { 0.413 } else { 0.363 };
+        up = up.max(shift * em).max(sup.descent + 0.11 * em);
+    }
+    match (&sup, &sub) {
+        (None, Some(sub)) => down = down.max(0.15 * em).max(sub.ascent - 0.35 * em),
+        (Some(sup), Some(sub)) => {
+            down = down.max(0.247 * em);
+            let gap = (up - sup.descent) - (sub.ascent - down);
+            if gap < 4.0 * t {
+                down += 4.0 * t - gap;
+            }
+        }
+        _ => {}
+    }
+
+    let x = base_layout.width;
+    let mut layout = base_layout;
+    if let Some(sup) = sup {
+        layout.add(sup, x, -up);
+    }
+    if let Some(sub) = sub {
+        layout.add(sub, x, down);
+    }
+    layout.width += 0.05 * em;
+    layout
+}
+
+/// 显示模式中放在运算符正上方和正下方的上下限
+fn limits(base: &Node, sup: Option<&Node>, sub: Option<&Node>, style: Style) -> Layout {
+    let gap = 0.15 * style.em();
+    let base = layout(base, style);
+    let sup = sup.map(|node| layout(node, style.script()));
+    let sub = sub.map(|node| layout(node, style.script()));
+    let width = [Some(&base), sup.as_ref(), sub.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|layout| layout.width)
+        .fold(0.0, f32::max);
+
+    let (ascent, descent) = (base.ascent, base.descent);
+    let mut layout = Layout::space(width);
+    let x = (width - base.width) / 2.0;
+    layout.add(base, x, 0.0);
+    if let Some(sup) = sup {
+        let (x, y) = ((width - sup.width) / 2.0, -(ascent + gap + sup.descent));
+        layout.add(sup, x, y);
+    }
+    if let Some(sub) = sub {
+        let (x, y) = ((width - sub.width) / 2.0, descent + gap + sub.ascent);
+        layout.add(sub, x, y);
+    }
+    layout
+}
+
+fn fraction(
+    numerator: &Node,
+    denominator: &Node,
+    rule: bool,
+    display: Option<bool>,
+    style: Style,
+) -> Layout {
+    let style = match display {
+        Some(display) => Style {
+            level: 0,
+            display,
+            ..style
+        },
+        None => style,
+    };
+    let em = style.em();
+    let t = style.rule();
+    let axis = style.axis();
+    let numerator = layout(numerator, style.fraction());
+    let denominator = layout(denominator, style.fraction());
+
+    let (mut up, mut down) = match (style.display, rule) {
+        (true, _) => (0.677 * em, 0.686 * em),
+        (false, true) => (0.394 * em, 0.345 * em),
+        (false, false) => (0.444 * em, 0.345 * em),
+    };
+    if rule {
+        let clearance = if style.display { 3.0 * t } else { t };
+        up = up.max(axis + t / 2.0 + clearance + numerator.descent);
+        down = down.max(clearance + t / 2.0 - axis + denominator.ascent);
+    } else {
+        let clearance = if style.display { 7.0 * t } else { 3.0 * t };
+        let gap = (up - numerator.descent) - (denominator.ascent - down);
+        if gap < clearance {
+            up += (clearance - gap) / 2.0;
+            down += (clearance - gap) / 2.0;
+        }
+    }
+
+    // 分数线比分子分母略宽，两侧再留出一点空白
+    let padding = 0.08 * em;
+    let width = numerator.width.max(denominator.width) + 2.0 * padding;
+    let mut layout = Layout::space(width + 2.0 * padding);
+    let x = padding + (width - numerator.width) / 2.0;
+    layout.add(numerator, x, -up);
+    let x = padding + (width - denominator.width) / 2.0;
+    layout.add(denominator, x, down);
+    if rule {
+        layout.rule(Rect::from_min_max(
+            pos2(padding, -axis - t / 2.0),
+            pos2(padding + width, -axis + t / 2.0),
+        ));
+    }
+    layout
+}
+
+fn radical(index: Option<&Node>, radicand: &Node, style: Style) -> Layout {
+    let em = style.em();
+    let t = style.rule();
+    let radicand = layout(radicand, style);
+    let gap = t + if style.display { 0.13 } else { 0.06 } * em;
+
+    // 根号的竖直范围：顶部与横线相接，底部到被开方数的最低处
+    let top = -(radicand.ascent + gap + t / 2.0);
+    let bottom = radicand.descent.max(0.05 * em);
+    let height = bottom - top;
+    let width = (0.5 * em + 0.08 * height).min(0.9 * em);
+    let tick = bottom - 0.45 * height.min(em);
+
+    let mut layout = Layout::default();
+    let mut x = 0.0;
+    if let Some(index) = index {
+        let index = self::layout(index, style.script().script());
+        x = (index.width - 0.55 * width).max(0.0);
+        let y = bottom - 0.6 * height - index.descent;
+        let index_x = x + 0.55 * width - index.width;
+        layout.add(index, index_x, y);
+    }
+
+    layout.line(pos2(x, tick), pos2(x + 0.25 * width, tick - 0.06 * em), t);
+    layout.line(
+        pos2(x + 0.25 * width, tick - 0.06 * em),
+        pos2(x + 0.5 * width, bottom),
+        2.0 * t,
+    );
+    layout.line(pos2(x + 0.5 * width, bottom), pos2(x + width, top), t);
+
+    let left = x + width;
+    let radicand_width = radicand.width;
+    layout.add(radicand, left + 0.05 * em, 0.0);
+    layout.rule(Rect::from_min_max(
+        pos2(left, top - t / 2.0),
+        pos2(left + radicand_width + 0.1 * em, top + t / 2.0),
+    ));
+    layout.width += 0.05 * em;
+    layout
+}
+
+fn accent(base: &Node, accent: char, stretch: bool, style: Style) -> Layout {
+    let em = style.em();
+    let t = style.rule();
+    let base_layout = layout(base, style);
+    let (width, ascent) = (base_layout.width, base_layout.ascent);
+    // 斜体字母的重音符号右移到字母顶部的中间
+    let shift = if matches!(base, Node::Variable(_)) {
+        SLANT * ascent / 2.0
+    } else {
+        0.0
+    };
+    let bottom = -(ascent + 0.08 * em);
+    let mut layout = base_layout;
+
+    if matches!(accent, '→' | '←') {
+        let length = if stretch { width } else { 0.45 * em };
+        let left = shift + (width - length) / 2.0;
+        let y = bottom - 0.12 * em;
+        let (tip, end) = if accent == '→' {
+            (left + length, left)
+        } else {
+            (left, left + length)
+        };
+        let back = if accent == '→' {
+            -0.15 * em
+        } else {
+            0.15 * em
+        };
+        layout.line(pos2(end, y), pos2(tip, y), t);
+        layout.line(pos2(tip, y), pos2(tip + back, y - 0.1 * em), t);
+        layout.line(pos2(tip, y), pos2(tip + back, y + 0.1 * em), t);
+        return layout;
+    }
+
+    let Some(glyph) = fonts().glyph(accent, em, em) else {
+        return layout;
+    };
+    let Some(ink) = glyph.ink else {
+        return layout;
+    };
+    // 拉伸的重音符号横向放大到与内容同宽
+    let glyph = if stretch && ink.width() < width * 0.9 {
+        fonts()
+            .glyph(accent, em * width * 0.9 / ink.width(), em)
+            .unwrap_or(glyph)
+    } else {
+        glyph
+    };
+    let ink = glyph.ink.unwrap_or(ink);
+    let x = shift + width / 2.0 - ink.center().x;
+    layout.add(Layout::glyph(&glyph, 0.0), x, bottom - ink.max.y);
+    layout
+}
+
+/// 定界符需要覆盖的高度：以数学轴为中心包住内容
+fn fenced(open: Option<char>, body: &Node, close: Option<char>, style: Style) -> Layout {
+    let em = style.em();
+    let body = layout(body, style);
+    let axis = style.axis();
+    let half = (body.ascent - axis).max(body.descent + axis);
+    let height = (2.0 * half * 0.901).max(2.0 * half - 0.5 * em);
+
+    let side = |delimiter: Option<char>| match delimiter {
+        Some(delimiter) => self::delimiter(delimiter, height, style),
+        None => Layout::space(0.12 * em),
+    };
+
+    let mut layout = Layout::default();
+    let mut x = 0.0;
+    for part in [side(open), body, side(close)] {
+        let width = part.width;
+        layout.add(part, x, 0.0);
+        x += width;
+    }
+    layout
+}
+
+/// 绘制至少 `height` 高的定界符
+fn delimiter(delimiter: char, height: f32, style: Style) -> Layout {
+    let em = style.em();
+    let t = style.rule();
+    let axis = style.axis();
+    let natural = fonts()
+        .glyph('(', em, em)
+        .and_then(|glyph| glyph.ink)
+        .map_or(em, |ink| ink.height());
+
+    if matches!(delimiter, '(' | ')' | '{' | '}') {
+        let Some(glyph) = fonts().glyph(delimiter, em, em) else {
+            return text(delimiter.encode_utf8(&mut [0; 4]), em, 0.0);
+        };
+        let Some(ink) = glyph.ink.filter(|ink| height > ink.height() * 1.05) else {
+            return Layout::glyph(&glyph, 0.0);
+        };
+        // 纵向拉伸字形，横向略微加宽，再以数学轴为中心
+        let stretch = height / ink.height();
+        let widen = (1.0 + (stretch - 1.0) * 0.15).min(1.6);
+        let Some(glyph) = fonts().glyph(delimiter, em * widen, em * stretch) else {
+            return Layout::glyph(&glyph, 0.0);
+        };
+        let center = glyph.ink.map_or(0.0, |ink| ink.center().y);
+        let mut layout = Layout::space(glyph.advance);
+        layout.add(Layout::glyph(&glyph, 0.0), 0.0, -axis - center);
+        return layout;
+    }
+
+    let height = height.max(natural);
+    let top = -axis - height / 2.0;
+    let bottom = -axis + height / 2.0;
+    let mut layout = Layout::default();
+    let vertical = |layout: &mut Layout, x: f32| {
+        layout.rule(Rect::from_min_max(pos2(x, top), pos2(x + t, bottom)))
+    };
+    let horizontal = |layout: &mut Layout, from: f32, to: f32, y: f32| {
+        layout.rule(Rect::from_min_max(pos2(from, y), pos2(to, y + t)))
+    };
+
+    let width = match delimiter {
+        '[' | ']' | '⌊' | '⌋' | '⌈' | '⌉' => {
+            let (left, right) = (0.1 * em, 0.32 * em);
+            let right_side = matches!(delimiter, ']' | '⌋' | '⌉');
+            let stem = if right_side { right - t } else { left };
+            vertical(&mut layout, stem);
+            if matches!(delimiter, '[' | ']' | '⌈' | '⌉') {
+                horizontal(&mut layout, left, right, top);
+            }
+            if matches!(delimiter, '[' | ']' | '⌊' | '⌋') {
+                horizontal(&mut layout, left, right, bottom - t);
+            }
+            0.42 * em
+        }
+        '|' => {
+            vertical(&mut layout, 0.12 * em);
+            0.24 * em + t
+        }
+        '‖' => {
+            vertical(&mut layout, 0.12 * em);
+            vertical(&mut layout, 0.26 * em);
+            0.38 * em + t
+        }
+        '⟨' | '⟩' => {
+            let depth = (0.12 * height).clamp(0.25 * em, 0.5 * em);
+            let (point, ends) = if delimiter == '⟨' {
+                (0.08 * em, 0.08 * em + depth)
+            } else {
+                (0.08 * em + depth, 0.08 * em)
+            };
+            layout.line(pos2(ends, top), pos2(point, -axis), t);
+            layout.line(pos2(point, -axis), pos2(ends, bottom), t);
+            depth + 0.16 * em
+        }
+        '/' => {
+            let width = 0.3 * height;
+            layout.line(pos2(0.05 * em, bottom), pos2(0.05 * em + width, top), t);
+            width + 0.1 * em
+        }
+        c => return text(c.encode_utf8(&mut [0; 4]), em, 0.0),
+    };
+    layout.width = width;
+    layout
+}
+
+fn table(table: &Table, style: Style) -> Layout {
+    let em = style.em();
+    let style = Style {
+        display: table.display,
+        ..style
+    };
+    let alignments = table.align.len().max(1);
+    let align = |column: usize| {
+        table
+            .align
+            .get(column % alignments)
+            .copied()
+            .unwrap_or(Align::Center)
+    };
+
+    // 对齐环境中 `&` 之后的单元格，开头的关系符前面要有间距，相当于前面接着一个普通符号
+    let cells: Vec<Vec<Layout>> = table
+        .rows
+        .iter()
+        .map(|cells| {
+            cells
+                .iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    if column > 0
+                        && align(column - 1) == Align::Right
+                        && align(column) == Align::Left
+                    {
+                        let mut nodes = vec![Node::Row(Vec::new())];
+                        match cell {
+                            Node::Row(cell) => nodes.extend(cell.iter().cloned()),
+                            cell => nodes.push(cell.clone()),
+                        }
+                        row(&nodes, style)
+                    } else {
+                        layout(cell, style)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0.0_f32; columns];
+    for row in &cells {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(cell.width);
+        }
+    }
+    let mut lefts = Vec::with_capacity(columns);
+    let mut x = 0.0;
+    for (column, width) in widths.iter().enumerate() {
+        lefts.push(x);
+        let gap = match table.gaps.as_slice() {
+            [] => 1.0,
+            gaps => gaps[column % gaps.len()],
+        };
+        x += width + gap * em;
+    }
+    let total_width = match (lefts.last(), widths.last()) {
+        (Some(left), Some(width)) => left + width,
+        _ => 0.0,
+    };
+
+    // 每行至少有正常行高，行间距在显示模式中更大
+    let row_gap = if table.display { 0.5 } else { 0.2 } * em;
+    let metrics: Vec<(f32, f32)> = cells
+        .iter()
+        .map(|row| {
+            row.iter()
+                .fold((0.7 * em, 0.3 * em), |(ascent, descent), cell| {
+                    (ascent.max(cell.ascent), descent.max(cell.descent))
+                })
+        })
+        .collect();
+    let height = metrics
+        .iter()
+        .map(|(ascent, descent)| ascent + descent)
+        .sum::<f32>()
+        + row_gap * metrics.len().saturating_sub(1) as f32;
+
+    let mut layout = Layout::space(total_width);
+    let mut y = -style.axis() - height / 2.0;
+    for (row, (ascent, descent)) in cells.into_iter().zip(metrics) {
+        y += ascent;
+        for (column, cell) in row.into_iter().enumerate() {
+            let x = lefts[column]
+                + match align(column) {
+                    Align::Left => 0.0,
+                    Align::Center => (widths[column] - cell.width) / 2.0,
+                    Align::Right => widths[column] - cell.width,
+                };
+            layout.add(cell, x, y);
+        }
+        y += descent + row_gap;
+    }
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::components::markdown::math::parse;
+
+    fn typeset(source: &str, display: bool) -> Layout {
+        layout(
+            &parse(source).unwrap(),
+            Style {
+                size: 16.0,
+                level: 0,
+                display,
+            },
+        )
+    }
+
+    /// 所有字形基线起点的纵坐标
+    fn baselines(layout: &Layout) -> Vec<f32> {
+        layout
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Glyph { position, .. } => Some(position.y),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn glyph_xs(layout: &Layout) -> Vec<f32> {
+        layout
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Glyph { position, .. } => Some(position.x),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stacks_fractions_around_the_rule() {
+        let layout = typeset(r"\frac{a}{b}", true);
+        let rule = layout
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Rule(rect) => Some(*rect),
+                _ => None,
+            })
+            .unwrap();
+        let [numerator, denominator] = baselines(&layout)[..] else {
+            panic!("expected two glyphs");
+        };
+        // 分子在分数线上方，分母在下方，分数线在基线以上
+        assert!(numerator < rule.min.y);
+        assert!(denominator > rule.max.y);
+        assert!(rule.max.y < 0.0);
+        assert!(layout.ascent > 16.0 && layout.descent > 8.0);
+
+        // 行内分数更紧凑
+        let inline = typeset(r"\frac{a}{b}", false);
+        assert!(inline.ascent + inline.descent < layout.ascent + layout.descent);
+    }
+
+    #[test]
+    fn raises_superscripts_and_lowers_subscripts() {
+        let layout = typeset("x_i^2", false);
+        let [base, sup, sub] = baselines(&layout)[..] else {
+            panic!("expected three glyphs");
+        };
+        assert_eq!(base, 0.0);
+        assert!(sup < -4.0);
+        assert!(sub > 1.0);
+
+        // 显示模式中求和的上下限在正上方和正下方
+        let layout = typeset(r"\sum_{k=1}^n k", true);
+        let xs = glyph_xs(&layout);
+        let ys = baselines(&layout);
+        let n = ys.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(n < -layout.ascent / 2.0);
+        assert!(xs[1] < xs[0] + 16.0);
+    }
+
+    #[test]
+    fn aligns_matrix_columns() {
+        let layout = typeset(r"\begin{matrix} 1 & 2 \\ 333 & 4 \end{matrix}", false);
+        let xs = glyph_xs(&layout);
+        let ys = baselines(&layout);
+        // 1, 2, 3, 3, 3, 4：同一行基线相同，两行上下排列
+        assert_eq!(ys[0], ys[1]);
+        assert!(ys[2] > ys[0]);
+        // 居中的 "1" 在 "333" 中间，第二列的字符左侧对齐到同一列
+        assert!(xs[0] > xs[2] && xs[0] < xs[4]);
+        assert!((xs[1] - xs[5]).abs() < 0.01);
+
+        let layout = typeset(r"\begin{aligned} x &= 1 \\ yy &= 22 \end{aligned}", true);
+        let xs = glyph_xs(&layout);
+        // 两行的 "=" 对齐
+        assert!((xs[1] - xs[5]).abs() < 0.01);
+    }
+
+    #[test]
+    fn falls_back_for_missing_glyphs() {
+        let layout = typeset(r"\text{数}", false);
+        assert!(layout.width > 0.0);
+        assert_eq!(baselines(&layout).len(), 1);
+        // 缺少的符号用相近的字符代替
+        let glyph_id = |layout: &Layout| match layout.items.as_slice() {
+            [Item::Glyph { id, .. }] => *id,
+            items => panic!("expected one glyph, got {:?}", items),
+        };
+        assert_eq!(
+            glyph_id(&typeset(r"\bigoplus", false)),
+            glyph_id(&typeset(r"\oplus", false))
+        );
+        assert_eq!(
+            glyph_id(&typeset(r"\mathbb{R}", false)),
+            glyph_id(&typeset(r"\mathrm{R}", false))
+        );
+    }
+
+    #[test]
+    fn renders_centered_on_the_text_middle() {
+        let node = parse(r"\frac{1}{2} + \sqrt{x}").unwrap();
+        let image = render(&node, false, 16.0, Color32::WHITE).unwrap();
+        let [width, height] = image.size;
+        assert!(width > 30 && height > 16);
+        assert!(image.pixels.iter().any(|pixel| pixel.a() > 200));
+
+        let image = render(&parse("x").unwrap(), false, 16.0, Color32::WHITE).unwrap();
+        assert!(image.size[1] >= 10);
+    }
+}
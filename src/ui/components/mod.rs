@@ -1,7 +1,11 @@
 pub mod chat;
+pub mod markdown;
+pub mod model_picker;
 pub mod sidebar;
 pub mod settings;
+pub mod usage;
 
 pub use chat::Chat;
 pub use sidebar::Sidebar;
 pub use settings::Settings;
+pub use usage::UsagePanel;
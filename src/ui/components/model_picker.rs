@@ -0,0 +1,129 @@
+use crate::llm::provider::ModelInfo;
+use eframe::egui::{self, PopupCloseBehavior, Response, Ui};
+
+/// 列表中最多显示的模型数，其余的通过搜索找到
+const MAX_SHOWN: usize = 200;
+
+/// 模型选择：可以直接输入名称，也可以从接口列出的模型中搜索选择
+///
+/// 返回输入框的 `Response`，从列表中选择模型时也会标记为已修改。
+pub fn model_picker(
+    ui: &mut Ui,
+    id_salt: &str,
+    model: &mut String,
+    hint: &str,
+    models: &[ModelInfo],
+) -> Response {
+    let mut response = ui.add(
+        egui::TextEdit::singleline(model)
+            .hint_text(hint)
+            .desired_width(200.0),
+    );
+
+    let popup_id = ui.make_persistent_id(id_salt);
+    let search_id = popup_id.with("search");
+    let hover = if models.is_empty() {
+        "No models listed yet; type a model name"
+    } else {
+        "Choose from the endpoint's models"
+    };
+    let button = ui
+        .add_enabled(!models.is_empty(), egui::Button::new("⏷"))
+        .on_hover_text(hover);
+    if button.clicked() {
+        ui.memory_mut(|memory| memory.toggle_popup(popup_id));
+    }
+
+    // 点击列表中的搜索框时不能关闭弹出框，所以不用 ComboBox
+    egui::popup_below_widget(
+        ui,
+        popup_id,
+        &button,
+        PopupCloseBehavior::CloseOnClickOutside,
+        |ui| {
+            ui.set_min_width(320.0);
+            let mut search: String = ui
+                .data_mut(|data| data.get_temp(search_id))
+                .unwrap_or_default();
+            ui.add(
+                egui::TextEdit::singleline(&mut search)
+                    .hint_text("Search models")
+                    .desired_width(f32::INFINITY),
+            )
+            .request_focus();
+
+            let query = search.to_lowercase();
+            let matches: Vec<&ModelInfo> = models
+                .iter()
+                .filter(|info| info.id.to_lowercase().contains(&query))
+                .collect();
+            egui::ScrollArea::vertical()
+                .max_height(320.0)
+                .show(ui, |ui| {
+                    for info in matches.iter().take(MAX_SHOWN) {
+                        ui.horizontal(|ui| {
+                            if ui.selectable_label(*model == info.id, &info.id).clicked() {
+                                *model = info.id.clone();
+                                response.mark_changed();
+                                ui.memory_mut(|memory| memory.close_popup());
+                            }
+                            ui.label(egui::RichText::new(describe(info)).weak());
+                        });
+                    }
+                });
+            if matches.len() > MAX_SHOWN {
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{} more, refine the search",
+                        matches.len() - MAX_SHOWN
+                    ))
+                    .weak(),
+                );
+            } else if matches.is_empty() {
+                ui.label(egui::RichText::new("No matching models").weak());
+            }
+
+            ui.data_mut(|data| data.insert_temp(search_id, search));
+        },
+    );
+
+    // 已知的元数据显示在输入框旁边
+    if let Some(info) = models.iter().find(|info| info.id == *model) {
+        let text = describe(info);
+        if !text.is_empty() {
+            ui.label(egui::RichText::new(text).weak());
+        }
+    }
+    response
+}
+
+/// 编辑可选的模型，清空时保存为 `None`
+pub fn optional_model_picker(
+    ui: &mut Ui,
+    id_salt: &str,
+    value: &mut Option<String>,
+    hint: &str,
+    models: &[ModelInfo],
+) -> Response {
+    let mut model = value.clone().unwrap_or_default();
+    let response = model_picker(ui, id_salt, &mut model, hint, models);
+    if response.changed() {
+        *value = Some(model).filter(|model| !model.is_empty());
+    }
+    response
+}
+
+/// 模型的已知元数据，例如 "128k context · vision · tools"
+fn describe(info: &ModelInfo) -> String {
+    let mut parts = Vec::new();
+    if let Some(size) = info.context_window {
+        parts.push(format!("{}k context", size / 1000));
+    }
+    if info.vision == Some(true) {
+        parts.push("vision".to_string());
+    }
+    if info.tools == Some(true) {
+        parts.push("tools".to_string());
+    }
+    parts.join(" · ")
+}
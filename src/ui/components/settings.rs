@@ -1,27 +1,131 @@
+use crate::config::{ModelPrice, Preset, Profile};
+use crate::llm::context::ContextPolicy;
+use crate::llm::models::{CachedModels, ModelCache};
+use crate::llm::provider::ModelInfo;
+use crate::llm::{provider, tokens, ProviderKind};
+use crate::ui::components::model_picker::{model_picker, optional_model_picker};
 use crate::ui::state::{SettingsState, UIState};
-use eframe::egui::{self, Ui};
+use eframe::egui::{self, Response, Ui};
+use std::sync::Arc;
+use tokio::sync::oneshot;
 
-#[derive(Default)]
 pub struct Settings {
     temp_settings: SettingsState,
+    // 正在编辑的配置档案和默认配置档案在列表中的位置，
+    // 用下标而不是名称记录，这样重命名档案时不会丢失
+    selected: usize,
+    default_index: usize,
+    selected_preset: usize,
+    error: Option<String>,
+    runtime: Arc<tokio::runtime::Runtime>,
+    // 获取模型列表，同时用来检查连接：进行中的请求和最近一次的结果
+    connection_rx: Option<oneshot::Receiver<ModelsResult>>,
+    connection_status: Option<Result<usize, String>>,
+    // 已经自动获取过模型列表的配置档案，避免失败后反复请求
+    auto_listed: Option<usize>,
 }
 
+/// 获取模型列表的结果和请求时使用的配置档案
+type ModelsResult = (Profile, Result<Vec<ModelInfo>, String>);
+
 impl Settings {
-    pub fn ui(&mut self, ui: &mut Ui, state: &mut UIState) {
+    pub fn new(settings: SettingsState, runtime: Arc<tokio::runtime::Runtime>) -> Self {
+        let mut this = Self {
+            temp_settings: SettingsState::default(),
+            selected: 0,
+            default_index: 0,
+            selected_preset: 0,
+            error: None,
+            runtime,
+            connection_rx: None,
+            connection_status: None,
+            auto_listed: None,
+        };
+        this.reset(&settings);
+        this
+    }
+
+    /// 丢弃未保存的修改
+    pub fn reset(&mut self, settings: &SettingsState) {
+        let profiles = &settings.config.profiles;
+        let index_of = |name: &str| profiles.iter().position(|p| p.name == name);
+
+        self.temp_settings = settings.clone();
+        self.selected = index_of(&settings.active_profile).unwrap_or(0);
+        self.default_index = index_of(&settings.config.default_profile).unwrap_or(0);
+        self.selected_preset = 0;
+        self.error = None;
+        self.connection_rx = None;
+        self.connection_status = None;
+        self.auto_listed = None;
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, state: &mut UIState, models: &ModelCache) {
         ui.vertical(|ui| {
             ui.heading("Settings");
+            if let Some(error) = &state.config_error {
+                ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+            }
+
+            let selected = self.selected;
+            self.profile_selector(ui);
+            if self.selected != selected {
+                self.connection_rx = None;
+                self.connection_status = None;
+            }
+
+            let is_default = self.selected == self.default_index;
+            let profile = &mut self.temp_settings.config.profiles[self.selected];
+            let cached = models.get(profile);
+            let listed = cached.map_or(&[][..], |cached| &cached.models[..]);
+
+            ui.group(|ui| {
+                ui.label("Profile");
+
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut profile.name);
+                });
+
+                if is_default {
+                    ui.label("This profile is used at startup.");
+                } else if ui.button("Use at startup").clicked() {
+                    self.default_index = self.selected;
+                }
+            });
 
             ui.group(|ui| {
                 ui.label("API Configuration");
 
+                ui.horizontal(|ui| {
+                    ui.label("Provider:");
+                    egui::ComboBox::from_id_salt("provider")
+                        .selected_text(profile.provider.label())
+                        .show_ui(ui, |ui| {
+                            for kind in ProviderKind::ALL {
+                                ui.selectable_value(&mut profile.provider, kind, kind.label());
+                            }
+                        });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("API Key env var:");
+                    optional_text_edit(ui, &mut profile.api_key_env, "e.g. OPENAI_API_KEY", false);
+                });
+
                 ui.horizontal(|ui| {
                     ui.label("API Key:");
-                    ui.text_edit_singleline(&mut self.temp_settings.api_key);
+                    optional_text_edit(
+                        ui,
+                        &mut profile.api_key,
+                        "used when the env var is not set",
+                        true,
+                    );
                 });
 
                 ui.horizontal(|ui| {
                     ui.label("API Base URL:");
-                    ui.text_edit_singleline(&mut self.temp_settings.api_base);
+                    ui.text_edit_singleline(&mut profile.api_base);
                 });
             });
 
@@ -30,59 +134,439 @@ impl Settings {
 
                 ui.horizontal(|ui| {
                     ui.label("Model:");
-                    egui::ComboBox::from_label("")
-                        .selected_text(&self.temp_settings.model)
-                        .show_ui(ui, |ui| {
-                            ui.selectable_value(
-                                &mut self.temp_settings.model,
-                                "gpt-3.5-turbo".to_string(),
-                                "GPT-3.5 Turbo",
-                            );
-                            ui.selectable_value(
-                                &mut self.temp_settings.model,
-                                "gpt-4".to_string(),
-                                "GPT-4",
-                            );
-                            ui.selectable_value(
-                                &mut self.temp_settings.model,
-                                "mistral-large-latest".to_string(),
-                                "Mistral Large",
-                            );
-                        });
+                    model_picker(ui, "model", &mut profile.model, "model name", listed);
                 });
 
                 ui.horizontal(|ui| {
                     ui.label("Temperature:");
                     ui.add(
-                        egui::Slider::new(&mut self.temp_settings.temperature, 0.0..=2.0)
-                            .text("temperature"),
+                        egui::Slider::new(&mut profile.temperature, 0.0..=2.0).text("temperature"),
                     );
                 });
 
                 ui.horizontal(|ui| {
                     ui.label("Max Tokens:");
                     ui.add(
-                        egui::Slider::new(&mut self.temp_settings.max_tokens, 100..=4000)
-                            .text("max tokens"),
+                        egui::Slider::new(&mut profile.max_tokens, 100..=4000).text("max tokens"),
                     );
                 });
+
+                ui.horizontal(|ui| {
+                    ui.label("Context window:");
+                    let mut custom = profile.context_window.is_some();
+                    if ui.checkbox(&mut custom, "Override").changed() {
+                        profile.context_window = custom.then_some(8192);
+                    }
+                    match &mut profile.context_window {
+                        Some(size) => {
+                            ui.add(
+                                egui::DragValue::new(size)
+                                    .range(1024..=2_000_000)
+                                    .suffix(" tokens"),
+                            );
+                        }
+                        None => {
+                            // 接口报告了上下文长度时优先显示，可以一键用作覆盖值
+                            let reported = cached
+                                .and_then(|cached| cached.find(&profile.model))
+                                .and_then(|info| info.context_window);
+                            match reported {
+                                Some(size) => {
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "{} tokens (reported by endpoint)",
+                                            size
+                                        ))
+                                        .weak(),
+                                    );
+                                    if ui.button("Use").clicked() {
+                                        profile.context_window = Some(size);
+                                    }
+                                }
+                                None => {
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "{} tokens (from model name)",
+                                            tokens::context_window(&profile.model)
+                                        ))
+                                        .weak(),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("When too long:");
+                    egui::ComboBox::from_id_salt("context_policy")
+                        .selected_text(profile.context_policy.label())
+                        .show_ui(ui, |ui| {
+                            for policy in ContextPolicy::ALL {
+                                ui.selectable_value(
+                                    &mut profile.context_policy,
+                                    policy,
+                                    policy.label(),
+                                );
+                            }
+                        });
+                });
+            });
+
+            let profile = self.temp_settings.config.profiles[self.selected].clone();
+            self.connection_ui(ui, state, &profile, cached);
+
+            self.presets_ui(ui, listed);
+
+            ui.group(|ui| {
+                ui.label("Session titles");
+                ui.label(
+                    egui::RichText::new(
+                        "Prompt used to name a chat after the first reply. \
+                         Leave empty to keep \"New Chat\".",
+                    )
+                    .weak(),
+                );
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.temp_settings.config.title_prompt)
+                        .desired_rows(2)
+                        .desired_width(f32::INFINITY),
+                );
             });
 
+            self.pricing_ui(ui);
+
+            if let Some(error) = &self.error {
+                ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+            }
+
             ui.separator();
 
             ui.horizontal(|ui| {
                 if ui.button("Save").clicked() {
-                    // 保存设置
-                    state.settings = self.temp_settings.clone();
-                    state.show_settings = false;
+                    // 保存设置，并切换到正在编辑的配置档案
+                    match self.validate() {
+                        Ok(()) => {
+                            let profiles = &self.temp_settings.config.profiles;
+                            self.temp_settings.config.default_profile =
+                                profiles[self.default_index].name.clone();
+                            self.temp_settings.active_profile =
+                                profiles[self.selected].name.clone();
+
+                            state.settings = self.temp_settings.clone();
+                            state.settings_saved = true;
+                            state.show_settings = false;
+                        }
+                        Err(error) => self.error = Some(error),
+                    }
                 }
 
                 if ui.button("Cancel").clicked() {
                     // 取消修改
-                    self.temp_settings = state.settings.clone();
+                    self.reset(&state.settings);
                     state.show_settings = false;
                 }
             });
         });
     }
+
+    /// 从接口获取模型列表，同时检查当前编辑的配置能否连通
+    ///
+    /// 没有缓存或缓存过期时，每个配置档案在打开设置后自动获取一次。
+    fn connection_ui(
+        &mut self,
+        ui: &mut Ui,
+        state: &mut UIState,
+        profile: &Profile,
+        cached: Option<&CachedModels>,
+    ) {
+        if let Some(rx) = &mut self.connection_rx {
+            if let Ok((profile, result)) = rx.try_recv() {
+                self.connection_status = Some(result.as_ref().map(Vec::len).map_err(Clone::clone));
+                if let Ok(models) = result {
+                    state.models_fetched = Some((profile, models));
+                }
+                self.connection_rx = None;
+            }
+        }
+
+        let stale = cached.is_none_or(CachedModels::is_stale);
+        let auto = stale && self.auto_listed != Some(self.selected);
+
+        ui.horizontal(|ui| {
+            let checking = self.connection_rx.is_some();
+            if (auto && !checking)
+                || ui
+                    .add_enabled(!checking, egui::Button::new("Refresh models"))
+                    .on_hover_text("List the endpoint's models and check the connection")
+                    .clicked()
+            {
+                self.auto_listed = Some(self.selected);
+                let (tx, rx) = oneshot::channel();
+                let ctx = ui.ctx().clone();
+                let profile = profile.clone();
+                self.runtime.spawn(async move {
+                    let result = provider::list_models(&profile.to_llm_config())
+                        .await
+                        .map_err(|e| e.to_string());
+                    let _ = tx.send((profile, result));
+                    ctx.request_repaint();
+                });
+                self.connection_rx = Some(rx);
+                self.connection_status = None;
+            }
+
+            if checking {
+                ui.spinner();
+            }
+            match (&self.connection_status, cached) {
+                (Some(Ok(count)), _) => {
+                    ui.label(
+                        egui::RichText::new(format!("Connected, {} models available", count))
+                            .color(egui::Color32::GREEN),
+                    );
+                }
+                (Some(Err(error)), _) => {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                }
+                (None, Some(cached)) => {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "{} models, listed {}",
+                            cached.models.len(),
+                            cached
+                                .fetched_at
+                                .with_timezone(&chrono::Local)
+                                .format("%Y-%m-%d %H:%M")
+                        ))
+                        .weak(),
+                    );
+                }
+                (None, None) => {}
+            }
+        });
+    }
+
+    /// 新建会话时可选的预设
+    fn presets_ui(&mut self, ui: &mut Ui, models: &[ModelInfo]) {
+        ui.group(|ui| {
+            ui.label("Presets");
+            let presets = &mut self.temp_settings.config.presets;
+
+            ui.horizontal(|ui| {
+                ui.label("Preset:");
+                egui::ComboBox::from_id_salt("preset")
+                    .selected_text(
+                        presets
+                            .get(self.selected_preset)
+                            .map_or("None", |preset| preset.name.as_str()),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (index, preset) in presets.iter().enumerate() {
+                            ui.selectable_value(&mut self.selected_preset, index, &preset.name);
+                        }
+                    });
+
+                if ui.button("➕").on_hover_text("New preset").clicked() {
+                    presets.push(Preset::new(format!("preset {}", presets.len() + 1), ""));
+                    self.selected_preset = presets.len() - 1;
+                }
+
+                if ui
+                    .add_enabled(!presets.is_empty(), egui::Button::new("🗑"))
+                    .on_hover_text("Delete preset")
+                    .clicked()
+                {
+                    presets.remove(self.selected_preset);
+                    self.selected_preset =
+                        self.selected_preset.min(presets.len().saturating_sub(1));
+                }
+            });
+
+            let Some(preset) = presets.get_mut(self.selected_preset) else {
+                return;
+            };
+
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut preset.name);
+            });
+            ui.label("System prompt:");
+            ui.add(
+                egui::TextEdit::multiline(&mut preset.system_prompt)
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Model:");
+                optional_model_picker(
+                    ui,
+                    "preset_model",
+                    &mut preset.model,
+                    "profile default",
+                    models,
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Temperature:");
+                optional_temperature(ui, &mut preset.temperature);
+            });
+        });
+    }
+
+    /// 价格表和每月预算
+    fn pricing_ui(&mut self, ui: &mut Ui) {
+        let config = &mut self.temp_settings.config;
+        ui.group(|ui| {
+            ui.label("Usage and cost");
+            ui.horizontal(|ui| {
+                ui.label("Monthly budget:");
+                let mut enabled = config.monthly_budget.is_some();
+                if ui.checkbox(&mut enabled, "Warn above").changed() {
+                    config.monthly_budget = enabled.then_some(20.0);
+                }
+                if let Some(budget) = &mut config.monthly_budget {
+                    ui.add(
+                        egui::DragValue::new(budget)
+                            .prefix("$")
+                            .speed(1.0)
+                            .range(0.0..=f64::MAX),
+                    );
+                }
+            });
+
+            egui::CollapsingHeader::new("Prices (USD per million tokens)")
+                .id_salt("prices")
+                .show(ui, |ui| {
+                    let mut removed = None;
+                    egui::Grid::new("prices").num_columns(4).show(ui, |ui| {
+                        ui.strong("Model prefix");
+                        ui.strong("Input");
+                        ui.strong("Output");
+                        ui.end_row();
+
+                        for (index, price) in config.prices.iter_mut().enumerate() {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut price.model).desired_width(160.0),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut price.input)
+                                    .speed(0.01)
+                                    .range(0.0..=f64::MAX),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut price.output)
+                                    .speed(0.01)
+                                    .range(0.0..=f64::MAX),
+                            );
+                            if ui.button("🗑").on_hover_text("Remove price").clicked() {
+                                removed = Some(index);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(index) = removed {
+                        config.prices.remove(index);
+                    }
+                    if ui.button("➕ Add price").clicked() {
+                        config.prices.push(ModelPrice::new("", 0.0, 0.0));
+                    }
+                });
+        });
+    }
+
+    fn profile_selector(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let profiles = &mut self.temp_settings.config.profiles;
+
+            ui.label("Profile:");
+            egui::ComboBox::from_id_salt("profile")
+                .selected_text(&profiles[self.selected].name)
+                .show_ui(ui, |ui| {
+                    for (index, profile) in profiles.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected, index, &profile.name);
+                    }
+                });
+
+            if ui.button("➕").on_hover_text("New profile").clicked() {
+                profiles.push(Profile::new(format!("profile {}", profiles.len() + 1)));
+                self.selected = profiles.len() - 1;
+            }
+
+            if ui
+                .add_enabled(profiles.len() > 1, egui::Button::new("🗑"))
+                .on_hover_text("Delete profile")
+                .clicked()
+            {
+                profiles.remove(self.selected);
+                if self.default_index == self.selected {
+                    self.default_index = 0;
+                } else if self.default_index > self.selected {
+                    self.default_index -= 1;
+                }
+                self.selected = self.selected.min(profiles.len() - 1);
+            }
+        });
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let profiles = &self.temp_settings.config.profiles;
+        for (index, profile) in profiles.iter().enumerate() {
+            if profile.name.trim().is_empty() {
+                return Err("Profile names cannot be empty".to_string());
+            }
+            if profiles[..index].iter().any(|p| p.name == profile.name) {
+                return Err(format!("Duplicate profile name: {}", profile.name));
+            }
+        }
+
+        let presets = &self.temp_settings.config.presets;
+        for (index, preset) in presets.iter().enumerate() {
+            if preset.name.trim().is_empty() {
+                return Err("Preset names cannot be empty".to_string());
+            }
+            if presets[..index].iter().any(|p| p.name == preset.name) {
+                return Err(format!("Duplicate preset name: {}", preset.name));
+            }
+        }
+
+        if self
+            .temp_settings
+            .config
+            .prices
+            .iter()
+            .any(|price| price.model.trim().is_empty())
+        {
+            return Err("Prices need a model name".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 编辑可选字符串，清空时保存为 `None`
+pub fn optional_text_edit(ui: &mut Ui, value: &mut Option<String>, hint: &str, password: bool) {
+    let mut text = value.clone().unwrap_or_default();
+    if ui
+        .add(
+            egui::TextEdit::singleline(&mut text)
+                .hint_text(hint)
+                .password(password),
+        )
+        .changed()
+    {
+        *value = Some(text).filter(|text| !text.is_empty());
+    }
+}
+
+/// 编辑可选的温度，不勾选时使用配置档案中的值
+pub fn optional_temperature(ui: &mut Ui, value: &mut Option<f32>) -> Response {
+    let mut enabled = value.is_some();
+    let mut response = ui.checkbox(&mut enabled, "Override");
+    if response.changed() {
+        *value = enabled.then_some(0.7);
+    }
+    if let Some(temperature) = value {
+        response |= ui.add(egui::Slider::new(temperature, 0.0..=2.0));
+    }
+    response
 }
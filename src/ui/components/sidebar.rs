@@ -1,18 +1,14 @@
+use crate::chat::export::ExportFormat;
+use crate::chat::search::SearchHit;
 use crate::chat::session::ChatSession;
 use crate::ui::state::UIState;
-use chrono::{DateTime, Utc};
-use eframe::egui::{self, Ui};
-
-#[derive(Debug, Clone, Default)]
-pub struct ChatInfo {
-    pub id: String,
-    pub title: String,
-    pub created_at: DateTime<Utc>,
-}
+use eframe::egui::{self, text::LayoutJob, TextFormat, Ui};
+use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct Sidebar {
-    chats: Vec<ChatInfo>,
+    // 正在重命名的会话 id 和输入的标题
+    renaming: Option<(String, String)>,
 }
 
 impl Sidebar {
@@ -22,28 +18,208 @@ impl Sidebar {
 
     pub fn ui(&mut self, ui: &mut Ui, state: &mut UIState, sessions: &[&ChatSession]) {
         ui.vertical(|ui| {
-            if ui.button("New Chat").clicked() {
-                state.new_chat_requested = true;
-            }
+            ui.horizontal(|ui| {
+                if ui.button("New Chat").clicked() {
+                    state.new_chat_requested = true;
+                }
+
+                ui.menu_button("▾", |ui| {
+                    ui.label(egui::RichText::new("New chat from preset").weak());
+                    for preset in &state.settings.config.presets {
+                        if ui.button(&preset.name).clicked() {
+                            state.new_chat_preset = Some(preset.name.clone());
+                            ui.close_menu();
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("New chat from preset");
+            });
+
+            ui.add(
+                egui::TextEdit::singleline(&mut state.search_query)
+                    .hint_text("🔍 Search messages")
+                    .desired_width(f32::INFINITY),
+            );
 
             ui.separator();
 
-            for session in sessions {
-                let is_current = state.current_chat_id == Some(session.id.clone());
-                if ui.selectable_label(is_current, &session.title).clicked() {
-                    state.current_chat_id = Some(session.id.clone());
+            if !state.search_query.trim().is_empty() {
+                search_results(ui, state);
+                ui.separator();
+                if ui.button("Settings").clicked() {
+                    state.show_settings = true;
                 }
+                return;
+            }
 
-                if ui.small_button("🗑").clicked() {
-                    state.delete_chat_requested = Some(session.id.clone());
+            // 分叉出的会话显示在来源会话下方
+            let mut children: HashMap<&str, Vec<&ChatSession>> = HashMap::new();
+            let mut roots = Vec::new();
+            for session in sessions {
+                match session
+                    .forked_from
+                    .as_ref()
+                    .filter(|origin| sessions.iter().any(|s| s.id == origin.session_id))
+                {
+                    Some(origin) => children
+                        .entry(origin.session_id.as_str())
+                        .or_default()
+                        .push(session),
+                    None => roots.push(*session),
                 }
             }
 
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, true])
+                .max_height(ui.available_height() - 40.0)
+                .show(ui, |ui| {
+                    for session in roots {
+                        self.session_entry(ui, state, session, &children, 0);
+                    }
+                });
+
             ui.separator();
 
-            if ui.button("Settings").clicked() {
-                state.show_settings = true;
+            ui.horizontal(|ui| {
+                if ui.button("Settings").clicked() {
+                    state.show_settings = true;
+                }
+                if ui.button("Usage").clicked() {
+                    state.show_usage = true;
+                }
+                if ui
+                    .button("Import…")
+                    .on_hover_text("Import sessions from JSON or a ChatGPT export")
+                    .clicked()
+                {
+                    state.import_requested = true;
+                }
+            });
+        });
+    }
+
+    fn session_entry(
+        &mut self,
+        ui: &mut Ui,
+        state: &mut UIState,
+        session: &ChatSession,
+        children: &HashMap<&str, Vec<&ChatSession>>,
+        depth: usize,
+    ) {
+        ui.horizontal(|ui| {
+            ui.add_space(depth as f32 * 12.0);
+            if depth > 0 {
+                ui.label("↳");
+            }
+
+            if let Some((id, title)) = &mut self.renaming {
+                if *id == session.id {
+                    let response = ui.text_edit_singleline(title);
+                    if !response.has_focus() && !response.lost_focus() {
+                        response.request_focus();
+                    }
+                    if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        self.renaming = None;
+                    } else if response.lost_focus() {
+                        let title = title.trim().to_string();
+                        if !title.is_empty() {
+                            state.rename_requested = Some((session.id.clone(), title));
+                        }
+                        self.renaming = None;
+                    }
+                    return;
+                }
+            }
+
+            let is_current = state.current_chat_id == Some(session.id.clone());
+            let mut label = ui.selectable_label(is_current, &session.title);
+            if let Some(origin) = &session.forked_from {
+                label =
+                    label.on_hover_text(format!("Forked at message {}", origin.message_index + 1));
+            }
+            if label.clicked() {
+                state.current_chat_id = Some(session.id.clone());
+            }
+            label.context_menu(|ui| {
+                for format in ExportFormat::ALL {
+                    if ui
+                        .button(format!("Export as {}…", format.label()))
+                        .clicked()
+                    {
+                        state.export_requested = Some((session.id.clone(), format));
+                        ui.close_menu();
+                    }
+                }
+            });
+            if label.double_clicked() || ui.small_button("✏").on_hover_text("Rename").clicked() {
+                self.renaming = Some((session.id.clone(), session.title.clone()));
+            }
+
+            if ui.small_button("🗑").clicked() {
+                state.delete_chat_requested = Some(session.id.clone());
+            }
+        });
+
+        for child in children.get(session.id.as_str()).into_iter().flatten() {
+            self.session_entry(ui, state, child, children, depth + 1);
+        }
+    }
+}
+
+/// 搜索结果列表，点击后切换到对应会话并滚动到匹配的消息
+fn search_results(ui: &mut Ui, state: &mut UIState) {
+    let mut selected = None;
+
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, true])
+        .max_height(ui.available_height() - 40.0)
+        .show(ui, |ui| {
+            if state.search_results.is_empty() {
+                ui.label(egui::RichText::new("No matches").weak());
+            }
+
+            for hit in &state.search_results {
+                ui.label(egui::RichText::new(&hit.session_title).small().weak());
+                let label = egui::SelectableLabel::new(false, highlighted_snippet(ui, hit));
+                if ui.add(label).clicked() {
+                    selected = Some((hit.session_id.clone(), hit.message_index));
+                }
+                ui.add_space(4.0);
             }
         });
+
+    if let Some((id, index)) = selected {
+        state.current_chat_id = Some(id);
+        state.scroll_to_message = Some(index);
+    }
+}
+
+fn highlighted_snippet(ui: &Ui, hit: &SearchHit) -> LayoutJob {
+    let normal = TextFormat {
+        font_id: egui::TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let highlight = TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        color: ui.visuals().strong_text_color(),
+        ..normal.clone()
+    };
+
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = ui.available_width();
+    let mut cursor = 0;
+    for range in &hit.highlights {
+        // 重叠的匹配只高亮一次
+        let start = range.start.max(cursor);
+        if start >= range.end {
+            continue;
+        }
+        job.append(&hit.snippet[cursor..start], 0.0, normal.clone());
+        job.append(&hit.snippet[start..range.end], 0.0, highlight.clone());
+        cursor = range.end;
     }
+    job.append(&hit.snippet[cursor..], 0.0, normal);
+    job
 }
@@ -0,0 +1,109 @@
+use crate::chat::ledger::{Grouping, UsageLedger, UsageTotal};
+use crate::chat::session::ChatSession;
+use crate::config::AppConfig;
+use eframe::egui::{self, Ui};
+
+/// token 用量和费用统计
+#[derive(Default)]
+pub struct UsagePanel {
+    grouping: Grouping,
+}
+
+impl UsagePanel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        ledger: &UsageLedger,
+        config: &AppConfig,
+        sessions: &[&ChatSession],
+    ) {
+        let month = ledger.month_total(&config.prices);
+        ui.horizontal(|ui| {
+            ui.label("This month:");
+            ui.strong(cost_text(&month));
+            ui.label(format!(
+                "· {} requests · {} tokens",
+                month.requests,
+                month.prompt_tokens + month.completion_tokens
+            ));
+        });
+        if let Some(budget) = config.monthly_budget.filter(|budget| *budget > 0.0) {
+            let fraction = (month.cost / budget) as f32;
+            let bar = egui::ProgressBar::new(fraction.min(1.0)).text(format!(
+                "{:.0}% of ${:.2} budget",
+                fraction * 100.0,
+                budget
+            ));
+            ui.add(if fraction >= 1.0 {
+                bar.fill(egui::Color32::from_rgb(200, 120, 0))
+            } else {
+                bar
+            });
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Group by:");
+            for grouping in Grouping::ALL {
+                ui.selectable_value(&mut self.grouping, grouping, grouping.label());
+            }
+        });
+
+        let totals = ledger.totals(self.grouping, &config.prices);
+        if totals.is_empty() {
+            ui.label(egui::RichText::new("No usage recorded yet").weak());
+            return;
+        }
+
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                egui::Grid::new("usage")
+                    .striped(true)
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        ui.strong(self.grouping.label());
+                        ui.strong("Requests");
+                        ui.strong("Prompt");
+                        ui.strong("Completion");
+                        ui.strong("Cost");
+                        ui.end_row();
+
+                        for total in &totals {
+                            let name = match self.grouping {
+                                Grouping::Session => sessions
+                                    .iter()
+                                    .find(|session| session.id == total.key)
+                                    .map_or("Deleted chat", |session| session.title.as_str()),
+                                _ => total.key.as_str(),
+                            };
+                            ui.label(name);
+                            ui.label(total.requests.to_string());
+                            ui.label(total.prompt_tokens.to_string());
+                            ui.label(total.completion_tokens.to_string());
+                            let cost = ui.label(cost_text(total));
+                            if total.unpriced {
+                                cost.on_hover_text(
+                                    "Some models have no price in Settings and are not counted",
+                                );
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
+
+/// 没有价格的模型不计入费用，用星号标出
+fn cost_text(total: &UsageTotal) -> String {
+    format!(
+        "${:.4}{}",
+        total.cost,
+        if total.unpriced { "*" } else { "" }
+    )
+}
@@ -1,23 +1,21 @@
-use serde::{Deserialize, Serialize};
+use crate::chat::export::ExportFormat;
+use crate::chat::search::SearchHit;
+use crate::config::{AppConfig, Profile};
+use crate::llm::provider::ModelInfo;
+use crate::llm::{LLMError, Usage};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default)]
 pub struct SettingsState {
-    pub api_key: String,
-    pub api_base: String,
-    pub model: String,
-    pub temperature: f32,
-    pub max_tokens: u32,
+    pub config: AppConfig,
+    /// 客户端正在使用的配置档案
+    pub active_profile: String,
 }
 
-impl Default for SettingsState {
-    fn default() -> Self {
-        Self {
-            api_key: String::new(),
-            api_base: "https://api.openai.com/v1".to_string(),
-            model: "gpt-3.5-turbo".to_string(),
-            temperature: 0.7,
-            max_tokens: 1000,
-        }
+impl SettingsState {
+    pub fn active_profile(&self) -> &Profile {
+        self.config
+            .profile(&self.active_profile)
+            .unwrap_or_else(|| self.config.default_profile())
     }
 }
 
@@ -25,6 +23,10 @@ impl Default for SettingsState {
 pub struct ChatState {
     pub is_sending: bool,
     pub error: Option<String>,
+    /// 最近一次请求失败的原因
+    pub api_error: Option<LLMError>,
+    /// 正在等待重试时显示的状态
+    pub retry_status: Option<String>,
 }
 
 #[derive(Default)]
@@ -32,8 +34,36 @@ pub struct UIState {
     pub show_settings: bool,
     pub current_chat_id: Option<String>,
     pub chat_input: String,
+    pub chat_attachment: Option<String>,
+    pub image_url_input: String,
     pub settings: SettingsState,
     pub chat_state: ChatState,
+    pub settings_saved: bool,
     pub new_chat_requested: bool,
+    /// 新建会话时使用的预设
+    pub new_chat_preset: Option<String>,
     pub delete_chat_requested: Option<String>,
+    /// 从某个会话的某条消息处分叉：(会话 id, 消息位置)
+    pub fork_requested: Option<(String, usize)>,
+    /// 需要自动生成标题的会话
+    pub title_requested: Option<String>,
+    /// 回复完成后检查是否需要总结的会话
+    pub summary_requested: Option<String>,
+    /// 需要记入账本的用量：(会话 id, 用量)
+    pub usage_to_record: Option<(String, Usage)>,
+    /// 设置中新获取的模型列表：(请求时的配置档案, 模型)
+    pub models_fetched: Option<(Profile, Vec<ModelInfo>)>,
+    /// 本月费用超出预算时显示的提示
+    pub budget_warning: Option<String>,
+    /// 配置文件无法读取时的错误，此时设置不会写回文件
+    pub config_error: Option<String>,
+    pub show_usage: bool,
+    /// 手动重命名会话：(会话 id, 新标题)
+    pub rename_requested: Option<(String, String)>,
+    pub export_requested: Option<(String, ExportFormat)>,
+    pub import_requested: bool,
+    pub search_query: String,
+    pub search_results: Vec<SearchHit>,
+    /// 切换会话后需要滚动到的消息位置
+    pub scroll_to_message: Option<usize>,
 }
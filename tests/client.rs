@@ -0,0 +1,303 @@
+use llm_client::llm::{
+    ConfigOverrides, LLMClient, LLMError, Message, MessageContent, Role, StreamMessage,
+    ToolRegistry,
+};
+use llm_client::mock::{MockReply, MockServer, Script};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+async fn start() -> (MockServer, LLMClient) {
+    let server = MockServer::start(Script::default()).await.unwrap();
+    let client = LLMClient::new(server.config());
+    (server, client)
+}
+
+fn history(text: &str) -> VecDeque<Message> {
+    VecDeque::from([Message::new(
+        Role::User,
+        MessageContent::Text(text.to_string()),
+    )])
+}
+
+/// 读取流中的全部消息，直到回复完成或出错
+async fn collect(client: &LLMClient, text: &str) -> Vec<StreamMessage> {
+    let mut rx = client
+        .send_message_streaming(&history(text), ConfigOverrides::default())
+        .await
+        .unwrap();
+    let mut messages = Vec::new();
+    while let Some(message) = rx.recv().await {
+        let finished = matches!(message, StreamMessage::Done(_) | StreamMessage::Error(_));
+        messages.push(message);
+        if finished {
+            break;
+        }
+    }
+    messages
+}
+
+fn text(message: &Message) -> &str {
+    match &message.content {
+        MessageContent::Text(text) => text,
+        other => panic!("expected text, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn streams_text_and_usage() {
+    let (server, client) = start().await;
+    server.push(MockReply::text(["Hel", "lo"]).with_usage(3, 4));
+
+    let messages = collect(&client, "hi").await;
+    let chunks: Vec<&str> = messages
+        .iter()
+        .filter_map(|message| match message {
+            StreamMessage::Chunk(chunk) => Some(chunk.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(chunks, ["Hel", "lo"]);
+
+    let Some(StreamMessage::Done(done)) = messages.last() else {
+        panic!("expected a finished reply, got {:?}", messages.last());
+    };
+    assert_eq!(text(done), "Hello");
+    let usage = done.usage.as_ref().unwrap();
+    assert_eq!(usage.model, "mock-model");
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (3, 4));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["stream"], true);
+    assert_eq!(requests[0]["messages"][0]["content"], "hi");
+}
+
+#[tokio::test]
+async fn echoes_when_the_script_is_empty() {
+    let (_server, client) = start().await;
+
+    let messages = collect(&client, "ping").await;
+    let Some(StreamMessage::Done(done)) = messages.last() else {
+        panic!("expected a finished reply, got {:?}", messages.last());
+    };
+    assert_eq!(text(done), "echo: ping");
+}
+
+#[tokio::test]
+async fn runs_tool_calls_and_continues() {
+    let (server, client) = start().await;
+    let client = client.with_tools(ToolRegistry::with_builtin_tools());
+    server.push(
+        MockReply::tool_call(
+            "call_1",
+            "get_current_time",
+            serde_json::json!({ "utc_offset_hours": 0 }),
+        )
+        .with_usage(5, 1),
+    );
+    server.push(MockReply::text(["It is noon"]).with_usage(10, 3));
+
+    let messages = collect(&client, "what time is it?").await;
+    let intermediate: Vec<&Message> = messages
+        .iter()
+        .filter_map(|message| match message {
+            StreamMessage::Intermediate(message) => Some(message),
+            _ => None,
+        })
+        .collect();
+    assert!(matches!(
+        &intermediate[..],
+        [
+            Message {
+                content: MessageContent::Function { .. },
+                ..
+            },
+            Message {
+                content: MessageContent::ToolResult { .. },
+                ..
+            },
+        ]
+    ));
+
+    let Some(StreamMessage::Done(done)) = messages.last() else {
+        panic!("expected a finished reply, got {:?}", messages.last());
+    };
+    assert_eq!(text(done), "It is noon");
+    // 用量包括工具调用的那一轮
+    let usage = done.usage.as_ref().unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (15, 4));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0]["tools"][0]["function"]["name"],
+        "get_current_time"
+    );
+    let followup = requests[1]["messages"].as_array().unwrap();
+    assert_eq!(followup.last().unwrap()["role"], "tool");
+    assert_eq!(followup.last().unwrap()["tool_call_id"], "call_1");
+}
+
+#[tokio::test]
+async fn retries_transient_errors() {
+    let (server, client) = start().await;
+    server.push(MockReply::error(503, "overloaded").with_retry_after(0));
+    server.push(MockReply::text(["recovered"]));
+
+    let messages = collect(&client, "hi").await;
+    assert!(matches!(
+        messages.first(),
+        Some(StreamMessage::Retrying {
+            error: LLMError::Server { status: 503, .. },
+            attempt: 1,
+            ..
+        })
+    ));
+    let Some(StreamMessage::Done(done)) = messages.last() else {
+        panic!("expected a finished reply, got {:?}", messages.last());
+    };
+    assert_eq!(text(done), "recovered");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn retries_dropped_streams() {
+    let (server, client) = start().await;
+    server.push(MockReply::disconnect(["par"]));
+    server.push(MockReply::text(["full reply"]));
+
+    let messages = collect(&client, "hi").await;
+    assert!(messages.iter().any(|message| matches!(
+        message,
+        StreamMessage::Retrying {
+            error: LLMError::Network(_),
+            ..
+        }
+    )));
+    let Some(StreamMessage::Done(done)) = messages.last() else {
+        panic!("expected a finished reply, got {:?}", messages.last());
+    };
+    assert_eq!(text(done), "full reply");
+}
+
+#[tokio::test]
+async fn gives_up_after_repeated_rate_limits() {
+    let (server, client) = start().await;
+    for _ in 0..4 {
+        server.push(MockReply::error(429, "slow down").with_retry_after(0));
+    }
+
+    let messages = collect(&client, "hi").await;
+    let retries = messages
+        .iter()
+        .filter(|message| matches!(message, StreamMessage::Retrying { .. }))
+        .count();
+    assert_eq!(retries, 3);
+    assert!(matches!(
+        messages.last(),
+        Some(StreamMessage::Error(LLMError::RateLimit { .. }))
+    ));
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn does_not_retry_permanent_errors() {
+    let (server, client) = start().await;
+    server.push(MockReply::error(401, "bad key"));
+
+    let messages = collect(&client, "hi").await;
+    assert!(matches!(
+        &messages[..],
+        [StreamMessage::Error(LLMError::Auth(_))]
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn classifies_errors_inside_the_stream() {
+    let (server, client) = start().await;
+    server.push(MockReply::stream_error(
+        ["Partial"],
+        "context_length_exceeded",
+        "This model's maximum context length is 8192 tokens",
+    ));
+
+    let messages = collect(&client, "hi").await;
+    assert!(matches!(
+        messages.last(),
+        Some(StreamMessage::Error(LLMError::ContextLength(_)))
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn dropping_the_receiver_cancels_the_reply() {
+    let (server, client) = start().await;
+    let client = client.with_tools(ToolRegistry::with_builtin_tools());
+    server.push(
+        MockReply::tool_call("call_1", "get_current_time", serde_json::json!({}))
+            .with_delay(Duration::from_millis(200)),
+    );
+    server.push(MockReply::text(["never requested"]));
+
+    let rx = client
+        .send_message_streaming(&history("hi"), ConfigOverrides::default())
+        .await
+        .unwrap();
+    drop(rx);
+
+    // 工具调用结束后客户端发现接收端已关闭，不会再发出下一轮请求
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn dropping_the_receiver_cancels_a_pending_retry() {
+    let (server, client) = start().await;
+    server.push(MockReply::error(503, "overloaded").with_retry_after(1));
+
+    let mut rx = client
+        .send_message_streaming(&history("hi"), ConfigOverrides::default())
+        .await
+        .unwrap();
+    assert!(matches!(
+        rx.recv().await,
+        Some(StreamMessage::Retrying { .. })
+    ));
+    drop(rx);
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn completes_without_tools() {
+    let (server, client) = start().await;
+    let client = client.with_tools(ToolRegistry::with_builtin_tools());
+    server.push(MockReply::text(["A ", "short ", "title"]).with_usage(7, 3));
+
+    let reply = client
+        .complete(history("name this chat").into())
+        .await
+        .unwrap();
+    assert_eq!(reply.text, "A short title");
+    let usage = reply.usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (7, 3));
+    assert!(server.requests()[0].get("tools").is_none());
+}
+
+#[tokio::test]
+async fn lists_models() {
+    let server = MockServer::start(Script {
+        models: vec!["alpha".to_string(), "beta".to_string()],
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let models = llm_client::llm::provider::list_models(&server.config())
+        .await
+        .unwrap();
+    let ids: Vec<&str> = models.iter().map(|model| model.id.as_str()).collect();
+    assert_eq!(ids, ["alpha", "beta"]);
+}
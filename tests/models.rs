@@ -0,0 +1,50 @@
+use llm_client::config::Profile;
+use llm_client::llm::models::ModelCache;
+use llm_client::llm::provider::ModelInfo;
+use std::path::PathBuf;
+
+fn cache_path() -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("llm-client-test-{}", uuid::Uuid::new_v4()))
+        .join("models.json")
+}
+
+#[test]
+fn caches_models_per_profile_on_disk() {
+    let path = cache_path();
+    let profile = Profile::new("main");
+    let mut cache = ModelCache::open(&path).unwrap();
+    let mut large = ModelInfo::new("large");
+    large.context_window = Some(128_000);
+    large.vision = Some(true);
+    cache
+        .insert(&profile, vec![large.clone(), ModelInfo::new("fast")])
+        .unwrap();
+
+    let reopened = ModelCache::open(&path).unwrap();
+    let cached = reopened.get(&profile).unwrap();
+    let ids: Vec<&str> = cached
+        .models
+        .iter()
+        .map(|model| model.id.as_str())
+        .collect();
+    assert_eq!(ids, ["fast", "large"]);
+    assert_eq!(cached.find("large"), Some(&large));
+    assert!(!cached.is_stale());
+    assert!(reopened.models(&Profile::new("other")).is_empty());
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn ignores_the_cache_after_the_endpoint_changes() {
+    let mut profile = Profile::new("main");
+    let mut cache = ModelCache::default();
+    cache
+        .insert(&profile, vec![ModelInfo::new("gpt-4o")])
+        .unwrap();
+    assert_eq!(cache.models(&profile).len(), 1);
+
+    profile.api_base = "http://localhost:11434".to_string();
+    assert!(cache.get(&profile).is_none());
+}
@@ -0,0 +1,249 @@
+use llm_client::chat::ledger::UsageLedger;
+use llm_client::chat::SessionManager;
+use llm_client::config::{AppConfig, Profile};
+use llm_client::llm::{MessageContent, Role};
+use llm_client::mock::{MockReply, MockServer, Script};
+use llm_client::proxy::Proxy;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// 上游的模拟服务器、代理和代理的地址
+struct Setup {
+    upstream: MockServer,
+    proxy: Proxy,
+    base: String,
+}
+
+/// 两个配置档案都指向同一个模拟服务器，"fast" 使用另一个模型
+async fn start() -> Setup {
+    let upstream = MockServer::start(Script::default()).await.unwrap();
+    let profile = |name: &str, model: &str| Profile {
+        api_key: Some("mock".to_string()),
+        api_base: upstream.api_base(),
+        model: model.to_string(),
+        ..Profile::new(name)
+    };
+    let config = AppConfig {
+        default_profile: "main".to_string(),
+        profiles: vec![profile("main", "mock-model"), profile("fast", "mock-fast")],
+        ..Default::default()
+    };
+    let proxy = Proxy::new(config, None, SessionManager::new(), UsageLedger::default()).unwrap();
+
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let base = format!("http://{}/v1", listener.local_addr().unwrap());
+    let router = proxy.router();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    Setup {
+        upstream,
+        proxy,
+        base,
+    }
+}
+
+async fn post(base: &str, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/chat/completions", base))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// SSE 响应中的每个 `data:` 事件
+fn events(body: &str) -> Vec<String> {
+    body.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(str::to_string)
+        .collect()
+}
+
+#[tokio::test]
+async fn forwards_a_completion_and_records_it() {
+    let setup = start().await;
+    setup
+        .upstream
+        .push(MockReply::text(["Hello ", "there"]).with_usage(7, 2));
+
+    let response = post(
+        &setup.base,
+        json!({
+            "model": "main",
+            "messages": [
+                { "role": "system", "content": "Be brief" },
+                { "role": "user", "content": "Say hello" },
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "Hello there");
+    assert_eq!(body["usage"]["total_tokens"], 9);
+
+    let sessions = setup.proxy.sessions();
+    assert_eq!(sessions.len(), 1);
+    let session = &sessions[0];
+    assert_eq!(session.title, "Proxy: Say hello");
+    assert_eq!(session.settings.system_prompt, "Be brief");
+    let messages: Vec<_> = session.messages.iter().collect();
+    assert_eq!(messages.len(), 2);
+    assert!(matches!(messages[0].role, Role::User));
+    assert!(matches!(
+        &messages[1].content,
+        MessageContent::Text(text) if text == "Hello there"
+    ));
+
+    let upstream = setup.upstream.requests();
+    assert_eq!(upstream[0]["model"], "mock-model");
+    assert_eq!(upstream[0]["messages"][0]["role"], "system");
+}
+
+#[tokio::test]
+async fn streams_chunks_with_usage() {
+    let setup = start().await;
+    setup
+        .upstream
+        .push(MockReply::text(["One ", "two"]).with_usage(3, 2));
+
+    let response = post(
+        &setup.base,
+        json!({
+            "model": "main",
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "messages": [{ "role": "user", "content": "Count" }],
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let events = events(&response.text().await.unwrap());
+    assert_eq!(events.last().unwrap(), "[DONE]");
+
+    let chunks: Vec<Value> = events[..events.len() - 1]
+        .iter()
+        .map(|event| serde_json::from_str(event).unwrap())
+        .collect();
+    let text: String = chunks
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(text, "One two");
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk["choices"][0]["finish_reason"] == "stop"));
+    assert_eq!(chunks.last().unwrap()["usage"]["prompt_tokens"], 3);
+
+    // 会话在流结束后记录
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(setup.proxy.sessions().len(), 1);
+}
+
+#[tokio::test]
+async fn routes_by_profile_name_or_model() {
+    let setup = start().await;
+
+    for model in ["fast", "custom-model", ""] {
+        let response = post(
+            &setup.base,
+            json!({ "model": model, "messages": [{ "role": "user", "content": "hi" }] }),
+        )
+        .await;
+        assert_eq!(response.status(), 200);
+    }
+
+    let models: Vec<Value> = setup
+        .upstream
+        .requests()
+        .iter()
+        .map(|request| request["model"].clone())
+        .collect();
+    assert_eq!(models, ["mock-fast", "custom-model", "mock-model"]);
+}
+
+#[tokio::test]
+async fn maps_upstream_errors_to_statuses() {
+    let setup = start().await;
+    setup.upstream.push(MockReply::error(401, "bad key"));
+
+    let response = post(
+        &setup.base,
+        json!({ "stream": true, "messages": [{ "role": "user", "content": "hi" }] }),
+    )
+    .await;
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "authentication_error");
+    assert!(setup.proxy.sessions().is_empty());
+}
+
+#[tokio::test]
+async fn rejects_invalid_messages() {
+    let setup = start().await;
+
+    let response = post(
+        &setup.base,
+        json!({ "messages": [{ "role": "narrator", "content": "hi" }] }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+    assert!(setup.upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn lists_profiles_as_models() {
+    let setup = start().await;
+
+    let body: Value = reqwest::get(format!("{}/models", setup.base))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|model| model["id"].as_str())
+        .collect();
+    assert_eq!(ids, ["main", "fast"]);
+}
+
+#[tokio::test]
+async fn ends_the_stream_when_the_upstream_retries_mid_reply() {
+    let setup = start().await;
+    setup
+        .upstream
+        .push(MockReply::disconnect(["Partial "]).with_chunk_delay(Duration::from_millis(100)));
+    setup.upstream.push(MockReply::text(["Full reply"]));
+
+    let response = post(
+        &setup.base,
+        json!({ "stream": true, "messages": [{ "role": "user", "content": "hi" }] }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let events = events(&response.text().await.unwrap());
+    let text: String = events
+        .iter()
+        .filter_map(|event| serde_json::from_str::<Value>(event).ok())
+        .filter_map(|event| {
+            event["choices"][0]["delta"]["content"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .collect();
+    assert_eq!(text, "Partial ");
+    let last: Value = serde_json::from_str(events.last().unwrap()).unwrap();
+    assert_eq!(last["error"]["type"], "server_error");
+
+    // 客户端没有等到完整的回复，不记录会话
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(setup.proxy.sessions().is_empty());
+}